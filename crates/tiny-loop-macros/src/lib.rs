@@ -84,6 +84,25 @@ use quote::quote;
 /// }
 /// ```
 ///
+/// ## Parameter Examples
+///
+/// `#[example = value]` adds a value to the `examples` of the parameter schema,
+/// to steer the model toward the expected format. Repeat it for multiple examples:
+///
+/// ```ignore
+/// #[tool]
+/// async fn get_events(
+///     /// Start date
+///     #[example = "2024-01-01"]
+///     since: String,
+///     /// Max number of events
+///     #[example = 10]
+///     limit: u32,
+/// ) -> String {
+///     todo!()
+/// }
+/// ```
+///
 /// # Macro Expansion
 ///
 /// ## Transform a Function
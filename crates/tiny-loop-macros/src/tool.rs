@@ -250,7 +250,7 @@ fn extract_args_struct(
                     return None;
                 };
                 Some(syn::Field {
-                    attrs: pat_type.attrs.clone(),
+                    attrs: pat_type.attrs.iter().map(example_to_schemars).collect(),
                     vis: syn::Visibility::Public(syn::token::Pub::default()),
                     mutability: syn::FieldMutability::None,
                     ident: Some(ident.ident.clone()),
@@ -270,6 +270,25 @@ fn extract_args_struct(
     }
 }
 
+/// Convert `#[example = value]` to `#[schemars(example = value)]`.
+/// String literals are passed by reference as schemars requires
+fn example_to_schemars(attr: &syn::Attribute) -> syn::Attribute {
+    if !attr.path().is_ident("example") {
+        return attr.clone();
+    }
+    let Ok(nv) = attr.meta.require_name_value() else {
+        return attr.clone();
+    };
+    let value = &nv.value;
+    match value {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Str(_),
+            ..
+        }) => syn::parse_quote!(#[schemars(example = &#value)]),
+        _ => syn::parse_quote!(#[schemars(example = #value)]),
+    }
+}
+
 fn to_pascal_case(s: &str) -> String {
     s.split('_')
         .map(|word| {
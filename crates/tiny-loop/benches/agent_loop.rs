@@ -0,0 +1,28 @@
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+use tiny_loop::{Agent, llm::mock::NoopProvider, tool::ScriptedExecutor};
+
+const DEPTH: usize = 50;
+
+fn bench_agent_loop(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    let mut group = c.benchmark_group("agent loop");
+    // One step per tool call plus the final answer
+    group.throughput(Throughput::Elements(DEPTH as u64 + 1));
+    group.bench_function("deep tool-call loop", |b| {
+        b.iter(|| {
+            let llm = NoopProvider::new("Done").tool_calls("search", r#"{"query":"rust"}"#, DEPTH);
+            let mut agent = Agent::new(llm)
+                .executor(ScriptedExecutor::new())
+                .system("You are a helpful assistant");
+            black_box(runtime.block_on(agent.chat("Go")).unwrap());
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_agent_loop);
+criterion_main!(benches);
@@ -0,0 +1,30 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+use tiny_loop::tokenizer::{TiktokenTokenizer, Tokenizer};
+
+const MESSAGE: &str =
+    "The quick brown fox jumps over the lazy dog. Rust is a systems programming language.";
+const MESSAGES: usize = 20;
+
+fn bench_tokenizer(c: &mut Criterion) {
+    c.bench_function("load encoding per message", |b| {
+        b.iter(|| {
+            for _ in 0..MESSAGES {
+                let tokenizer = TiktokenTokenizer::cl100k_base().unwrap();
+                black_box(tokenizer.count(black_box(MESSAGE)));
+            }
+        })
+    });
+
+    let tokenizer = TiktokenTokenizer::cl100k_base().unwrap();
+    c.bench_function("reuse loaded encoding", |b| {
+        b.iter(|| {
+            for _ in 0..MESSAGES {
+                black_box(tokenizer.count(black_box(MESSAGE)));
+            }
+        })
+    });
+}
+
+criterion_group!(benches, bench_tokenizer);
+criterion_main!(benches);
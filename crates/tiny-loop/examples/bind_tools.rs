@@ -1,11 +1,7 @@
 mod common;
 
 use common::run_cli_loop;
-use std::{
-    collections::HashMap,
-    io::{Write, stdout},
-    sync::Arc,
-};
+use std::{collections::HashMap, io::stdout, sync::Arc};
 use tiny_loop::{Agent, llm::OpenAIProvider, tool::tool};
 use tokio::sync::Mutex;
 
@@ -71,10 +67,7 @@ async fn main() {
         .api_key(api_key)
         .base_url("https://openrouter.ai/api/v1")
         .model("google/gemini-3-flash-preview")
-        .stream_callback(|chunk| {
-            print!("{}", chunk);
-            stdout().flush().unwrap();
-        });
+        .stream_to_writer(stdout());
 
     let mut data = HashMap::new();
     data.insert("name".to_string(), "Alice".to_string());
@@ -0,0 +1,53 @@
+use std::io::{self, Write};
+use tiny_loop::{Agent, Conversation, llm::OpenAIProvider};
+
+#[tokio::main]
+async fn main() {
+    let api_key = std::env::var("LLM_API_KEY").expect("LLM_API_KEY not set");
+
+    let llm = OpenAIProvider::new()
+        .api_key(api_key)
+        .base_url("https://openrouter.ai/api/v1")
+        .model("google/gemini-3-flash-preview");
+
+    let agent = Agent::new(llm).system("You are a helpful assistant");
+    let mut conversation = Conversation::new(agent);
+
+    println!("Commands: /retry, /undo, /history, quit\n");
+
+    loop {
+        print!("> ");
+        io::stdout().flush().unwrap();
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input).unwrap();
+
+        let response = match input.trim() {
+            "quit" => break,
+            "/retry" => conversation.regenerate().await,
+            "/undo" => {
+                if !conversation.undo_last_turn() {
+                    println!("Nothing to undo\n");
+                }
+                continue;
+            }
+            "/history" => {
+                println!("{}\n", conversation.history_markdown());
+                continue;
+            }
+            input => conversation.send(input).await,
+        };
+
+        match response {
+            Ok(response) => {
+                println!("{}\n", response.content);
+                println!(
+                    "({} tool calls, {} tokens)\n",
+                    response.tool_calls.len(),
+                    response.usage.total_tokens
+                );
+            }
+            Err(e) => eprintln!("Error: {}\n", e),
+        }
+    }
+}
@@ -29,6 +29,7 @@ async fn main() -> tiny_loop::Result<()> {
         .into(),
         timestamp: std::time::SystemTime::now(),
         elapsed: std::time::Duration::ZERO,
+        metadata: None,
     });
 
     // Custom loop with iteration limit
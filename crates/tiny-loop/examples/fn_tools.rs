@@ -1,7 +1,7 @@
 mod common;
 
 use common::run_cli_loop;
-use std::io::{Write, stdout};
+use std::io::stdout;
 use tiny_loop::{Agent, llm::OpenAIProvider, tool::tool};
 
 /// Get the current weather for a location
@@ -32,10 +32,7 @@ async fn main() {
         .api_key(api_key)
         .base_url("https://openrouter.ai/api/v1")
         .model("google/gemini-3-flash-preview")
-        .stream_callback(|chunk| {
-            print!("{}", chunk);
-            stdout().flush().unwrap();
-        });
+        .stream_to_writer(stdout());
 
     let agent = Agent::new(llm)
         .system("You are a helpful assistant with access to tools")
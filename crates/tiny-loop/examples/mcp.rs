@@ -6,7 +6,7 @@ use rmcp::{
     model::CallToolRequestParams,
     transport::{ConfigureCommandExt, TokioChildProcess},
 };
-use std::io::{Write, stdout};
+use std::io::stdout;
 use tiny_loop::{
     Agent,
     llm::OpenAIProvider,
@@ -20,7 +20,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let service = ()
         .serve(TokioChildProcess::new(Command::new("npx").configure(
             |cmd| {
-                cmd.args(&["-y", "@modelcontextprotocol/server-filesystem", "."]);
+                cmd.args(["-y", "@modelcontextprotocol/server-filesystem", "."]);
             },
         ))?)
         .await?;
@@ -70,10 +70,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .api_key(api_key)
         .base_url("https://openrouter.ai/api/v1")
         .model("google/gemini-3-flash-preview")
-        .stream_callback(|chunk| {
-            print!("{}", chunk);
-            stdout().flush().unwrap();
-        });
+        .stream_to_writer(stdout());
 
     let agent = Agent::new(llm)
         .system("You are a helpful assistant")
@@ -1,10 +1,132 @@
+mod config;
+mod recall;
+
 use crate::{
     history::{History, InfiniteHistory},
-    llm::LLMProvider,
+    llm::{CallOptions, LLMProvider, RetryBudget},
+    tokenizer::Tokenizer,
     tool::{ClosureTool, ParallelExecutor, ToolArgs, ToolExecutor},
-    types::ToolDefinition,
+    types::{
+        AssistantMessage, FinishReason, FunctionCall, LLMResponse, Message, TimedMessage, ToolCall,
+        ToolDefinition, ToolMeta, ToolResult, Usage,
+    },
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
 };
 
+pub use config::*;
+
+/// What to do when the results of one batch of tool calls exceed the token budget.
+/// See [`Agent::result_budget`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultBudgetPolicy {
+    /// Truncate each result to an equal share of the budget
+    TruncateEach,
+    /// Replace all results with one summary written by the LLM
+    SummarizeAll,
+    /// Fail with [`Error::ToolResultsTooLarge`](crate::Error::ToolResultsTooLarge)
+    Error,
+}
+
+/// How to handle tool calls reusing the id of an earlier call in the same message.
+/// See [`Agent::duplicate_call_ids`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateCallIds {
+    /// Give each duplicate a new unique id, e.g. `call_1_2`, and execute it
+    #[default]
+    Rename,
+    /// Drop the duplicates without executing them
+    Drop,
+}
+
+/// What to do when the model requests more tool calls at once than allowed.
+/// See [`Agent::max_parallel_tool_calls`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParallelCallOverflow {
+    /// Execute the first calls and answer the others with a message that the limit was exceeded
+    Reject,
+    /// Execute all calls in consecutive batches no larger than the limit
+    Sequential,
+}
+
+/// Time spent in one step of the agent loop. See [`Agent::on_step_timing`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepTiming {
+    /// Waiting for the LLM
+    pub llm: Duration,
+    /// Executing tools and post-processing their results
+    pub tools: Duration,
+    /// The whole step
+    pub total: Duration,
+}
+
+impl StepTiming {
+    /// Time spent in the framework itself, i.e. neither in the LLM nor in tools
+    pub fn overhead(&self) -> Duration {
+        self.total.saturating_sub(self.llm + self.tools)
+    }
+}
+
+/// What to do after a failed step. See [`Agent::on_error`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorAction {
+    /// Retry the step
+    Retry,
+    /// Return the error
+    Fail,
+}
+
+/// Parser extracting tool calls written in the content
+type TextToolCallParser = Box<dyn Fn(&str) -> Vec<FunctionCall> + Send>;
+
+/// Hook observing every response of the LLM
+type ResponseHook = Box<dyn FnMut(&LLMResponse) + Send>;
+
+/// Hook observing the timing of every step
+type TimingHook = Box<dyn FnMut(StepTiming) + Send>;
+
+/// Hook observing every finalized assistant message
+type AssistantHook = Box<dyn FnMut(&AssistantMessage) + Send>;
+
+/// Hook post-processing tool results before they enter the history
+type ResultHook = Box<dyn FnMut(&mut Vec<ToolResult>) + Send>;
+
+/// Hook deciding how to recover from a failed step
+type ErrorHook = Box<dyn FnMut(&crate::Error, &mut dyn History) -> ErrorAction + Send>;
+
+/// Formatter of the timestamps sent to the LLM. See [`Agent::timestamp_format`]
+type TimestampFormatter = Box<dyn Fn(std::time::SystemTime) -> String + Send>;
+
+/// Factory building an agent with a fresh history. See [`Agent::from_factory`]
+type AgentFactory = Arc<dyn Fn() -> Agent + Send + Sync>;
+
+/// Shared handle to queue user messages into a running [`Agent`]. See [`Agent::interrupt_handle`]
+///
+/// Cloning the handle is cheap and all clones feed the same agent.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle(Arc<Mutex<VecDeque<String>>>);
+
+impl InterruptHandle {
+    /// Queue a user message, added to the history before the next LLM call
+    pub fn interrupt(&self, message: impl Into<String>) {
+        self.0.lock().unwrap().push_back(message.into());
+    }
+}
+
+/// Token budget for the results of one batch of tool calls
+struct ResultBudget {
+    max_tokens: usize,
+    tokenizer: Box<dyn Tokenizer>,
+    policy: ResultBudgetPolicy,
+}
+
 /// Agent loop that coordinates LLM calls and tool execution.
 /// Uses [`ParallelExecutor`] by default.
 pub struct Agent {
@@ -12,6 +134,64 @@ pub struct Agent {
     llm: Box<dyn LLMProvider>,
     executor: Box<dyn ToolExecutor>,
     tools: Vec<ToolDefinition>,
+    spill_threshold: Option<usize>,
+    repeat_call_limit: Option<usize>,
+    max_parallel_tool_calls: Option<(usize, ParallelCallOverflow)>,
+    max_steps: Option<usize>,
+    /// Steps taken in the current run
+    steps: usize,
+    /// Last non-empty assistant content of the current run
+    partial_response: Option<String>,
+    /// Content chunks streamed by the provider in the current run
+    streamed_content: String,
+    trim_responses: bool,
+    skip_completed: bool,
+    include_timestamps: bool,
+    timestamp_format: Option<TimestampFormatter>,
+    /// Separator to join the content of every assistant message of a run with
+    response_separator: Option<String>,
+    auto_trim_on_overflow: bool,
+    validate_tools: bool,
+    send_tools_first_only: bool,
+    duplicate_call_ids: DuplicateCallIds,
+    /// Whether an LLM call with the tool definitions has been made
+    tools_sent: bool,
+    error_on_finish: HashSet<FinishReason>,
+    /// Tools ending the run once executed
+    terminal_tools: HashSet<String>,
+    /// Custom finish reasons treated as [`FinishReason::ToolCalls`]
+    tool_call_finish_reasons: HashSet<String>,
+    result_budget: Option<ResultBudget>,
+    on_error: Option<ErrorHook>,
+    after_tool_exec: Option<ResultHook>,
+    on_llm_response: Option<ResponseHook>,
+    on_assistant_complete: Option<AssistantHook>,
+    on_step_timing: Option<TimingHook>,
+    text_tool_calls: Option<TextToolCallParser>,
+    /// Whether `recall` calls are answered from the history
+    recall_tool: bool,
+    /// Canonical tool name by alias
+    tool_aliases: HashMap<String, String>,
+    /// Aliases resolved by the executor, whose calls are not renamed
+    executor_aliases: HashSet<String>,
+    /// Description overrides by tool name, applied before sending the definitions
+    tool_catalog: HashMap<String, ToolMeta>,
+    /// Max provider retries per run
+    retry_budget: Option<u32>,
+    /// Options for LLM calls in the current run
+    call_options: CallOptions,
+    /// Total token usage reported by the provider
+    usage: Usage,
+    /// User messages queued while running
+    interrupts: InterruptHandle,
+    /// Context messages of the current turn and the history position to insert them at
+    turn_context: Option<(usize, Vec<Message>)>,
+    /// Shared with registered tools, so it applies regardless of registration order
+    schema_errors: Arc<AtomicBool>,
+    /// Factory this agent was built with, used by [`clone_config`](Self::clone_config)
+    factory: Option<AgentFactory>,
+    /// Execution count and last result per `(name, arguments)` in the current run
+    repeated_calls: HashMap<(String, String), (usize, String)>,
 }
 
 impl Agent {
@@ -22,7 +202,129 @@ impl Agent {
             history: Box::new(InfiniteHistory::new()),
             executor: Box::new(ParallelExecutor::new()),
             tools: Vec::new(),
+            spill_threshold: None,
+            repeat_call_limit: None,
+            max_parallel_tool_calls: None,
+            max_steps: None,
+            steps: 0,
+            partial_response: None,
+            streamed_content: String::new(),
+            trim_responses: false,
+            skip_completed: false,
+            include_timestamps: false,
+            timestamp_format: None,
+            response_separator: None,
+            auto_trim_on_overflow: false,
+            validate_tools: false,
+            send_tools_first_only: false,
+            duplicate_call_ids: DuplicateCallIds::default(),
+            tools_sent: false,
+            error_on_finish: HashSet::new(),
+            terminal_tools: HashSet::new(),
+            tool_call_finish_reasons: HashSet::new(),
+            result_budget: None,
+            on_error: None,
+            after_tool_exec: None,
+            on_llm_response: None,
+            on_assistant_complete: None,
+            on_step_timing: None,
+            text_tool_calls: None,
+            recall_tool: false,
+            tool_aliases: HashMap::new(),
+            executor_aliases: HashSet::new(),
+            tool_catalog: HashMap::new(),
+            retry_budget: None,
+            call_options: CallOptions::default(),
+            usage: Usage::default(),
+            interrupts: InterruptHandle::default(),
+            turn_context: None,
+            schema_errors: Arc::new(AtomicBool::new(false)),
+            repeated_calls: HashMap::new(),
+            factory: None,
+        }
+    }
+
+    /// Create a new agent loop from a factory, so [`clone_config`](Self::clone_config)
+    /// can build more agents with the same configuration
+    ///
+    /// Providers, tools and hooks are not `Clone`, so the factory builds them again for every agent.
+    /// Share state between the agents by capturing an `Arc` in the factory.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::from_factory(|| {
+    ///     Agent::new(OpenAIProvider::new()).system("You are a helpful assistant")
+    /// });
+    /// let workers: Vec<Agent> = (0..4).filter_map(|_| agent.clone_config()).collect();
+    /// ```
+    pub fn from_factory(factory: impl Fn() -> Agent + Send + Sync + 'static) -> Self {
+        let factory: AgentFactory = Arc::new(factory);
+        let mut agent = factory();
+        agent.factory = Some(factory);
+        agent
+    }
+
+    /// Build a new agent with the same configuration and a fresh history,
+    /// e.g. for a pool of workers handling independent conversations.
+    /// Return `None` if this agent was not created by [`from_factory`](Self::from_factory)
+    pub fn clone_config(&self) -> Option<Agent> {
+        let factory = self.factory.clone()?;
+        let mut agent = factory();
+        agent.factory = Some(factory);
+        Some(agent)
+    }
+
+    /// Fork the conversation: build a new agent like [`clone_config`](Self::clone_config)
+    /// with a copy of this agent's history, to explore a different continuation.
+    /// Return `None` if this agent was not created by [`from_factory`](Self::from_factory),
+    /// or if the factory's history has messages but doesn't support [`remove`](History::remove)
+    ///
+    /// The copy replaces the messages the factory added, e.g. the system prompt,
+    /// and keeps the history type the factory configured.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// # async fn run() -> tiny_loop::Result<()> {
+    /// let mut agent = Agent::from_factory(|| Agent::new(OpenAIProvider::new()));
+    /// agent.chat("Suggest a name for my cat").await?;
+    ///
+    /// let mut branch = agent.branch().unwrap();
+    /// branch.chat("Make it shorter").await?;
+    /// agent.chat("Make it longer").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn branch(&self) -> Option<Agent> {
+        let mut agent = self.clone_config()?;
+        while let Some(last) = agent.history.get_all().len().checked_sub(1) {
+            if agent.history.remove(last).is_none() {
+                tracing::warn!("Cannot branch: the history doesn't support removing messages");
+                return None;
+            }
         }
+        agent.history.add_batch(self.history.get_all().to_vec());
+        Some(agent)
+    }
+
+    /// Create a new agent loop resuming a saved conversation.
+    /// The messages are added to an [`InfiniteHistory`]
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider, types::TimedMessage};
+    ///
+    /// # fn load() -> Vec<TimedMessage> { vec![] }
+    /// let messages: Vec<TimedMessage> = load();
+    /// let agent = Agent::from_messages(OpenAIProvider::new(), messages);
+    /// ```
+    pub fn from_messages(llm: impl LLMProvider + 'static, messages: Vec<TimedMessage>) -> Self {
+        let mut agent = Self::new(llm);
+        agent.history.add_batch(messages);
+        agent
     }
 
     /// Set custom history manager (default: [`InfiniteHistory`])
@@ -49,22 +351,241 @@ impl Agent {
     ///     .system("You are a helpful assistant");
     /// ```
     pub fn system(mut self, content: impl Into<String>) -> Self {
-        self.history.add(crate::types::TimedMessage {
+        self.history.add(TimedMessage {
             message: crate::types::SystemMessage {
                 content: content.into(),
             }
             .into(),
             timestamp: std::time::SystemTime::now(),
             elapsed: std::time::Duration::ZERO,
+            metadata: None,
         });
         self
     }
 
+    /// Append a user/assistant exchange as a few-shot example
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .system("Classify the sentiment as positive or negative")
+    ///     .example("I love it", "positive")
+    ///     .example("Worst purchase ever", "negative");
+    /// ```
+    pub fn example(mut self, user: impl Into<String>, assistant: impl Into<String>) -> Self {
+        let now = std::time::SystemTime::now();
+        self.history.add_batch(vec![
+            TimedMessage {
+                message: crate::types::UserMessage {
+                    content: user.into(),
+                }
+                .into(),
+                timestamp: now,
+                elapsed: std::time::Duration::ZERO,
+                metadata: None,
+            },
+            TimedMessage {
+                message: crate::types::AssistantMessage {
+                    content: assistant.into(),
+                    tool_calls: None,
+                    refusal: None,
+                    annotations: None,
+                }
+                .into(),
+                timestamp: now,
+                elapsed: std::time::Duration::ZERO,
+                metadata: None,
+            },
+        ]);
+        self
+    }
+
+    /// Append a call to the tool `name` with `arguments` and its `result`, without running the tool,
+    /// e.g. to provide precomputed context as if the model had fetched it
+    ///
+    /// The assistant message and the tool message share a generated call id `primed_call_<index>`.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .system("You are a support assistant")
+    ///     .prime_tool_result("get_account", r#"{"id":"42"}"#, r#"{"plan":"pro"}"#);
+    /// ```
+    pub fn prime_tool_result(
+        mut self,
+        name: impl Into<String>,
+        arguments: impl Into<String>,
+        result: impl Into<String>,
+    ) -> Self {
+        let id = format!("primed_call_{}", self.history.get_all().len());
+        let now = std::time::SystemTime::now();
+        self.history.add_batch(vec![
+            TimedMessage {
+                message: crate::types::AssistantMessage {
+                    content: String::new(),
+                    tool_calls: Some(vec![ToolCall {
+                        id: id.clone(),
+                        call_type: "function".into(),
+                        function: FunctionCall {
+                            name: name.into(),
+                            arguments: arguments.into(),
+                        },
+                    }]),
+                    refusal: None,
+                    annotations: None,
+                }
+                .into(),
+                timestamp: now,
+                elapsed: std::time::Duration::ZERO,
+                metadata: None,
+            },
+            TimedMessage {
+                message: crate::types::ToolMessage {
+                    content: result.into(),
+                    tool_call_id: id,
+                }
+                .into(),
+                timestamp: now,
+                elapsed: std::time::Duration::ZERO,
+                metadata: None,
+            },
+        ]);
+        self
+    }
+
     /// Get reference to registered tool definitions
     pub fn tools(&self) -> &[ToolDefinition] {
         &self.tools
     }
 
+    /// Get a handle to steer the agent while it runs, e.g. from another task
+    ///
+    /// Messages queued with [`InterruptHandle::interrupt`] are added to the
+    /// history as user messages at the next step boundary, before the next LLM call.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// # async fn example() -> tiny_loop::Result<()> {
+    /// let mut agent = Agent::new(OpenAIProvider::new());
+    /// let handle = agent.interrupt_handle();
+    /// // e.g. from a UI handler
+    /// handle.interrupt("Stop, focus on the failing test instead");
+    /// agent.chat("Fix the build").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupts.clone()
+    }
+
+    /// Queue a user message, added to the history before the next LLM call.
+    /// Use [`interrupt_handle`](Self::interrupt_handle) to interrupt a running agent
+    pub fn interrupt(&self, message: impl Into<String>) {
+        self.interrupts.interrupt(message);
+    }
+
+    /// Number of calls per tool name requested by the model in the whole history
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// # async fn example() -> tiny_loop::Result<()> {
+    /// let mut agent = Agent::new(OpenAIProvider::new());
+    /// agent.chat("What's the weather in Paris?").await?;
+    /// for (name, count) in agent.tool_call_stats() {
+    ///     println!("{}: {}", name, count);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn tool_call_stats(&self) -> HashMap<String, usize> {
+        count_tool_calls(self.history.get_all())
+    }
+
+    /// Number of calls per tool name requested by the model since the last user message
+    pub fn last_turn_tool_call_stats(&self) -> HashMap<String, usize> {
+        let messages = self.history.get_all();
+        let start = messages
+            .iter()
+            .rposition(|tm| matches!(tm.message, crate::types::Message::User(_)))
+            .map_or(0, |i| i + 1);
+        count_tool_calls(&messages[start..])
+    }
+
+    /// Results of the tool calls of the last step, i.e. the tool messages at the end of the history.
+    /// Empty if the last step didn't call tools
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// # async fn example() -> tiny_loop::Result<()> {
+    /// let mut agent = Agent::new(OpenAIProvider::new());
+    /// while agent.step().await?.is_none() {
+    ///     for result in agent.last_tool_results() {
+    ///         println!("{}: {}", result.tool_message.tool_call_id, result.tool_message.content);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn last_tool_results(&self) -> Vec<ToolResult> {
+        let messages = self.history.get_all();
+        let start = messages
+            .iter()
+            .rposition(|tm| !matches!(tm.message, Message::Tool(_)))
+            .map_or(0, |i| i + 1);
+        messages[start..]
+            .iter()
+            .filter_map(|tm| match &tm.message {
+                Message::Tool(m) => Some(ToolResult {
+                    tool_message: m.clone(),
+                    timestamp: tm.timestamp,
+                    elapsed: tm.elapsed,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Total token usage of all LLM calls of this agent, as reported by the provider.
+    /// Calls without usage information are not counted
+    pub fn usage(&self) -> Usage {
+        self.usage
+    }
+
+    /// Estimate the tokens of the next LLM request (history plus tool definitions)
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider, tokenizer::ApproxTokenizer};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .system("You are a helpful assistant");
+    /// assert!(agent.estimated_tokens(&ApproxTokenizer) > 0);
+    /// ```
+    pub fn estimated_tokens(&self, tokenizer: &dyn Tokenizer) -> usize {
+        let messages: usize = self
+            .history
+            .get_all()
+            .iter()
+            .map(|tm| tokenizer.count_message(&tm.message))
+            .sum();
+        let tools: usize = self
+            .tools
+            .iter()
+            .map(|t| tokenizer.count(&serde_json::to_string(t).unwrap_or_default()))
+            .sum();
+        messages + tools
+    }
+
     /// Set a custom tool executor (default: [`ParallelExecutor`])
     ///
     /// # Example
@@ -76,6 +597,657 @@ impl Agent {
     /// ```
     pub fn executor(mut self, executor: impl ToolExecutor + 'static) -> Self {
         self.executor = Box::new(executor);
+        self.executor_aliases.clear();
+        for (alias, canonical) in &self.tool_aliases {
+            if self.executor.add_alias(alias.clone(), canonical.clone()) {
+                self.executor_aliases.insert(alias.clone());
+            }
+        }
+        self
+    }
+
+    /// Declare tool definitions for tools already registered in a custom executor
+    ///
+    /// Tools added directly to an executor passed to [`executor`](Self::executor)
+    /// carry no definitions, so the model would not know about them.
+    /// Use this to send their definitions to the model.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider, tool::{ParallelExecutor, ToolArgs, tool}};
+    ///
+    /// /// Get the current time
+    /// #[tool]
+    /// async fn now() -> String {
+    ///     "12:00".into()
+    /// }
+    ///
+    /// // e.g. an executor shared between agents
+    /// let executor = ParallelExecutor::new();
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .executor(executor)
+    ///     .import_tool_defs(vec![NowArgs::definition()]);
+    /// ```
+    pub fn import_tool_defs(mut self, defs: Vec<ToolDefinition>) -> Self {
+        self.tools.extend(defs);
+        self
+    }
+
+    /// Override tool and parameter descriptions sent to the model (default: none)
+    ///
+    /// Keys are tool names. The catalog applies to tools registered before or
+    /// after this call; only the definitions sent to the model change, tools
+    /// are still called by their names. Useful for localization or A/B testing
+    /// of tool prompts without recompiling.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider, types::ToolMeta};
+    /// use std::collections::HashMap;
+    ///
+    /// let catalog: HashMap<String, ToolMeta> = serde_json::from_str(r#"{
+    ///     "get_weather": {
+    ///         "description": "Obtenir la météo",
+    ///         "parameters": { "city": "Nom de la ville" }
+    ///     }
+    /// }"#).unwrap();
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .apply_tool_catalog(catalog);
+    /// ```
+    pub fn apply_tool_catalog(mut self, catalog: HashMap<String, ToolMeta>) -> Self {
+        self.tool_catalog = catalog;
+        self
+    }
+
+    /// Spill tool results larger than `threshold` bytes to a temp file (default: disabled)
+    ///
+    /// The result sent to the model is replaced by the file path plus a preview
+    /// of the first `threshold` bytes, so the model can read the rest in ranges.
+    /// This crate has no file reading tool: register one so the model can access the file.
+    /// Files are created with [`spill_to_file`](crate::tool::spill_to_file) and not deleted automatically.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .spill_large_results(16 * 1024);
+    /// ```
+    pub fn spill_large_results(mut self, threshold: usize) -> Self {
+        self.spill_threshold = Some(threshold);
+        self
+    }
+
+    /// Register the builtin `recall` tool, letting the model search its own conversation history
+    ///
+    /// The tool takes an optional case-insensitive `query` keyword and a `limit` (default: 5),
+    /// and returns the latest matching user, assistant and tool messages, each truncated to 200 chars.
+    /// Without a query, the most recent messages are returned.
+    /// Calls are answered by the agent from [`history`](Self::history) instead of the executor,
+    /// so the tool always sees the current history.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .recall_tool();
+    /// ```
+    pub fn recall_tool(mut self) -> Self {
+        if !self.recall_tool {
+            self.tools.push(recall::RecallArgs::definition());
+            self.recall_tool = true;
+        }
+        self
+    }
+
+    /// Execute calls to `alias` with the tool registered as `canonical`
+    ///
+    /// Useful when models call a tool by a near-miss name, e.g. `search_web` for `web_search`.
+    /// Only the canonical definition is sent to the LLM; the history keeps the called name.
+    /// Tools see the called name in [`Tool::call_with_meta`](crate::tool::Tool::call_with_meta)
+    /// if the executor supports [`add_alias`](ToolExecutor::add_alias).
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider, tool::tool};
+    ///
+    /// /// Search the web
+    /// #[tool]
+    /// async fn web_search(
+    ///     /// Search query
+    ///     query: String,
+    /// ) -> String {
+    ///     format!("Results for {}", query)
+    /// }
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .tool(web_search)
+    ///     .tool_alias("web_search", "search_web");
+    /// ```
+    pub fn tool_alias(mut self, canonical: impl Into<String>, alias: impl Into<String>) -> Self {
+        let (canonical, alias) = (canonical.into(), alias.into());
+        if self.executor.add_alias(alias.clone(), canonical.clone()) {
+            self.executor_aliases.insert(alias.clone());
+        } else {
+            self.executor_aliases.remove(&alias);
+        }
+        self.tool_aliases.insert(alias, canonical);
+        self
+    }
+
+    /// Execute tool calls the model wrote in the content instead of the tool calls field (default: off)
+    ///
+    /// When a response finishes with [`FinishReason::Stop`] without tool calls,
+    /// `parser` extracts calls from its content, e.g. [`parse_fenced_tool_calls`](crate::tool::parse_fenced_tool_calls).
+    /// If it returns any, they're executed like regular tool calls and the loop continues.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider, tool::parse_fenced_tool_calls};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .system("To call a tool, reply with a ```json block like {\"tool\": \"name\", \"args\": {...}}")
+    ///     .text_tool_calls(parse_fenced_tool_calls);
+    /// ```
+    pub fn text_tool_calls(
+        mut self,
+        parser: impl Fn(&str) -> Vec<FunctionCall> + Send + 'static,
+    ) -> Self {
+        self.text_tool_calls = Some(Box::new(parser));
+        self
+    }
+
+    /// Limit how many times an identical tool call may execute within one run (default: unlimited)
+    ///
+    /// Once a `(name, arguments)` pair has been executed `limit` times,
+    /// further identical calls are not executed; the model instead receives
+    /// a message with the previous result, breaking tool-call loops.
+    /// Identical calls in the same message count too, and are answered as duplicates.
+    /// With a limit of `0`, no tool call is ever executed.
+    /// Counts reset at the start of every [`run`](Self::run).
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .repeat_call_limit(2);
+    /// ```
+    pub fn repeat_call_limit(mut self, limit: usize) -> Self {
+        self.repeat_call_limit = Some(limit);
+        self
+    }
+
+    /// Limit the number of steps, i.e. LLM calls, within one run (default: unlimited)
+    ///
+    /// Once reached, [`run`](Self::run) fails with [`Error::MaxSteps`](crate::Error::MaxSteps)
+    /// instead of calling the LLM again, carrying the last non-empty assistant content of the run
+    /// so callers can show where the agent got to. Steps retried by [`on_error`](Self::on_error) count once.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .max_steps(10);
+    /// ```
+    pub fn max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    /// Trim leading and trailing whitespace from the responses returned by [`run`](Self::run),
+    /// [`run_joined`](Self::run_joined) and [`chat`](Self::chat) (default: `false`)
+    ///
+    /// The assistant messages in the history are kept as received,
+    /// and stream callbacks still receive the raw chunks.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .trim_responses(true);
+    /// ```
+    pub fn trim_responses(mut self, enabled: bool) -> Self {
+        self.trim_responses = enabled;
+        self
+    }
+
+    /// Prefix the content of the messages sent to the LLM with their timestamps,
+    /// e.g. `[2026-01-02T03:04:05Z] Hello`, so the model can reason about when things were said
+    /// (default: `false`)
+    ///
+    /// Applies to user, tool and non-empty assistant messages; the history is not modified.
+    /// Timestamps are UTC in RFC 3339 unless [`timestamp_format`](Self::timestamp_format) is set.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .include_timestamps(true);
+    /// ```
+    pub fn include_timestamps(mut self, enabled: bool) -> Self {
+        self.include_timestamps = enabled;
+        self
+    }
+
+    /// Format the timestamps added by [`include_timestamps`](Self::include_timestamps)
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    /// use std::time::UNIX_EPOCH;
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .include_timestamps(true)
+    ///     .timestamp_format(|t| {
+    ///         let secs = t.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    ///         format!("unix {}", secs)
+    ///     });
+    /// ```
+    pub fn timestamp_format(
+        mut self,
+        format: impl Fn(std::time::SystemTime) -> String + Send + 'static,
+    ) -> Self {
+        self.timestamp_format = Some(Box::new(format));
+        self
+    }
+
+    /// Don't call the LLM when the history already ends with a complete answer (default: `false`)
+    ///
+    /// If the last message is an assistant message without tool calls, e.g. when resuming
+    /// from a history persisted after the run finished, [`step`](Self::step) and [`run`](Self::run)
+    /// return its content instead of asking the LLM again, so running a completed conversation
+    /// doesn't add another answer.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .skip_completed(true);
+    /// ```
+    pub fn skip_completed(mut self, enabled: bool) -> Self {
+        self.skip_completed = enabled;
+        self
+    }
+
+    /// Return the content of every assistant message of a run from [`run`](Self::run)
+    /// and [`chat`](Self::chat), joined by `separator`, instead of only the final response
+    ///
+    /// This includes content the model emitted alongside tool calls in intermediate rounds,
+    /// like [`run_joined`](Self::run_joined) does. Empty segments are skipped.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .join_responses("\n");
+    /// ```
+    pub fn join_responses(mut self, separator: impl Into<String>) -> Self {
+        self.response_separator = Some(separator.into());
+        self
+    }
+
+    /// Set a correlation id to tie the logs of the agent to the LLM requests and external tracing
+    ///
+    /// Runs are wrapped in an `agent_run` tracing span with a `request_id` field,
+    /// and the id is passed to the provider as [`CallOptions::request_id`],
+    /// which [`OpenAIProvider`](crate::llm::OpenAIProvider) sends as the `X-Request-Id` header.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .with_request_id("req-42");
+    /// ```
+    pub fn with_request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.call_options.request_id = Some(request_id.into());
+        self
+    }
+
+    /// Recover from context length errors by dropping old messages (default: `false`)
+    ///
+    /// When an LLM call fails with the `context_length_exceeded` error code,
+    /// the oldest half of the turns before the last user message is removed,
+    /// keeping system messages, and the step is retried once before the
+    /// [`on_error`](Self::on_error) hook gets the error.
+    /// Requires a [`History`] that supports removal.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .auto_trim_on_overflow(true);
+    /// ```
+    pub fn auto_trim_on_overflow(mut self, enabled: bool) -> Self {
+        self.auto_trim_on_overflow = enabled;
+        self
+    }
+
+    /// Validate tool definitions with [`ToolDefinition::validate`] before calling the LLM (default: `false`)
+    ///
+    /// When enabled, [`step`](Self::step) fails with [`Error::InvalidToolDefinition`](crate::Error::InvalidToolDefinition)
+    /// instead of sending a malformed schema that the API would reject with an opaque error.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .validate_tools(true);
+    /// ```
+    pub fn validate_tools(mut self, enabled: bool) -> Self {
+        self.validate_tools = enabled;
+        self
+    }
+
+    /// Send the tool definitions only on the first LLM call of this agent (default: `false`)
+    ///
+    /// For providers that cache tool definitions server-side per conversation,
+    /// this saves the tokens of resending them every turn. Only enable it if the
+    /// provider really keeps them: otherwise the model can't call tools after
+    /// the first call, and tools registered later are never sent.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .send_tools_first_only(true);
+    /// ```
+    pub fn send_tools_first_only(mut self, enabled: bool) -> Self {
+        self.send_tools_first_only = enabled;
+        self
+    }
+
+    /// Set how to handle tool calls reusing the id of an earlier call in the same response
+    /// (default: [`DuplicateCallIds::Rename`])
+    ///
+    /// Some models emit several tool calls with the same id, which breaks the
+    /// pairing of calls and results on the next request. The calls are fixed
+    /// before being added to the history, so every tool result references a unique id.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, DuplicateCallIds, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .duplicate_call_ids(DuplicateCallIds::Drop);
+    /// ```
+    pub fn duplicate_call_ids(mut self, policy: DuplicateCallIds) -> Self {
+        self.duplicate_call_ids = policy;
+        self
+    }
+
+    /// Fail with [`Error::FinishReason`](crate::Error::FinishReason) when the LLM finishes with one of these reasons (default: none)
+    ///
+    /// By default the loop returns the content for any finish reason other than
+    /// [`FinishReason::ToolCalls`], even if it was truncated or filtered.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider, types::FinishReason};
+    /// use std::collections::HashSet;
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .error_on_finish(HashSet::from([FinishReason::ContentFilter, FinishReason::Length]));
+    /// ```
+    pub fn error_on_finish(mut self, reasons: HashSet<FinishReason>) -> Self {
+        self.error_on_finish = reasons;
+        self
+    }
+
+    /// End the run after executing any of these tools, e.g. a `finish` or `handoff` tool (default: none)
+    ///
+    /// The other calls of the same assistant message are still executed.
+    /// [`step`](Self::step) then returns the result of the terminal tool,
+    /// which [`run`](Self::run) returns instead of calling the LLM again.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider, tool::tool};
+    ///
+    /// /// Submit the final answer
+    /// #[tool]
+    /// async fn finish(
+    ///     /// Final answer
+    ///     answer: String,
+    /// ) -> String {
+    ///     answer
+    /// }
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .tool(finish)
+    ///     .terminal_tools(["finish"]);
+    /// ```
+    pub fn terminal_tools(mut self, names: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.terminal_tools = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Treat these [`FinishReason::Custom`] values as [`FinishReason::ToolCalls`] (default: none)
+    ///
+    /// The loop only continues after [`FinishReason::ToolCalls`], so backends reporting
+    /// tool calls with another reason, e.g. `tool_use` from Anthropic-compatible shims,
+    /// would stop after the first round of tools.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .tool_call_finish_reasons(["tool_use"]);
+    /// ```
+    pub fn tool_call_finish_reasons(
+        mut self,
+        reasons: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.tool_call_finish_reasons = reasons.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Limit the total provider retries across all steps of one run (default: unlimited)
+    ///
+    /// Each LLM call still retries according to the provider's own settings,
+    /// but every retry also takes one from this budget, and calls stop retrying
+    /// once it's exhausted. The budget is reset at the start of every run.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new().max_retries(3))
+    ///     .retry_budget(5);
+    /// ```
+    pub fn retry_budget(mut self, max_retries: u32) -> Self {
+        self.retry_budget = Some(max_retries);
+        self.call_options.retry_budget = Some(RetryBudget::new(max_retries));
+        self
+    }
+
+    /// Limit the number of tool calls executed at once, regardless of the executor (default: unlimited)
+    ///
+    /// When the model requests more calls in one message, they are handled by `policy`.
+    /// Calls answered without the executor, e.g. repeated calls, don't count.
+    /// A limit of `0` is treated as `1`.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, ParallelCallOverflow, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .max_parallel_tool_calls(4, ParallelCallOverflow::Reject);
+    /// ```
+    pub fn max_parallel_tool_calls(mut self, limit: usize, policy: ParallelCallOverflow) -> Self {
+        self.max_parallel_tool_calls = Some((limit.max(1), policy));
+        self
+    }
+
+    /// Limit the estimated tokens of the results of one batch of tool calls (default: unlimited)
+    ///
+    /// If the model requests many tool calls at once, their combined results
+    /// could overflow the context window of the next LLM call. When the results
+    /// exceed `max_tokens` according to `tokenizer`, they are handled by `policy`
+    /// before being added to the history.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, ResultBudgetPolicy, llm::OpenAIProvider, tokenizer::ApproxTokenizer};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .result_budget(32_000, ApproxTokenizer, ResultBudgetPolicy::TruncateEach);
+    /// ```
+    pub fn result_budget(
+        mut self,
+        max_tokens: usize,
+        tokenizer: impl Tokenizer + 'static,
+        policy: ResultBudgetPolicy,
+    ) -> Self {
+        self.result_budget = Some(ResultBudget {
+            max_tokens,
+            tokenizer: Box::new(tokenizer),
+            policy,
+        });
+        self
+    }
+
+    /// Set a hook to transform tool results before they are added to the history (default: none)
+    ///
+    /// Called after every batch of tool executions, e.g. to redact secrets,
+    /// reformat outputs or attach metadata in one place for all tools.
+    /// Results added for interrupted runs by [`shutdown`](Self::shutdown) are not passed to it.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new()).after_tool_exec(|results| {
+    ///     for r in results {
+    ///         r.tool_message.content = r.tool_message.content.replace("sk-secret", "[REDACTED]");
+    ///     }
+    /// });
+    /// ```
+    pub fn after_tool_exec(
+        mut self,
+        hook: impl FnMut(&mut Vec<ToolResult>) + Send + 'static,
+    ) -> Self {
+        self.after_tool_exec = Some(Box::new(hook));
+        self
+    }
+
+    /// Set a hook called with every response of the LLM in the loop (default: none)
+    ///
+    /// Receives the response as returned by the provider, including the finish
+    /// reason and usage, before it's added to the history. Useful for logging and telemetry.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new()).on_llm_response(|response| {
+    ///     println!("{:?} {:?}", response.finish_reason, response.usage);
+    /// });
+    /// ```
+    pub fn on_llm_response(mut self, hook: impl FnMut(&LLMResponse) + Send + 'static) -> Self {
+        self.on_llm_response = Some(Box::new(hook));
+        self
+    }
+
+    /// Set a hook called when an assistant message is complete (default: none)
+    ///
+    /// Fires after the message finished streaming and was added to the history,
+    /// and before its tool calls are executed, e.g. to lock the input box or
+    /// show a spinner while tools run.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new()).on_assistant_complete(|message| {
+    ///     if message.tool_calls.is_some() {
+    ///         println!("\nRunning tools...");
+    ///     }
+    /// });
+    /// ```
+    pub fn on_assistant_complete(
+        mut self,
+        hook: impl FnMut(&AssistantMessage) + Send + 'static,
+    ) -> Self {
+        self.on_assistant_complete = Some(Box::new(hook));
+        self
+    }
+
+    /// Set a hook called with the time spent in every successful step (default: none)
+    ///
+    /// Useful for profiling, e.g. with [`NoopProvider`](crate::llm::mock::NoopProvider)
+    /// to measure the overhead of the loop itself.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new()).on_step_timing(|timing| {
+    ///     println!("LLM: {:?}, tools: {:?}, overhead: {:?}", timing.llm, timing.tools, timing.overhead());
+    /// });
+    /// ```
+    pub fn on_step_timing(mut self, hook: impl FnMut(StepTiming) + Send + 'static) -> Self {
+        self.on_step_timing = Some(Box::new(hook));
+        self
+    }
+
+    /// Set a hook deciding whether to retry a failed step (default: none, errors are returned)
+    ///
+    /// Called by [`run`](Self::run) and [`run_joined`](Self::run_joined) when a step fails,
+    /// e.g. after the provider exhausted its own retries. The hook can modify the history
+    /// before returning [`ErrorAction::Retry`], such as dropping old messages on a
+    /// context length error.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, Error, ErrorAction, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new()).on_error(|err, history| match err {
+    ///     Error::ApiError { body, .. } if body.contains("context_length_exceeded") => {
+    ///         // Drop the oldest message after the system prompt
+    ///         match history.remove(1) {
+    ///             Some(_) => ErrorAction::Retry,
+    ///             None => ErrorAction::Fail,
+    ///         }
+    ///     }
+    ///     _ => ErrorAction::Fail,
+    /// });
+    /// ```
+    pub fn on_error(
+        mut self,
+        hook: impl FnMut(&crate::Error, &mut dyn History) -> ErrorAction + Send + 'static,
+    ) -> Self {
+        self.on_error = Some(Box::new(hook));
+        self
+    }
+
+    /// Include the parameter schema in the result when the model sends invalid tool arguments (default: `false`)
+    ///
+    /// By default the model only receives the deserialization error, e.g.
+    /// ``missing field `city` at line 1 column 2``. When enabled, the result also
+    /// explains the expected arguments with the tool's JSON schema so the model can self-correct.
+    /// Applies to tools registered with [`tool`](Self::tool) and [`bind`](Self::bind).
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .schema_errors(true);
+    /// ```
+    pub fn schema_errors(self, enabled: bool) -> Self {
+        self.schema_errors.store(enabled, Ordering::Relaxed);
         self
     }
 
@@ -105,13 +1277,15 @@ impl Agent {
         Args: ToolArgs + 'static,
     {
         self.tools.push(Args::definition());
+        let schema_errors = self.schema_errors.clone();
         self.executor.add(
             Args::TOOL_NAME.into(),
             Box::new(ClosureTool::boxed(move |s: String| {
+                let schema_errors = schema_errors.clone();
                 Box::pin(async move {
-                    let args = match serde_json::from_str::<Args>(&s) {
+                    let args = match parse_args::<Args>(&s, &schema_errors) {
                         Ok(args) => args,
-                        Err(e) => return e.to_string(),
+                        Err(e) => return e,
                     };
                     tool(args).await
                 })
@@ -158,14 +1332,16 @@ impl Agent {
         Args: ToolArgs + 'static,
     {
         self.tools.push(Args::definition());
+        let schema_errors = self.schema_errors.clone();
         self.executor.add(
             Args::TOOL_NAME.into(),
             Box::new(ClosureTool::boxed(move |s: String| {
                 let ins = ins.clone();
+                let schema_errors = schema_errors.clone();
                 Box::pin(async move {
-                    let args = match serde_json::from_str::<Args>(&s) {
+                    let args = match parse_args::<Args>(&s, &schema_errors) {
                         Ok(args) => args,
-                        Err(e) => return e.to_string(),
+                        Err(e) => return e,
                     };
                     tool(ins, args).await
                 })
@@ -174,6 +1350,69 @@ impl Agent {
         self
     }
 
+    /// Register a tool taking free-form text instead of JSON arguments
+    ///
+    /// The tool is declared with a single `input: string` parameter, and the
+    /// function receives that string as-is. If the model sends arguments that
+    /// are not an object with a string `input`, the raw arguments are passed instead.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .raw_tool("calc", "Evaluate an arithmetic expression like `1 + 2 * 3`", |expr| async move {
+    ///         format!("Evaluated: {}", expr)
+    ///     });
+    /// ```
+    pub fn raw_tool<Fut>(
+        mut self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+        tool: impl Fn(String) -> Fut + Send + Sync + 'static,
+    ) -> Self
+    where
+        Fut: Future<Output = String> + Send + 'static,
+    {
+        let name = name.into();
+        self.tools.push(ToolDefinition {
+            tool_type: "function".into(),
+            function: crate::types::ToolFunction {
+                name: name.clone(),
+                description: description.into(),
+                parameters: crate::types::Parameters::from_object(
+                    serde_json::json!({
+                        "type": "object",
+                        "properties": {
+                            "input": {
+                                "type": "string",
+                                "description": "Free-form input"
+                            }
+                        },
+                        "required": ["input"]
+                    })
+                    .as_object()
+                    .unwrap()
+                    .clone(),
+                ),
+            },
+        });
+        self.executor.add(
+            name,
+            Box::new(ClosureTool::boxed(move |s: String| {
+                let input = match serde_json::from_str::<serde_json::Value>(&s) {
+                    Ok(serde_json::Value::Object(mut args)) => match args.remove("input") {
+                        Some(serde_json::Value::String(input)) => input,
+                        _ => s,
+                    },
+                    _ => s,
+                };
+                Box::pin(tool(input))
+            })),
+        );
+        self
+    }
+
     /// Register external tools (e.g. from MCP servers)
     ///
     /// To register a standalone tool function, use [`tool`](Self::tool).
@@ -238,6 +1477,114 @@ impl Agent {
         self
     }
 
+    /// Register external tools with pre-parsed arguments
+    ///
+    /// Like [`external`](Self::external), but the executor receives the arguments as a
+    /// JSON [`Value`](serde_json::Value) and may return an error, which is sent to the
+    /// model as `Error: <message>`. Invalid JSON arguments are reported to the model
+    /// without calling the executor.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider, types::ToolDefinition};
+    /// use serde_json::Value;
+    ///
+    /// # let defs: Vec<ToolDefinition> = vec![];
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .external_typed(defs, |name: String, args: Value| async move {
+    ///         let city = args["city"].as_str().ok_or("missing city")?;
+    ///         Ok(format!("{}: sunny in {}", name, city))
+    ///     });
+    /// ```
+    pub fn external_typed<Fut>(
+        self,
+        defs: Vec<ToolDefinition>,
+        exec: impl Fn(String, serde_json::Value) -> Fut + Clone + Send + Sync + 'static,
+    ) -> Self
+    where
+        Fut: Future<Output = Result<String, String>> + Send + 'static,
+    {
+        self.external(defs, move |name: String, args: String| {
+            let exec = exec.clone();
+            async move {
+                let args = match serde_json::from_str(&args) {
+                    Ok(args) => args,
+                    Err(e) => return e.to_string(),
+                };
+                exec(name, args)
+                    .await
+                    .unwrap_or_else(|e| format!("Error: {}", e))
+            }
+        })
+    }
+
+    /// Register external tools handling all calls of one turn in a single batch
+    ///
+    /// Like [`external`](Self::external), but the executor receives every call to one tool
+    /// from the same assistant message at once, e.g. to fetch many keys in one database round-trip.
+    /// It must return one [`ToolMessage`](crate::types::ToolMessage) per call,
+    /// with the `tool_call_id` of the call.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider, types::{ToolCall, ToolDefinition, ToolMessage}};
+    ///
+    /// # let defs: Vec<ToolDefinition> = vec![];
+    /// let agent = Agent::new(OpenAIProvider::new())
+    ///     .external_batch(defs, |calls: Vec<ToolCall>| async move {
+    ///         // One query for all keys
+    ///         calls
+    ///             .into_iter()
+    ///             .map(|call| ToolMessage {
+    ///                 content: format!("value for {}", call.function.arguments),
+    ///                 tool_call_id: call.id,
+    ///             })
+    ///             .collect()
+    ///     });
+    /// ```
+    pub fn external_batch<Fut>(
+        mut self,
+        defs: Vec<ToolDefinition>,
+        exec: impl Fn(Vec<ToolCall>) -> Fut + Clone + Send + Sync + 'static,
+    ) -> Self
+    where
+        Fut: Future<Output = Vec<crate::types::ToolMessage>> + Send + 'static,
+    {
+        for d in &defs {
+            let name = d.function.name.clone();
+            let single = exec.clone();
+            let batch = exec.clone();
+            self.executor.add(
+                name.clone(),
+                Box::new(
+                    // Only reached through `Tool::call`, which has no call ID;
+                    // executors pass single calls to the batch closure with their ID
+                    ClosureTool::boxed(move |s: String| {
+                        let call = ToolCall {
+                            id: String::new(),
+                            call_type: "function".into(),
+                            function: FunctionCall {
+                                name: name.clone(),
+                                arguments: s,
+                            },
+                        };
+                        let single = single.clone();
+                        Box::pin(async move {
+                            single(vec![call])
+                                .await
+                                .pop()
+                                .map(|m| m.content)
+                                .unwrap_or_default()
+                        })
+                    })
+                    .batch(move |calls| Box::pin(batch(calls))),
+                ),
+            );
+        }
+        self.tools.extend(defs);
+        self
+    }
+
     /// Execute one iteration of the agent loop.
     /// Returns `Ok(Some(content))` if loop should terminate, `Ok(None)` to continue
     ///
@@ -271,76 +1618,922 @@ impl Agent {
     /// # }
     /// ```
     pub async fn step(&mut self) -> crate::Result<Option<String>> {
+        let (content, done) = self.step_content().await?;
+        Ok(done.then_some(content))
+    }
+
+    /// Execute one iteration of the agent loop.
+    /// Returns the assistant's content and whether the loop should terminate
+    async fn step_content(&mut self) -> crate::Result<(String, bool)> {
+        if self.skip_completed
+            && let Some(TimedMessage {
+                message: Message::Assistant(m),
+                ..
+            }) = self.history.get_all().last()
+            && m.tool_calls.as_ref().is_none_or(Vec::is_empty)
+        {
+            tracing::debug!("History ends with a complete answer, skipping the LLM call");
+            return Ok((m.content.clone(), true));
+        }
+        let step_start = std::time::Instant::now();
+        if self.validate_tools {
+            for def in &self.tools {
+                def.validate()
+                    .map_err(|reason| crate::Error::InvalidToolDefinition {
+                        name: def.function.name.clone(),
+                        reason,
+                    })?;
+            }
+        }
+
+        let interrupts: Vec<_> = self.interrupts.0.lock().unwrap().drain(..).collect();
+        for content in interrupts {
+            tracing::debug!("Adding interrupt message, length: {}", content.len());
+            self.history.add(TimedMessage {
+                message: crate::types::UserMessage { content }.into(),
+                timestamp: std::time::SystemTime::now(),
+                elapsed: std::time::Duration::ZERO,
+                metadata: None,
+            });
+        }
+
+        let removed = self.history.sanitize();
+        if removed > 0 {
+            tracing::debug!("Removed {} invalid tool messages from history", removed);
+        }
+
         tracing::trace!("Calling LLM with {} messages", self.history.get_all().len());
 
-        let messages: Vec<_> = self
+        let mut messages: Vec<_> = self
             .history
             .get_all()
             .iter()
-            .map(|tm| tm.message.clone())
+            .map(|tm| self.message_to_send(tm))
             .collect();
+        if let Some((index, context)) = &self.turn_context {
+            let index = (*index).min(messages.len());
+            messages.splice(index..index, context.iter().cloned());
+        }
         let start = std::time::SystemTime::now();
-        let response = self.llm.call(&messages, &self.tools).await?;
+        let tools = if self.send_tools_first_only && self.tools_sent {
+            std::borrow::Cow::Borrowed(&[][..])
+        } else {
+            apply_catalog(&self.tools, &self.tool_catalog)
+        };
+        let mut response = self
+            .llm
+            .call_with(&messages, &tools, &self.call_options)
+            .await?;
+        self.usage += response.usage.unwrap_or_default();
+        self.tools_sent = true;
+        if let Some(streamed) = self.llm.streamed_content() {
+            self.streamed_content.push_str(streamed);
+        }
         let elapsed = start.elapsed().unwrap();
+        if let Some(hook) = &mut self.on_llm_response {
+            hook(&response);
+        }
+        if let FinishReason::Custom(reason) = &response.finish_reason
+            && self.tool_call_finish_reasons.contains(reason)
+        {
+            response.finish_reason = FinishReason::ToolCalls;
+        }
+
+        if let Some(parser) = &self.text_tool_calls
+            && response.message.tool_calls.is_none()
+            && matches!(response.finish_reason, FinishReason::Stop)
+        {
+            let calls = parser(&response.message.content);
+            if !calls.is_empty() {
+                tracing::debug!("Parsed {} tool calls from the content", calls.len());
+                let prefix = self.history.get_all().len();
+                let calls = calls
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, function)| ToolCall {
+                        id: format!("text_call_{}_{}", prefix, i),
+                        call_type: "function".into(),
+                        function,
+                    })
+                    .collect();
+                response.message.tool_calls = Some(calls);
+                response.finish_reason = FinishReason::ToolCalls;
+            }
+        }
+
+        if let Some(calls) = &mut response.message.tool_calls {
+            fix_duplicate_call_ids(calls, self.duplicate_call_ids);
+        }
 
-        self.history.add(crate::types::TimedMessage {
+        self.history.add(TimedMessage {
             message: response.message.clone().into(),
             timestamp: start + elapsed,
             elapsed,
+            metadata: None,
         });
+        if let Some(hook) = &mut self.on_assistant_complete {
+            hook(&response.message);
+        }
 
         // Execute tool calls if any
+        let tools_start = std::time::Instant::now();
+        let mut terminal_result = None;
         if let Some(calls) = &response.message.tool_calls {
-            tracing::debug!("Executing {} tool calls", calls.len());
-            let results = self.executor.execute(calls.clone()).await;
+            let mut results = self.execute_tools(calls.clone()).await;
+            if let Some(hook) = &mut self.after_tool_exec {
+                hook(&mut results);
+            }
+            let results = self.fit_result_budget(calls, results).await?;
+            terminal_result = calls
+                .iter()
+                .find(|c| {
+                    let name = &c.function.name;
+                    let name = self.tool_aliases.get(name).unwrap_or(name);
+                    self.terminal_tools.contains(name)
+                })
+                .and_then(|c| {
+                    results
+                        .iter()
+                        .find(|r| r.tool_message.tool_call_id == c.id)
+                        .map(|r| r.tool_message.content.clone())
+                });
             self.history.add_batch(
                 results
                     .into_iter()
-                    .map(|r| crate::types::TimedMessage {
+                    .map(|r| TimedMessage {
                         message: r.tool_message.into(),
                         timestamp: r.timestamp,
                         elapsed: r.elapsed,
+                        metadata: None,
                     })
                     .collect(),
             );
         }
 
+        if let Some(hook) = &mut self.on_step_timing {
+            hook(StepTiming {
+                llm: elapsed,
+                tools: tools_start.elapsed(),
+                total: step_start.elapsed(),
+            });
+        }
+
+        if let Some(content) = terminal_result {
+            tracing::debug!("Agent loop completed by a terminal tool");
+            return Ok((content, true));
+        }
+
         // Break loop if finish reason is not tool_calls
-        if !matches!(
-            response.finish_reason,
-            crate::types::FinishReason::ToolCalls
-        ) {
+        if !matches!(response.finish_reason, FinishReason::ToolCalls) {
             tracing::debug!(
                 "Agent loop completed, finish_reason: {:?}",
                 response.finish_reason
             );
-            return Ok(Some(response.message.content));
+            if self.error_on_finish.contains(&response.finish_reason) {
+                return Err(crate::Error::FinishReason(response.finish_reason));
+            }
+            if let Some(refusal) = response.message.refusal {
+                return Err(crate::Error::Refusal(refusal));
+            }
+            return Ok((response.message.content, true));
+        }
+
+        Ok((response.message.content, false))
+    }
+
+    /// The message of `tm` as sent to the LLM, with the timestamp if enabled
+    fn message_to_send(&self, tm: &TimedMessage) -> Message {
+        let mut message = tm.message.clone();
+        if !self.include_timestamps {
+            return message;
+        }
+        let content = match &mut message {
+            Message::User(m) => &mut m.content,
+            Message::Assistant(m) if !m.content.is_empty() => &mut m.content,
+            Message::Tool(m) => &mut m.content,
+            _ => return message,
+        };
+        let timestamp = match &self.timestamp_format {
+            Some(format) => format(tm.timestamp),
+            None => format_utc(tm.timestamp),
+        };
+        *content = format!("[{}] {}", timestamp, content);
+        message
+    }
+
+    /// Execute tool calls and post-process their results
+    async fn execute_tools(&mut self, calls: Vec<ToolCall>) -> Vec<ToolResult> {
+        tracing::debug!("Executing {} tool calls", calls.len());
+        let order: HashMap<_, _> = calls
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (c.id.clone(), i))
+            .collect();
+
+        // Short-circuit calls repeated beyond the limit
+        let mut repeated = Vec::new();
+        let mut recalled = Vec::new();
+        let mut to_execute = Vec::new();
+        let mut batch_counts: HashMap<_, usize> = HashMap::new();
+        for mut call in calls {
+            let mut name = call.function.name.clone();
+            if let Some(canonical) = self.tool_aliases.get(&name) {
+                tracing::debug!("Resolved tool alias '{}' to '{}'", name, canonical);
+                if !self.executor_aliases.contains(&name) {
+                    call.function.name = canonical.clone();
+                }
+                name = canonical.clone();
+            }
+            if self.recall_tool && name == recall::RecallArgs::TOOL_NAME {
+                recalled.push(ToolResult {
+                    tool_message: crate::types::ToolMessage {
+                        tool_call_id: call.id,
+                        content: recall::recall(self.history.get_all(), &call.function.arguments),
+                    },
+                    timestamp: std::time::SystemTime::now(),
+                    elapsed: std::time::Duration::ZERO,
+                });
+                continue;
+            }
+            let key = (name, call.function.arguments.clone());
+            let Some(limit) = self.repeat_call_limit else {
+                to_execute.push(call);
+                continue;
+            };
+            let in_batch = batch_counts.entry(key.clone()).or_default();
+            let (count, result) = match self.repeated_calls.get(&key) {
+                Some((count, result)) => (*count, result.as_str()),
+                None => (0, ""),
+            };
+            if count + *in_batch < limit {
+                *in_batch += 1;
+                to_execute.push(call);
+                continue;
+            }
+            tracing::debug!("Tool '{}' repeated {} times", key.0, count + *in_batch);
+            let content = if count >= limit && count > 0 {
+                format!(
+                    "You already called '{}' with the same arguments {} times. The result was: {}",
+                    key.0, count, result
+                )
+            } else if *in_batch > 0 {
+                format!(
+                    "Not executed: duplicate of an earlier call to '{}' with the same arguments in this message",
+                    key.0
+                )
+            } else {
+                format!(
+                    "Not executed: repeated calls to '{}' are not allowed",
+                    key.0
+                )
+            };
+            repeated.push(ToolResult {
+                tool_message: crate::types::ToolMessage {
+                    tool_call_id: call.id,
+                    content,
+                },
+                timestamp: std::time::SystemTime::now(),
+                elapsed: std::time::Duration::ZERO,
+            });
+        }
+
+        let keys: HashMap<_, _> = to_execute
+            .iter()
+            .map(|c| {
+                let name = &c.function.name;
+                let name = self.tool_aliases.get(name).unwrap_or(name);
+                (c.id.clone(), (name.clone(), c.function.arguments.clone()))
+            })
+            .collect();
+        let mut rejected = Vec::new();
+        let batch_size = match self.max_parallel_tool_calls {
+            Some((limit, ParallelCallOverflow::Reject)) if to_execute.len() > limit => {
+                tracing::debug!("Rejecting {} tool calls", to_execute.len() - limit);
+                rejected = to_execute
+                    .split_off(limit)
+                    .into_iter()
+                    .map(|call| ToolResult {
+                        tool_message: crate::types::ToolMessage {
+                            tool_call_id: call.id,
+                            content: format!(
+                                "Not executed: at most {} tool calls can be made at once. \
+                                 Call it again later if still needed.",
+                                limit
+                            ),
+                        },
+                        timestamp: std::time::SystemTime::now(),
+                        elapsed: std::time::Duration::ZERO,
+                    })
+                    .collect();
+                limit
+            }
+            Some((limit, _)) => limit,
+            None => to_execute.len(),
+        };
+        let mut results = Vec::new();
+        let mut to_execute = to_execute.into_iter().peekable();
+        while to_execute.peek().is_some() {
+            let batch = to_execute.by_ref().take(batch_size).collect();
+            results.extend(self.executor.execute(batch).await);
+        }
+
+        if self.repeat_call_limit.is_some() {
+            for r in &results {
+                if let Some(key) = keys.get(&r.tool_message.tool_call_id) {
+                    let entry = self.repeated_calls.entry(key.clone()).or_default();
+                    entry.0 += 1;
+                    entry.1 = r.tool_message.content.clone();
+                }
+            }
+        }
+
+        if let Some(threshold) = self.spill_threshold {
+            for r in results
+                .iter_mut()
+                .filter(|r| r.tool_message.content.len() > threshold)
+            {
+                match crate::tool::spill_to_file(
+                    &r.tool_message.content,
+                    &r.tool_message.tool_call_id,
+                    threshold,
+                ) {
+                    Ok((path, preview)) => {
+                        tracing::debug!("Spilled tool result to {}", path.display());
+                        r.tool_message.content = preview;
+                    }
+                    Err(e) => tracing::debug!("Failed to spill tool result: {}", e),
+                }
+            }
         }
 
-        Ok(None)
+        results.extend(repeated);
+        results.extend(recalled);
+        results.extend(rejected);
+        results.sort_by_key(|r| {
+            order
+                .get(&r.tool_message.tool_call_id)
+                .copied()
+                .unwrap_or(usize::MAX)
+        });
+        results
+    }
+
+    /// Apply the [`ResultBudgetPolicy`] if the results exceed the token budget
+    async fn fit_result_budget(
+        &mut self,
+        calls: &[ToolCall],
+        mut results: Vec<ToolResult>,
+    ) -> crate::Result<Vec<ToolResult>> {
+        let Some(budget) = &self.result_budget else {
+            return Ok(results);
+        };
+        let counts: Vec<_> = results
+            .iter()
+            .map(|r| budget.tokenizer.count(&r.tool_message.content))
+            .collect();
+        let tokens: usize = counts.iter().sum();
+        if tokens <= budget.max_tokens || results.is_empty() {
+            return Ok(results);
+        }
+        tracing::debug!(
+            "Tool results exceed budget: {} > {} tokens, policy: {:?}",
+            tokens,
+            budget.max_tokens,
+            budget.policy
+        );
+
+        match budget.policy {
+            ResultBudgetPolicy::Error => {
+                return Err(crate::Error::ToolResultsTooLarge {
+                    tokens,
+                    max_tokens: budget.max_tokens,
+                });
+            }
+            ResultBudgetPolicy::TruncateEach => {
+                let share = budget.max_tokens / results.len();
+                for (r, count) in results.iter_mut().zip(counts) {
+                    if count <= share {
+                        continue;
+                    }
+                    let content = &r.tool_message.content;
+                    let mut end = content.len() * share / count;
+                    while !content.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    r.tool_message.content = format!(
+                        "{}\n[Truncated from about {} tokens]",
+                        &content[..end],
+                        count
+                    );
+                }
+            }
+            ResultBudgetPolicy::SummarizeAll => {
+                let names: HashMap<_, _> = calls
+                    .iter()
+                    .map(|c| (c.id.as_str(), c.function.name.as_str()))
+                    .collect();
+                let mut prompt = String::from(
+                    "Summarize the following tool results concisely, keeping every detail needed to continue the task.\n",
+                );
+                for r in &results {
+                    let id = &r.tool_message.tool_call_id;
+                    let name = names.get(id.as_str()).copied().unwrap_or_default();
+                    prompt.push_str(&format!(
+                        "\n## {} ({})\n{}\n",
+                        name, id, r.tool_message.content
+                    ));
+                }
+                let messages = [crate::types::UserMessage { content: prompt }.into()];
+                let response = self
+                    .llm
+                    .call_with(&messages, &[], &self.call_options)
+                    .await?;
+                self.usage += response.usage.unwrap_or_default();
+                let summary = response.message.content;
+
+                let first_id = results[0].tool_message.tool_call_id.clone();
+                results[0].tool_message.content = summary;
+                for r in &mut results[1..] {
+                    r.tool_message.content = format!(
+                        "[Summarized together with other results in the result of call '{}']",
+                        first_id
+                    );
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    /// Execute one iteration, retrying as long as the [`on_error`](Self::on_error) hook says so
+    async fn step_with_recovery(&mut self) -> crate::Result<(String, bool)> {
+        if let Some(max_steps) = self.max_steps
+            && self.steps >= max_steps
+        {
+            return Err(crate::Error::MaxSteps {
+                max_steps,
+                partial: self.partial_response.take(),
+            });
+        }
+        self.steps += 1;
+        let mut trimmed = false;
+        loop {
+            match self.step_content().await {
+                Err(e)
+                    if self.auto_trim_on_overflow
+                        && !trimmed
+                        && e.api_error_code().as_deref() == Some("context_length_exceeded") =>
+                {
+                    trimmed = true;
+                    let removed = self.trim_oldest();
+                    if removed == 0 {
+                        return Err(e);
+                    }
+                    tracing::debug!("Context length exceeded, removed {} messages", removed);
+                }
+                Err(e) => {
+                    let Some(hook) = &mut self.on_error else {
+                        return Err(e);
+                    };
+                    match hook(&e, self.history.as_mut()) {
+                        ErrorAction::Retry => tracing::debug!("Retrying step after error: {}", e),
+                        ErrorAction::Fail => return Err(e),
+                    }
+                }
+                Ok((mut content, done)) => {
+                    if self.trim_responses {
+                        content = content.trim().to_string();
+                    }
+                    if !content.is_empty() {
+                        self.partial_response = Some(content.clone());
+                    }
+                    return Ok((content, done));
+                }
+            }
+        }
+    }
+
+    /// Remove the oldest half of the non-system messages before the last user message,
+    /// extended to the next user message so whole turns are removed.
+    /// Return the number of removed messages
+    fn trim_oldest(&mut self) -> usize {
+        let messages = self.history.get_all();
+        let end = messages
+            .iter()
+            .rposition(|tm| matches!(tm.message, Message::User(_)))
+            .unwrap_or(0);
+        let candidates: Vec<_> = (0..end)
+            .filter(|&i| !matches!(messages[i].message, Message::System(_)))
+            .collect();
+        let half = candidates.len().div_ceil(2);
+        let count = (half..candidates.len())
+            .find(|&i| matches!(messages[candidates[i]].message, Message::User(_)))
+            .unwrap_or(candidates.len());
+        candidates[..count]
+            .iter()
+            .rev()
+            .filter(|&&i| self.history.remove(i).is_some())
+            .count()
+    }
+
+    /// Reset per-run state
+    fn begin_run(&mut self) {
+        self.repeated_calls.clear();
+        self.steps = 0;
+        self.partial_response = None;
+        self.streamed_content.clear();
+        self.call_options.retry_budget = self.retry_budget.map(RetryBudget::new);
     }
 
     /// Run the agent loop until completion.
-    /// Return the last AI's response
+    /// Return the last AI's response, or every response if [`join_responses`](Self::join_responses) is set
     pub async fn run(&mut self) -> crate::Result<String> {
         tracing::debug!("Starting agent loop");
+        let separator = self.response_separator.clone();
+        self.run_collect(separator.as_deref()).await
+    }
+
+    /// Run the agent loop until completion within the `agent_run` tracing span.
+    /// See [`run_steps`](Self::run_steps)
+    async fn run_collect(&mut self, separator: Option<&str>) -> crate::Result<String> {
+        use tracing::Instrument;
+
+        let span = tracing::debug_span!(
+            "agent_run",
+            request_id = self.call_options.request_id.as_deref()
+        );
+        self.run_steps(separator).instrument(span).await
+    }
+
+    /// Run the agent loop until completion.
+    /// Return the last response, or the non-empty responses joined by `separator`
+    async fn run_steps(&mut self, separator: Option<&str>) -> crate::Result<String> {
+        self.begin_run();
+        let mut segments = Vec::new();
         loop {
-            if let Some(content) = self.step().await? {
-                return Ok(content);
+            let (content, done) = self.step_with_recovery().await?;
+            let Some(separator) = separator else {
+                if done {
+                    return Ok(content);
+                }
+                continue;
+            };
+            if !content.is_empty() {
+                segments.push(content);
+            }
+            if done {
+                return Ok(segments.join(separator));
+            }
+        }
+    }
+
+    /// Run the agent loop until completion or until `cancel` resolves.
+    /// Return the last AI's response, or [`Error::Cancelled`](crate::Error::Cancelled)
+    ///
+    /// On cancellation the in-flight LLM call is dropped, which drops the
+    /// streaming HTTP response and closes the connection, so no more tokens are generated.
+    /// Tool executions in flight are dropped as well; history keeps whatever was
+    /// added before the cancellation.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// # async fn example() -> tiny_loop::Result<()> {
+    /// let mut agent = Agent::new(OpenAIProvider::new().stream_callback(|c| print!("{}", c)));
+    /// let (stop, stopped) = futures::channel::oneshot::channel::<()>();
+    /// // Call `stop.send(())` from elsewhere, e.g. a UI handler
+    /// # drop(stop);
+    /// let result = agent.run_stream(async { stopped.await.ok(); }).await;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn run_stream(&mut self, cancel: impl Future<Output = ()>) -> crate::Result<String> {
+        let run = std::pin::pin!(self.run());
+        let cancel = std::pin::pin!(cancel);
+        match futures::future::select(run, cancel).await {
+            futures::future::Either::Left((result, _)) => result,
+            futures::future::Either::Right(_) => {
+                tracing::debug!("Agent run cancelled");
+                Err(crate::Error::Cancelled)
             }
         }
     }
 
+    /// Run the agent loop until completion.
+    /// Return the content of every assistant message of this run, joined by blank lines
+    ///
+    /// Unlike [`run`](Self::run), which only returns the final response,
+    /// this also includes content the model emitted alongside tool calls
+    /// in intermediate rounds. Empty segments are skipped.
+    pub async fn run_joined(&mut self) -> crate::Result<String> {
+        tracing::debug!("Starting agent loop (joined)");
+        self.run_collect(Some("\n\n")).await
+    }
+
     /// Run the agent loop with a new user input appended.
     /// Return the last AI's response
     pub async fn chat(&mut self, prompt: impl Into<String>) -> crate::Result<String> {
-        let prompt = prompt.into();
-        tracing::debug!("Chat request, prompt length: {}", prompt.len());
-        self.history.add(crate::types::TimedMessage {
+        self.chat_with_context(prompt, Vec::new()).await
+    }
+
+    /// Run the agent loop with a new user input appended, like [`chat`](Self::chat),
+    /// sending `context` right before the prompt in every LLM call of this turn.
+    /// Return the last AI's response
+    ///
+    /// The context messages are not added to the history, so retrieved
+    /// documents don't bloat later turns.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider, types::SystemMessage};
+    ///
+    /// # async fn example() -> tiny_loop::Result<()> {
+    /// let mut agent = Agent::new(OpenAIProvider::new());
+    /// let docs = SystemMessage {
+    ///     content: "Relevant documents:\n...".into(),
+    /// };
+    /// let answer = agent.chat_with_context("How do I reset my password?", vec![docs.into()]).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn chat_with_context(
+        &mut self,
+        prompt: impl Into<String>,
+        context: Vec<Message>,
+    ) -> crate::Result<String> {
+        self.chat_turn(prompt.into(), context, None).await
+    }
+
+    /// Run the agent loop with a new user input appended, like [`chat`](Self::chat),
+    /// attaching `metadata` to the user message in the history.
+    /// Return the last AI's response
+    ///
+    /// The metadata is for the application only, e.g. which user sent the message,
+    /// and is never sent to the LLM. See [`TimedMessage::metadata`]
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// # async fn example() -> tiny_loop::Result<()> {
+    /// let mut agent = Agent::new(OpenAIProvider::new());
+    /// let meta = serde_json::json!({ "user_id": "u_123" });
+    /// let answer = agent.chat_with_meta("What's on my calendar?", meta).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn chat_with_meta(
+        &mut self,
+        prompt: impl Into<String>,
+        metadata: serde_json::Value,
+    ) -> crate::Result<String> {
+        self.chat_turn(prompt.into(), Vec::new(), Some(metadata))
+            .await
+    }
+
+    /// Add the user message and run the agent loop with the turn context
+    async fn chat_turn(
+        &mut self,
+        prompt: String,
+        context: Vec<Message>,
+        metadata: Option<serde_json::Value>,
+    ) -> crate::Result<String> {
+        tracing::debug!(
+            "Chat request, prompt length: {}, context messages: {}",
+            prompt.len(),
+            context.len()
+        );
+        self.turn_context = (!context.is_empty()).then(|| (self.history.get_all().len(), context));
+        self.history.add(TimedMessage {
             message: crate::types::UserMessage { content: prompt }.into(),
             timestamp: std::time::SystemTime::now(),
             elapsed: std::time::Duration::ZERO,
+            metadata,
         });
-        self.run().await
+        let result = self.run().await;
+        self.turn_context = None;
+        result
+    }
+
+    /// The content chunks streamed by the provider during the last run, concatenated as received.
+    /// Empty if the provider doesn't report them. See [`LLMProvider::streamed_content`]
+    ///
+    /// Useful to debug differences between the streamed text and the assembled messages.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// # async fn example() -> tiny_loop::Result<()> {
+    /// let mut agent = Agent::new(OpenAIProvider::new().stream_callback(|c| print!("{}", c)));
+    /// let answer = agent.chat("Hello").await?;
+    /// if agent.last_streamed_content() != answer {
+    ///     eprintln!("Streamed content differs from the answer");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn last_streamed_content(&self) -> &str {
+        &self.streamed_content
     }
+
+    /// Summarize the conversation with `llm`, e.g. to hand a session over to another agent.
+    /// The history of the agent is not modified
+    ///
+    /// Sends the history followed by a summarization instruction, without tools.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// # async fn example() -> tiny_loop::Result<()> {
+    /// let mut agent = Agent::new(OpenAIProvider::new());
+    /// agent.chat("Help me plan a trip to Japan").await?;
+    ///
+    /// let mut summarizer = OpenAIProvider::new().model("gpt-4o-mini");
+    /// let summary = agent.summarize(&mut summarizer).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn summarize(&self, llm: &mut dyn LLMProvider) -> crate::Result<String> {
+        let mut messages: Vec<_> = self
+            .history
+            .get_all()
+            .iter()
+            .map(|tm| tm.message.clone())
+            .collect();
+        tracing::debug!("Summarizing {} messages", messages.len());
+        messages.push(
+            crate::types::UserMessage {
+                content:
+                    "Summarize the conversation so far concisely, including the user's goals, \
+                          the decisions made, important facts and any open tasks. \
+                          Reply with the summary only."
+                        .into(),
+            }
+            .into(),
+        );
+        Ok(llm.call(&messages, &[]).await?.message.content)
+    }
+
+    /// Force streaming on or off for the following LLM calls, or `None` to restore the default
+    /// of the provider. See [`CallOptions::stream`]
+    ///
+    /// E.g. disable streaming for one turn requesting structured output,
+    /// while keeping the stream callback registered for the other turns.
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, llm::OpenAIProvider};
+    ///
+    /// # async fn example() -> tiny_loop::Result<()> {
+    /// let mut agent = Agent::new(OpenAIProvider::new().stream_callback(|c| print!("{}", c)));
+    /// agent.set_stream_override(Some(false));
+    /// let json = agent.chat("List three colors as a JSON array").await?;
+    /// agent.set_stream_override(None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_stream_override(&mut self, stream: Option<bool>) {
+        self.call_options.stream = stream;
+    }
+
+    /// Wait for in-flight tool executions and add the results recovered from an
+    /// interrupted run (e.g. cancelled by [`run_stream`](Self::run_stream)) to the history.
+    /// Return the number of recovered results
+    ///
+    /// Call this before shutting down or continuing after a cancellation, so
+    /// completed tool calls with side effects aren't lost. See [`ToolExecutor::shutdown`]
+    pub async fn shutdown(&mut self) -> usize {
+        let results = self.executor.shutdown().await;
+        tracing::debug!("Recovered {} tool results on shutdown", results.len());
+        let count = results.len();
+        self.history.add_batch(
+            results
+                .into_iter()
+                .map(|r| TimedMessage {
+                    message: r.tool_message.into(),
+                    timestamp: r.timestamp,
+                    elapsed: r.elapsed,
+                    metadata: None,
+                })
+                .collect(),
+        );
+        count
+    }
+}
+
+/// Rename or drop tool calls whose id was already used by an earlier call
+fn fix_duplicate_call_ids(calls: &mut Vec<ToolCall>, policy: DuplicateCallIds) {
+    let mut seen = HashSet::new();
+    let mut duplicates = Vec::new();
+    for (i, call) in calls.iter().enumerate() {
+        if !seen.insert(call.id.clone()) {
+            duplicates.push(i);
+        }
+    }
+    if duplicates.is_empty() {
+        return;
+    }
+    tracing::debug!("Found {} duplicate tool call ids", duplicates.len());
+
+    match policy {
+        DuplicateCallIds::Drop => {
+            for i in duplicates.into_iter().rev() {
+                calls.remove(i);
+            }
+        }
+        DuplicateCallIds::Rename => {
+            for i in duplicates {
+                let id = (2..)
+                    .map(|n| format!("{}_{}", calls[i].id, n))
+                    .find(|id| !seen.contains(id))
+                    .unwrap();
+                seen.insert(id.clone());
+                calls[i].id = id;
+            }
+        }
+    }
+}
+
+/// Count the tool calls of assistant messages by tool name
+fn count_tool_calls(messages: &[TimedMessage]) -> HashMap<String, usize> {
+    let mut stats = HashMap::new();
+    for tm in messages {
+        if let crate::types::Message::Assistant(m) = &tm.message {
+            for call in m.tool_calls.iter().flatten() {
+                *stats.entry(call.function.name.clone()).or_default() += 1;
+            }
+        }
+    }
+    stats
+}
+
+/// Tool definitions to send, with the catalog applied
+fn apply_catalog<'a>(
+    tools: &'a [ToolDefinition],
+    catalog: &HashMap<String, ToolMeta>,
+) -> std::borrow::Cow<'a, [ToolDefinition]> {
+    if catalog.is_empty() {
+        return tools.into();
+    }
+    tools
+        .iter()
+        .cloned()
+        .map(|mut def| {
+            if let Some(meta) = catalog.get(&def.function.name) {
+                def.apply_meta(meta);
+            }
+            def
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// Parse tool arguments, returning the result content for the model on failure
+fn parse_args<Args: ToolArgs>(s: &str, schema_errors: &AtomicBool) -> Result<Args, String> {
+    serde_json::from_str::<Args>(s).map_err(|e| {
+        if !schema_errors.load(Ordering::Relaxed) {
+            return e.to_string();
+        }
+        let schema = serde_json::to_string_pretty(&Args::definition().function.parameters)
+            .unwrap_or_default();
+        format!(
+            "Invalid arguments for tool '{}': {}\n\nThe arguments must be a JSON object matching this schema:\n{}",
+            Args::TOOL_NAME,
+            e,
+            schema
+        )
+    })
+}
+
+/// Format `time` as UTC in RFC 3339 with second precision, e.g. `2026-01-02T03:04:05Z`
+fn format_utc(time: std::time::SystemTime) -> String {
+    let secs = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,69 @@
+use super::{Agent, DuplicateCallIds};
+use crate::llm::LLMProvider;
+
+/// Declarative agent configuration, e.g. loaded from a JSON or YAML file.
+/// See [`Agent::with_config`]
+///
+/// Every field is optional and defaults to the agent's default.
+/// Provider options such as the model, tool choice or streaming
+/// are configured on the provider itself.
+///
+/// # Example
+/// ```
+/// use tiny_loop::AgentConfig;
+///
+/// let config: AgentConfig = serde_json::from_str(r#"{
+///     "system": "You are a helpful assistant",
+///     "max_steps": 10,
+///     "duplicate_call_ids": "drop"
+/// }"#).unwrap();
+/// ```
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AgentConfig {
+    /// See [`Agent::system`]
+    pub system: Option<String>,
+    /// See [`Agent::max_steps`]
+    pub max_steps: Option<usize>,
+    /// See [`Agent::spill_large_results`]
+    pub spill_large_results: Option<usize>,
+    /// See [`Agent::repeat_call_limit`]
+    pub repeat_call_limit: Option<usize>,
+    /// See [`Agent::retry_budget`]
+    pub retry_budget: Option<u32>,
+    /// See [`Agent::validate_tools`]
+    pub validate_tools: bool,
+    /// See [`Agent::send_tools_first_only`]
+    pub send_tools_first_only: bool,
+    /// See [`Agent::duplicate_call_ids`]
+    pub duplicate_call_ids: DuplicateCallIds,
+}
+
+impl Agent {
+    /// Create a new agent loop configured by `config`
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::{Agent, AgentConfig, llm::OpenAIProvider};
+    ///
+    /// # fn example() -> Result<(), Box<dyn std::error::Error>> {
+    /// let config: AgentConfig = serde_json::from_str(&std::fs::read_to_string("agent.json")?)?;
+    /// let agent = Agent::with_config(OpenAIProvider::new(), config);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_config(llm: impl LLMProvider + 'static, config: AgentConfig) -> Self {
+        let mut agent = Self::new(llm)
+            .validate_tools(config.validate_tools)
+            .send_tools_first_only(config.send_tools_first_only)
+            .duplicate_call_ids(config.duplicate_call_ids);
+        if let Some(content) = config.system {
+            agent = agent.system(content);
+        }
+        agent.max_steps = config.max_steps;
+        agent.spill_threshold = config.spill_large_results;
+        agent.repeat_call_limit = config.repeat_call_limit;
+        agent.retry_budget = config.retry_budget;
+        agent
+    }
+}
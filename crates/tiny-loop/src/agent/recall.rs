@@ -0,0 +1,105 @@
+use crate::{
+    tool::ToolArgs,
+    types::{Message, TimedMessage},
+};
+
+/// Default number of messages returned by the `recall` tool
+const DEFAULT_LIMIT: usize = 5;
+/// Maximum number of chars of each returned message
+const MAX_CHARS: usize = 200;
+
+/// Arguments of the builtin `recall` tool. See [`Agent::recall_tool`](super::Agent::recall_tool)
+#[derive(serde::Deserialize, schemars::JsonSchema)]
+pub(super) struct RecallArgs {
+    /// Case-insensitive keyword to search for. Omit to get the most recent messages
+    query: Option<String>,
+    /// Maximum number of messages to return (default: 5)
+    limit: Option<usize>,
+}
+
+impl ToolArgs for RecallArgs {
+    const TOOL_NAME: &'static str = "recall";
+    const TOOL_DESCRIPTION: &'static str =
+        "Search the earlier messages of this conversation by keyword, or get the most recent ones";
+}
+
+/// Run the `recall` tool against the history.
+/// Return the latest matching messages, oldest first, truncated to [`MAX_CHARS`]
+pub(super) fn recall(messages: &[TimedMessage], arguments: &str) -> String {
+    let args: RecallArgs = match serde_json::from_str(arguments) {
+        Ok(args) => args,
+        Err(e) => return format!("Invalid arguments for tool 'recall': {}", e),
+    };
+    let query = args.query.map(|q| q.to_lowercase());
+    let mut found: Vec<_> = messages
+        .iter()
+        .enumerate()
+        .filter_map(|(i, tm)| {
+            let (role, content) = match &tm.message {
+                Message::User(m) => ("user", &m.content),
+                Message::Assistant(m) => ("assistant", &m.content),
+                Message::Tool(m) => ("tool", &m.content),
+                Message::System(_) | Message::Custom(_) => return None,
+            };
+            let matches = match &query {
+                Some(query) => content.to_lowercase().contains(query),
+                None => !content.is_empty(),
+            };
+            matches.then_some((i, role, content))
+        })
+        .rev()
+        .take(args.limit.unwrap_or(DEFAULT_LIMIT))
+        .map(|(i, role, content)| {
+            let mut text: String = content.chars().take(MAX_CHARS).collect();
+            if text.len() < content.len() {
+                text.push_str("...");
+            }
+            format!("#{} {}: {}", i, role, text)
+        })
+        .collect();
+    if found.is_empty() {
+        return "No matching messages found".into();
+    }
+    found.reverse();
+    found.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock::timed;
+    use crate::types::{SystemMessage, UserMessage};
+
+    #[test]
+    fn test_recall() {
+        let messages: Vec<_> = [
+            SystemMessage {
+                content: "Remember the parking spot".into(),
+            }
+            .into(),
+            UserMessage {
+                content: "I parked on level 3".into(),
+            }
+            .into(),
+            UserMessage {
+                content: "x".repeat(300),
+            }
+            .into(),
+        ]
+        .into_iter()
+        .map(timed)
+        .collect();
+
+        assert_eq!(
+            recall(&messages, r#"{"query": "PARKED"}"#),
+            "#1 user: I parked on level 3"
+        );
+        let recent = recall(&messages, r#"{"limit": 1}"#);
+        assert_eq!(recent, format!("#2 user: {}...", "x".repeat(200)));
+        assert_eq!(
+            recall(&messages, r#"{"query": "spot"}"#),
+            "No matching messages found"
+        );
+        assert!(recall(&messages, "[]").starts_with("Invalid arguments"));
+    }
+}
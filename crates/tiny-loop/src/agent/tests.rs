@@ -0,0 +1,2079 @@
+use super::*;
+use crate::{
+    llm::mock::{MockCalls, MockProvider},
+    types::Message,
+};
+use std::sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+};
+
+#[tokio::test]
+async fn test_spill_large_results() {
+    let llm = MockProvider::new()
+        .tool_calls("", &[("call_1", "big", "{}")])
+        .text("done");
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm).spill_large_results(10);
+    agent.executor.add(
+        "big".into(),
+        Box::new(ClosureTool::boxed(|_| Box::pin(async { "x".repeat(100) }))),
+    );
+
+    assert_eq!(agent.chat("go").await.unwrap(), "done");
+
+    let calls = calls.lock().unwrap();
+    let Message::Tool(tool) = calls[1].0.last().unwrap() else {
+        panic!("expected tool message");
+    };
+    assert!(tool.content.contains("Preview:\nxxxxxxxxxx"));
+    let path = tool
+        .content
+        .split("Full output saved to: ")
+        .nth(1)
+        .unwrap()
+        .split(". Use")
+        .next()
+        .unwrap();
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "x".repeat(100));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_run_joined() {
+    let llm = MockProvider::new()
+        .tool_calls("Let me check.", &[("call_1", "noop", "{}")])
+        .tool_calls("", &[("call_2", "noop", "{}")])
+        .tool_calls("Almost there.", &[("call_3", "noop", "{}")])
+        .text("Done.");
+    let mut agent = Agent::new(llm);
+
+    assert_eq!(
+        agent.run_joined().await.unwrap(),
+        "Let me check.\n\nAlmost there.\n\nDone."
+    );
+}
+
+#[tokio::test]
+async fn test_join_responses() {
+    let llm = MockProvider::new()
+        .tool_calls(
+            "Let me check the weather.",
+            &[("call_1", "get_weather", r#"{"city":"Paris"}"#)],
+        )
+        .text("It is sunny in Paris.");
+    let mut agent = Agent::new(llm).tool(get_weather).join_responses(" ");
+
+    assert_eq!(
+        agent.chat("Weather in Paris?").await.unwrap(),
+        "Let me check the weather. It is sunny in Paris."
+    );
+}
+
+#[tokio::test]
+async fn test_run_returns_final_content_only() {
+    let llm = MockProvider::new()
+        .tool_calls("Let me check.", &[("call_1", "noop", "{}")])
+        .text("Done.");
+    let mut agent = Agent::new(llm);
+
+    assert_eq!(agent.run().await.unwrap(), "Done.");
+}
+
+#[tokio::test]
+async fn test_repeat_call_limit() {
+    let llm = MockProvider::new()
+        .tool_calls("", &[("call_1", "count", "{}")])
+        .tool_calls("", &[("call_2", "count", "{}")])
+        .tool_calls("", &[("call_3", "count", "{}")])
+        .text("done");
+    let calls = llm.calls();
+    let counter = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let mut agent = Agent::new(llm).repeat_call_limit(2);
+    let c = counter.clone();
+    agent.executor.add(
+        "count".into(),
+        Box::new(ClosureTool::boxed(move |_| {
+            let n = c.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+            Box::pin(async move { n.to_string() })
+        })),
+    );
+
+    agent.chat("go").await.unwrap();
+
+    assert_eq!(counter.load(std::sync::atomic::Ordering::SeqCst), 2);
+    let calls = calls.lock().unwrap();
+    let Message::Tool(tool) = calls[3].0.last().unwrap() else {
+        panic!("expected tool message");
+    };
+    assert_eq!(tool.tool_call_id, "call_3");
+    assert!(tool.content.contains("already called 'count'"));
+    assert!(tool.content.ends_with("The result was: 2"));
+}
+
+#[tokio::test]
+async fn test_repeat_call_limit_within_message() {
+    let llm = MockProvider::new()
+        .tool_calls(
+            "",
+            &[
+                ("call_1", "count", "{}"),
+                ("call_2", "count", "{}"),
+                ("call_3", "count", "{}"),
+            ],
+        )
+        .tool_calls("", &[("call_4", "count", "{}")])
+        .text("done");
+    let calls = llm.calls();
+    let counter = Arc::new(AtomicUsize::new(0));
+    let mut agent = Agent::new(llm).repeat_call_limit(1);
+    let c = counter.clone();
+    agent.executor.add(
+        "count".into(),
+        Box::new(ClosureTool::boxed(move |_| {
+            let n = c.fetch_add(1, Ordering::SeqCst) + 1;
+            Box::pin(async move { n.to_string() })
+        })),
+    );
+
+    agent.chat("go").await.unwrap();
+
+    assert_eq!(counter.load(Ordering::SeqCst), 1);
+    let results = tool_contents(&calls, 2);
+    assert_eq!(results[0], "1");
+    assert!(results[1].starts_with("Not executed: duplicate of an earlier call to 'count'"));
+    assert_eq!(results[1], results[2]);
+    assert!(results[3].ends_with("The result was: 1"));
+
+    // A limit of 0 never executes
+    let llm = MockProvider::new()
+        .tool_calls("", &[("call_1", "count", "{}")])
+        .text("done");
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm).repeat_call_limit(0);
+    let c = counter.clone();
+    agent.executor.add(
+        "count".into(),
+        Box::new(ClosureTool::boxed(move |_| {
+            let n = c.fetch_add(1, Ordering::SeqCst) + 1;
+            Box::pin(async move { n.to_string() })
+        })),
+    );
+    agent.chat("go").await.unwrap();
+    assert_eq!(counter.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        tool_contents(&calls, 1),
+        ["Not executed: repeated calls to 'count' are not allowed"]
+    );
+}
+
+/// IDs of the tool messages sent in the LLM call at `index`
+fn tool_ids(calls: &MockCalls, index: usize) -> Vec<String> {
+    calls.lock().unwrap()[index]
+        .0
+        .iter()
+        .filter_map(|m| match m {
+            Message::Tool(t) => Some(t.tool_call_id.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_repeated_calls_keep_call_order() {
+    let llm = MockProvider::new()
+        .tool_calls("", &[("call_1", "echo", "a")])
+        .tool_calls(
+            "",
+            &[
+                ("call_2", "echo", "a"),
+                ("call_3", "echo", "b"),
+                ("call_4", "echo", "a"),
+            ],
+        )
+        .text("done");
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm).repeat_call_limit(1);
+    agent.executor.add(
+        "echo".into(),
+        Box::new(ClosureTool::boxed(|args| Box::pin(async move { args }))),
+    );
+
+    agent.chat("go").await.unwrap();
+
+    assert_eq!(
+        tool_ids(&calls, 2),
+        ["call_1", "call_2", "call_3", "call_4"]
+    );
+    assert!(tool_contents(&calls, 2)[1].starts_with("You already called"));
+    assert_eq!(tool_contents(&calls, 2)[2], "b");
+}
+
+fn parallel_agent(policy: ParallelCallOverflow) -> (Agent, MockCalls, Arc<AtomicUsize>) {
+    let llm = MockProvider::new()
+        .tool_calls(
+            "",
+            &[
+                ("call_1", "work", "{}"),
+                ("call_2", "work", "{}"),
+                ("call_3", "work", "{}"),
+            ],
+        )
+        .text("done");
+    let calls = llm.calls();
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let mut agent = Agent::new(llm).max_parallel_tool_calls(2, policy);
+    let (f, p) = (in_flight.clone(), peak.clone());
+    agent.executor.add(
+        "work".into(),
+        Box::new(ClosureTool::boxed(move |_| {
+            let (f, p) = (f.clone(), p.clone());
+            Box::pin(async move {
+                p.fetch_max(f.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                f.fetch_sub(1, Ordering::SeqCst);
+                "ok".into()
+            })
+        })),
+    );
+    (agent, calls, peak)
+}
+
+#[tokio::test]
+async fn test_max_parallel_tool_calls_reject() {
+    let (mut agent, calls, peak) = parallel_agent(ParallelCallOverflow::Reject);
+
+    agent.chat("go").await.unwrap();
+
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+    let results = tool_contents(&calls, 1);
+    assert_eq!(results[..2], ["ok", "ok"]);
+    assert!(results[2].starts_with("Not executed: at most 2 tool calls"));
+}
+
+#[tokio::test]
+async fn test_max_parallel_tool_calls_keep_call_order() {
+    let llm = MockProvider::new()
+        .tool_calls("", &[("call_1", "echo", "a")])
+        .tool_calls(
+            "",
+            &[
+                ("call_2", "echo", "a"),
+                ("call_3", "echo", "b"),
+                ("call_4", "echo", "c"),
+                ("call_5", "echo", "d"),
+            ],
+        )
+        .text("done");
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm)
+        .repeat_call_limit(1)
+        .max_parallel_tool_calls(2, ParallelCallOverflow::Reject);
+    agent.executor.add(
+        "echo".into(),
+        Box::new(ClosureTool::boxed(|args| Box::pin(async move { args }))),
+    );
+
+    agent.chat("go").await.unwrap();
+
+    assert_eq!(
+        tool_ids(&calls, 2),
+        ["call_1", "call_2", "call_3", "call_4", "call_5"]
+    );
+    let results = tool_contents(&calls, 2);
+    assert!(results[1].starts_with("You already called"));
+    assert_eq!(results[2..4], ["b", "c"]);
+    assert!(results[4].starts_with("Not executed"));
+}
+
+#[tokio::test]
+async fn test_max_parallel_tool_calls_zero() {
+    let (agent, calls, peak) = parallel_agent(ParallelCallOverflow::Reject);
+    let mut agent = agent.max_parallel_tool_calls(0, ParallelCallOverflow::Reject);
+
+    agent.chat("go").await.unwrap();
+
+    assert_eq!(peak.load(Ordering::SeqCst), 1);
+    let results = tool_contents(&calls, 1);
+    assert_eq!(results[0], "ok");
+    assert!(results[1].starts_with("Not executed: at most 1 tool calls"));
+}
+
+#[tokio::test]
+async fn test_max_parallel_tool_calls_sequential() {
+    let (mut agent, calls, peak) = parallel_agent(ParallelCallOverflow::Sequential);
+
+    agent.chat("go").await.unwrap();
+
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+    assert_eq!(tool_contents(&calls, 1), ["ok", "ok", "ok"]);
+}
+
+#[tokio::test]
+async fn test_validate_tools() {
+    use crate::types::{Parameters, ToolFunction};
+
+    let defs = vec![ToolDefinition {
+        tool_type: "function".into(),
+        function: ToolFunction {
+            name: "broken".into(),
+            description: "".into(),
+            parameters: Parameters::from_object(
+                serde_json::json!({ "type": "object", "required": ["x"] })
+                    .as_object()
+                    .unwrap()
+                    .clone(),
+            ),
+        },
+    }];
+    let llm = MockProvider::new().text("done");
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm)
+        .validate_tools(true)
+        .external(defs, |_: String, _: String| async { String::new() });
+
+    let err = agent.chat("go").await.unwrap_err();
+    assert!(matches!(err, crate::Error::InvalidToolDefinition { name, .. } if name == "broken"));
+    assert!(calls.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_error_on_finish() {
+    use crate::types::FinishReason;
+    use std::collections::HashSet;
+
+    let llm = MockProvider::new()
+        .finish("filtered", FinishReason::ContentFilter)
+        .finish("filtered", FinishReason::ContentFilter);
+    let mut agent = Agent::new(llm);
+    assert_eq!(agent.chat("go").await.unwrap(), "filtered");
+
+    agent = agent.error_on_finish(HashSet::from([FinishReason::ContentFilter]));
+    let err = agent.chat("go").await.unwrap_err();
+    assert!(matches!(
+        err,
+        crate::Error::FinishReason(FinishReason::ContentFilter)
+    ));
+}
+
+#[tokio::test]
+async fn test_run_stream_cancel_closes_connection() {
+    use crate::llm::{OpenAIProvider, mock::MockServer};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let (closed_tx, closed_rx) = tokio::sync::oneshot::channel();
+    let closed_tx = std::sync::Mutex::new(Some(closed_tx));
+    let url = MockServer::spawn(move |mut stream, _| {
+        let closed_tx = closed_tx.lock().unwrap().take();
+        async move {
+            let chunk = r#"data: {"choices":[{"delta":{"content":"Hello"}}]}"#;
+            let chunk = format!("{}\n\n", chunk);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n",
+                chunk.len(),
+                chunk
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            // Never finish the stream; wait for the client to hang up
+            let mut buf = [0; 64];
+            while stream.read(&mut buf).await.unwrap_or(0) > 0 {}
+            if let Some(tx) = closed_tx {
+                tx.send(()).ok();
+            }
+        }
+    })
+    .await;
+
+    let (first_chunk_tx, first_chunk_rx) = tokio::sync::oneshot::channel::<()>();
+    let first_chunk_tx = std::sync::Mutex::new(Some(first_chunk_tx));
+    let llm = OpenAIProvider::new()
+        .base_url(url)
+        .max_retries(0)
+        .stream_callback(move |_| {
+            if let Some(tx) = first_chunk_tx.lock().unwrap().take() {
+                tx.send(()).ok();
+            }
+        });
+    let mut agent = Agent::new(llm);
+
+    let result = agent
+        .run_stream(async {
+            first_chunk_rx.await.ok();
+        })
+        .await;
+
+    assert!(matches!(result, Err(crate::Error::Cancelled)));
+    tokio::time::timeout(std::time::Duration::from_secs(5), closed_rx)
+        .await
+        .expect("connection should be closed after cancellation")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_from_messages() {
+    use crate::llm::mock::timed;
+    use crate::types::{AssistantMessage, UserMessage};
+
+    let saved = vec![
+        timed(
+            UserMessage {
+                content: "Hi".into(),
+            }
+            .into(),
+        ),
+        timed(
+            AssistantMessage {
+                content: "Hello!".into(),
+                tool_calls: None,
+                refusal: None,
+                annotations: None,
+            }
+            .into(),
+        ),
+    ];
+    let llm = MockProvider::new().text("Welcome back");
+    let calls = llm.calls();
+    let mut agent = Agent::from_messages(llm, saved);
+
+    assert_eq!(agent.history.get_all().len(), 2);
+    agent.chat("I'm back").await.unwrap();
+
+    let calls = calls.lock().unwrap();
+    assert_eq!(calls[0].0.len(), 3);
+    assert!(matches!(&calls[0].0[1], Message::Assistant(m) if m.content == "Hello!"));
+}
+
+/// Mock that requests `n` calls to `big` in one batch
+fn big_batch(n: usize) -> MockProvider {
+    let ids: Vec<_> = (0..n).map(|i| format!("call_{}", i)).collect();
+    let calls: Vec<_> = ids.iter().map(|id| (id.as_str(), "big", "{}")).collect();
+    MockProvider::new().tool_calls("", &calls)
+}
+
+/// Agent with a `big` tool returning `len` bytes
+fn big_agent(llm: MockProvider, len: usize) -> Agent {
+    let mut agent = Agent::new(llm);
+    agent.executor.add(
+        "big".into(),
+        Box::new(ClosureTool::boxed(move |_| {
+            Box::pin(async move { "x".repeat(len) })
+        })),
+    );
+    agent
+}
+
+/// Contents of the tool messages sent in the given LLM call
+fn tool_contents(calls: &MockCalls, index: usize) -> Vec<String> {
+    calls.lock().unwrap()[index]
+        .0
+        .iter()
+        .filter_map(|m| match m {
+            Message::Tool(t) => Some(t.content.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_result_budget_truncate_each() {
+    let llm = big_batch(50).text("done");
+    let calls = llm.calls();
+    let mut agent = big_agent(llm, 4000).result_budget(
+        10_000,
+        crate::tokenizer::ApproxTokenizer,
+        ResultBudgetPolicy::TruncateEach,
+    );
+
+    agent.chat("go").await.unwrap();
+
+    let results = tool_contents(&calls, 1);
+    assert_eq!(results.len(), 50);
+    for r in &results {
+        assert_eq!(
+            *r,
+            format!("{}\n[Truncated from about 1000 tokens]", "x".repeat(800))
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_result_budget_within_limit() {
+    let llm = big_batch(5).text("done");
+    let calls = llm.calls();
+    let mut agent = big_agent(llm, 400).result_budget(
+        10_000,
+        crate::tokenizer::ApproxTokenizer,
+        ResultBudgetPolicy::Error,
+    );
+
+    agent.chat("go").await.unwrap();
+
+    assert_eq!(tool_contents(&calls, 1), vec!["x".repeat(400); 5]);
+}
+
+#[tokio::test]
+async fn test_result_budget_summarize_all() {
+    let llm = big_batch(20).text("summary").text("done");
+    let calls = llm.calls();
+    let mut agent = big_agent(llm, 4000).result_budget(
+        1000,
+        crate::tokenizer::ApproxTokenizer,
+        ResultBudgetPolicy::SummarizeAll,
+    );
+
+    assert_eq!(agent.chat("go").await.unwrap(), "done");
+
+    let Message::User(prompt) = calls.lock().unwrap()[1].0[0].clone() else {
+        panic!("expected summary prompt");
+    };
+    assert!(prompt.content.contains("## big (call_19)"));
+    let results = tool_contents(&calls, 2);
+    assert_eq!(results.len(), 20);
+    assert_eq!(results[0], "summary");
+    assert!(results[1].contains("result of call 'call_0'"));
+}
+
+#[tokio::test]
+async fn test_result_budget_error() {
+    let llm = big_batch(50);
+    let mut agent = big_agent(llm, 4000).result_budget(
+        10_000,
+        crate::tokenizer::ApproxTokenizer,
+        ResultBudgetPolicy::Error,
+    );
+
+    let err = agent.chat("go").await.unwrap_err();
+    assert!(matches!(
+        err,
+        crate::Error::ToolResultsTooLarge {
+            tokens: 50_000,
+            max_tokens: 10_000
+        }
+    ));
+}
+
+#[tokio::test]
+async fn test_external_typed() {
+    let llm = MockProvider::new()
+        .tool_calls(
+            "",
+            &[
+                ("call_1", "weather", r#"{"city":"Paris"}"#),
+                ("call_2", "weather", "{}"),
+                ("call_3", "weather", "not json"),
+            ],
+        )
+        .text("done");
+    let calls = llm.calls();
+    let defs = vec![ToolDefinition {
+        tool_type: "function".into(),
+        function: crate::types::ToolFunction {
+            name: "weather".into(),
+            description: "Get weather".into(),
+            parameters: crate::types::Parameters::from_object(Default::default()),
+        },
+    }];
+    let mut agent = Agent::new(llm).external_typed(defs, |name, args| async move {
+        let city = args["city"].as_str().ok_or("missing city")?;
+        Ok(format!("{}: sunny in {}", name, city))
+    });
+
+    agent.chat("go").await.unwrap();
+
+    let results = tool_contents(&calls, 1);
+    assert_eq!(results[0], "weather: sunny in Paris");
+    assert_eq!(results[1], "Error: missing city");
+    assert!(results[2].starts_with("expected ident"));
+}
+
+#[tokio::test]
+async fn test_on_error_retry() {
+    let llm = MockProvider::new()
+        .fail(crate::Error::ApiError {
+            status: 400,
+            body: "context_length_exceeded".into(),
+        })
+        .text("done");
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm)
+        .system("system")
+        .on_error(|err, history| match err {
+            crate::Error::ApiError { body, .. } if body.contains("context_length_exceeded") => {
+                history.remove(1);
+                ErrorAction::Retry
+            }
+            _ => ErrorAction::Fail,
+        });
+    agent.history.add(crate::llm::mock::timed(
+        crate::types::UserMessage {
+            content: "old".into(),
+        }
+        .into(),
+    ));
+
+    assert_eq!(agent.chat("new").await.unwrap(), "done");
+
+    let calls = calls.lock().unwrap();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0].0.len(), 3);
+    assert_eq!(calls[1].0.len(), 2);
+    let Message::User(user) = &calls[1].0[1] else {
+        panic!("expected user message");
+    };
+    assert_eq!(user.content, "new");
+}
+
+#[tokio::test]
+async fn test_on_error_fail() {
+    let llm = MockProvider::new().fail(crate::Error::Custom("boom".into()));
+    let mut agent = Agent::new(llm).on_error(|_, _| ErrorAction::Fail);
+
+    assert_eq!(agent.chat("go").await.unwrap_err().to_string(), "boom");
+}
+
+#[tokio::test]
+async fn test_scripted_executor_multi_round() {
+    let llm = MockProvider::new()
+        .tool_calls(
+            "",
+            &[
+                ("call_1", "search", r#"{"q":"a"}"#),
+                ("call_2", "search", r#"{"q":"b"}"#),
+            ],
+        )
+        .tool_calls("", &[("call_3", "search", "{}"), ("call_4", "fetch", "{}")])
+        .tool_calls("", &[("call_5", "unknown", "{}")])
+        .text("done");
+    let calls = llm.calls();
+    let executor = crate::tool::ScriptedExecutor::new()
+        .result("search", "result a")
+        .result("search", "result b")
+        .fallback("nothing");
+    let mut agent = Agent::new(llm).executor(executor);
+    agent.executor.add(
+        "search".into(),
+        Box::new(ClosureTool::boxed(|_| {
+            Box::pin(async { "real search".into() })
+        })),
+    );
+
+    assert_eq!(agent.chat("go").await.unwrap(), "done");
+
+    assert_eq!(tool_contents(&calls, 1), vec!["result a", "result b"]);
+    assert_eq!(
+        tool_contents(&calls, 2),
+        vec!["result a", "result b", "real search", "nothing"]
+    );
+    assert_eq!(tool_contents(&calls, 3).last().unwrap(), "nothing");
+}
+
+#[tokio::test]
+async fn test_raw_tool() {
+    let llm = MockProvider::new()
+        .tool_calls(
+            "",
+            &[
+                ("call_1", "echo", r#"{"input":"1 + \"2\" * {3}"}"#),
+                ("call_2", "echo", "not json at all"),
+            ],
+        )
+        .text("done");
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm).raw_tool("echo", "Echo the input", |input| async move {
+        format!("got: {}", input)
+    });
+
+    assert!(agent.tools()[0].validate().is_ok());
+    agent.chat("go").await.unwrap();
+
+    assert_eq!(
+        tool_contents(&calls, 1),
+        vec![r#"got: 1 + "2" * {3}"#, "got: not json at all"]
+    );
+}
+
+#[tokio::test]
+async fn test_import_tool_defs() {
+    let llm = MockProvider::new()
+        .tool_calls("", &[("call_1", "now", "{}")])
+        .text("done");
+    let calls = llm.calls();
+    let mut executor = crate::tool::SequentialExecutor::new();
+    executor.add(
+        "now".into(),
+        Box::new(ClosureTool::boxed(|_| Box::pin(async { "12:00".into() }))),
+    );
+    let def = ToolDefinition {
+        tool_type: "function".into(),
+        function: crate::types::ToolFunction {
+            name: "now".into(),
+            description: "Get the current time".into(),
+            parameters: crate::types::Parameters::from_object(Default::default()),
+        },
+    };
+    let mut agent = Agent::new(llm)
+        .executor(executor)
+        .import_tool_defs(vec![def]);
+
+    assert_eq!(agent.tools().len(), 1);
+    agent.chat("go").await.unwrap();
+
+    assert_eq!(calls.lock().unwrap()[0].1[0].function.name, "now");
+    assert_eq!(tool_contents(&calls, 1), vec!["12:00"]);
+}
+
+#[tokio::test]
+async fn test_refusal() {
+    use crate::types::{AssistantMessage, LLMResponse};
+
+    let llm = MockProvider::new().respond(LLMResponse {
+        message: AssistantMessage {
+            content: String::new(),
+            tool_calls: None,
+            refusal: Some("I can't help with that.".into()),
+            annotations: None,
+        },
+        finish_reason: FinishReason::Stop,
+        logprobs: None,
+        usage: None,
+    });
+    let mut agent = Agent::new(llm);
+
+    let err = agent.chat("go").await.unwrap_err();
+    assert!(matches!(err, crate::Error::Refusal(r) if r == "I can't help with that."));
+    // The refusal is kept in history
+    assert_eq!(agent.history.get_all().len(), 2);
+}
+
+#[tokio::test]
+async fn test_example() {
+    let llm = MockProvider::new().text("positive");
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm)
+        .system("Classify sentiment")
+        .example("I love it", "positive")
+        .example("Awful", "negative");
+
+    agent.chat("Great!").await.unwrap();
+
+    let calls = calls.lock().unwrap();
+    let contents: Vec<_> = calls[0]
+        .0
+        .iter()
+        .map(|m| match m {
+            Message::System(m) => format!("system: {}", m.content),
+            Message::User(m) => format!("user: {}", m.content),
+            Message::Assistant(m) => format!("assistant: {}", m.content),
+            _ => panic!("unexpected message"),
+        })
+        .collect();
+    assert_eq!(
+        contents,
+        vec![
+            "system: Classify sentiment",
+            "user: I love it",
+            "assistant: positive",
+            "user: Awful",
+            "assistant: negative",
+            "user: Great!",
+        ]
+    );
+}
+
+/// Get the weather of a city
+#[tiny_loop_macros::tool_internal]
+async fn get_weather(
+    /// City name
+    city: String,
+) -> String {
+    format!("Sunny in {}", city)
+}
+
+#[tokio::test]
+async fn test_schema_errors() {
+    let script = || {
+        MockProvider::new()
+            .tool_calls("", &[("call_1", "get_weather", r#"{"town":"Paris"}"#)])
+            .text("done")
+    };
+
+    let llm = script();
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm).tool(get_weather);
+    agent.chat("go").await.unwrap();
+    assert_eq!(
+        tool_contents(&calls, 1),
+        vec!["missing field `city` at line 1 column 16"]
+    );
+
+    // The flag also applies to tools registered before it is set
+    let llm = script();
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm).tool(get_weather).schema_errors(true);
+    agent.chat("go").await.unwrap();
+    let result = &tool_contents(&calls, 1)[0];
+    assert!(result.starts_with(
+        "Invalid arguments for tool 'get_weather': missing field `city` at line 1 column 16"
+    ));
+    assert!(result.contains(r#""required": ["#));
+    assert!(result.contains(r#""description": "City name""#));
+}
+
+#[tokio::test]
+async fn test_retry_budget() {
+    use crate::llm::{OpenAIProvider, mock::MockServer};
+    use tokio::io::AsyncWriteExt;
+
+    // Fails every request except the second one, which asks for a tool call
+    let requests = Arc::new(std::sync::Mutex::new(0));
+    let r = requests.clone();
+    let url = MockServer::spawn(move |mut stream, _| {
+        let request = {
+            let mut requests = r.lock().unwrap();
+            *requests += 1;
+            *requests
+        };
+        async move {
+            let response = if request == 2 {
+                let body = r#"{"choices":[{"message":{"role":"assistant","content":"","tool_calls":[{"id":"call_1","type":"function","function":{"name":"missing","arguments":"{}"}}]},"finish_reason":"tool_calls"}]}"#;
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            } else {
+                "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n".into()
+            };
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    })
+    .await;
+
+    let llm = OpenAIProvider::new()
+        .base_url(url)
+        .max_retries(3)
+        .retry_delay(0);
+    let mut agent = Agent::new(llm).retry_budget(2);
+
+    // 1 retry in the first step, 1 in the second, then the budget is exhausted
+    let err = agent.chat("go").await.unwrap_err();
+    assert!(matches!(err, crate::Error::ApiError { status: 500, .. }));
+    assert_eq!(*requests.lock().unwrap(), 4);
+
+    // The budget is reset for the next run
+    *requests.lock().unwrap() = 2;
+    agent.chat("again").await.unwrap_err();
+    assert_eq!(*requests.lock().unwrap(), 5);
+}
+
+#[tokio::test]
+async fn test_shutdown_recovers_interrupted_results() {
+    let llm =
+        MockProvider::new().tool_calls("", &[("call_1", "fast", "{}"), ("call_2", "hang", "{}")]);
+    let mut agent = Agent::new(llm);
+    agent.executor.add(
+        "fast".into(),
+        Box::new(ClosureTool::boxed(|_| Box::pin(async { "written".into() }))),
+    );
+    agent.executor.add(
+        "hang".into(),
+        Box::new(ClosureTool::boxed(|_| Box::pin(futures::future::pending()))),
+    );
+
+    let result = agent
+        .run_stream(tokio::time::sleep(std::time::Duration::from_millis(50)))
+        .await;
+    assert!(matches!(result, Err(crate::Error::Cancelled)));
+    let len = agent.history.get_all().len();
+
+    assert_eq!(agent.shutdown().await, 1);
+    let messages = agent.history.get_all();
+    assert_eq!(messages.len(), len + 1);
+    let Message::Tool(result) = &messages[len].message else {
+        panic!("expected a tool message");
+    };
+    assert_eq!(result.tool_call_id, "call_1");
+    assert_eq!(result.content, "written");
+}
+
+#[tokio::test]
+async fn test_apply_tool_catalog() {
+    let llm = MockProvider::new().text("done");
+    let calls = llm.calls();
+    let catalog = serde_json::from_str(
+        r#"{
+            "get_weather": {
+                "description": "Obtenir la météo",
+                "parameters": { "city": "Nom de la ville", "unknown": "ignored" }
+            }
+        }"#,
+    )
+    .unwrap();
+    let mut agent = Agent::new(llm)
+        .apply_tool_catalog(catalog)
+        .tool(get_weather);
+    agent.chat("go").await.unwrap();
+
+    let sent = calls.lock().unwrap()[0].1.clone();
+    let sent = serde_json::to_value(&sent[0]).unwrap();
+    assert_eq!(sent["function"]["name"], "get_weather");
+    assert_eq!(sent["function"]["description"], "Obtenir la météo");
+    let properties = &sent["function"]["parameters"]["properties"];
+    assert_eq!(properties["city"]["description"], "Nom de la ville");
+    assert!(properties.get("unknown").is_none());
+    // The registered definitions are unchanged
+    assert_eq!(
+        agent.tools()[0].function.description,
+        "Get the weather of a city"
+    );
+}
+
+#[tokio::test]
+async fn test_send_tools_first_only() {
+    let script = || {
+        MockProvider::new()
+            .tool_calls("", &[("call_1", "get_weather", r#"{"city":"Paris"}"#)])
+            .text("done")
+    };
+
+    let llm = script();
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm).tool(get_weather);
+    agent.chat("go").await.unwrap();
+    assert_eq!(calls.lock().unwrap()[1].1.len(), 1);
+
+    let llm = script();
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm)
+        .tool(get_weather)
+        .send_tools_first_only(true);
+    agent.chat("go").await.unwrap();
+    let calls = calls.lock().unwrap();
+    assert_eq!(calls[0].1.len(), 1);
+    assert!(calls[1].1.is_empty());
+}
+
+#[tokio::test]
+async fn test_tool_call_stats() {
+    let llm = MockProvider::new()
+        .tool_calls(
+            "",
+            &[
+                ("call_1", "search", "{}"),
+                ("call_2", "search", "{}"),
+                ("call_3", "fetch", "{}"),
+            ],
+        )
+        .tool_calls("", &[("call_4", "fetch", "{}")])
+        .text("first")
+        .tool_calls("", &[("call_5", "search", "{}")])
+        .text("second")
+        .text("third");
+    let mut agent = Agent::new(llm);
+
+    agent.chat("one").await.unwrap();
+    agent.chat("two").await.unwrap();
+    assert_eq!(
+        agent.tool_call_stats(),
+        HashMap::from([("search".into(), 3), ("fetch".into(), 2)])
+    );
+    assert_eq!(
+        agent.last_turn_tool_call_stats(),
+        HashMap::from([("search".into(), 1)])
+    );
+
+    agent.chat("three").await.unwrap();
+    assert!(agent.last_turn_tool_call_stats().is_empty());
+    assert_eq!(agent.tool_call_stats().values().sum::<usize>(), 5);
+}
+
+#[tokio::test]
+async fn test_interrupt() {
+    let llm = MockProvider::new()
+        .tool_calls("", &[("call_1", "work", "{}")])
+        .text("done");
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm);
+    let handle = agent.interrupt_handle();
+    agent.executor.add(
+        "work".into(),
+        Box::new(ClosureTool::boxed(move |_| {
+            handle.interrupt("Stop, focus on X instead");
+            Box::pin(async { "working".into() })
+        })),
+    );
+
+    assert_eq!(agent.chat("go").await.unwrap(), "done");
+
+    // Added after the tool result, before the second LLM call
+    let messages = calls.lock().unwrap()[1].0.clone();
+    assert!(matches!(&messages[2], Message::Tool(m) if m.content == "working"));
+    assert!(matches!(&messages[3], Message::User(m) if m.content == "Stop, focus on X instead"));
+    assert_eq!(messages.len(), 4);
+}
+
+#[tokio::test]
+async fn test_duplicate_call_ids() {
+    let script = || {
+        MockProvider::new()
+            .tool_calls(
+                "",
+                &[
+                    ("call_1", "get_weather", r#"{"city":"Paris"}"#),
+                    ("call_1", "get_weather", r#"{"city":"Tokyo"}"#),
+                    ("call_1_2", "get_weather", r#"{"city":"Rome"}"#),
+                ],
+            )
+            .text("done")
+    };
+    let ids = |calls: &MockCalls| -> (Vec<String>, Vec<String>) {
+        let messages = calls.lock().unwrap()[1].0.clone();
+        let Message::Assistant(m) = &messages[1] else {
+            panic!("expected an assistant message");
+        };
+        let call_ids = m
+            .tool_calls
+            .iter()
+            .flatten()
+            .map(|c| c.id.clone())
+            .collect();
+        let result_ids = messages[2..]
+            .iter()
+            .filter_map(|m| match m {
+                Message::Tool(t) => Some(t.tool_call_id.clone()),
+                _ => None,
+            })
+            .collect();
+        (call_ids, result_ids)
+    };
+
+    let llm = script();
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm).tool(get_weather);
+    agent.chat("go").await.unwrap();
+    let (call_ids, result_ids) = ids(&calls);
+    assert_eq!(call_ids, ["call_1", "call_1_3", "call_1_2"]);
+    assert_eq!(result_ids, call_ids);
+    assert_eq!(
+        tool_contents(&calls, 1),
+        vec!["Sunny in Paris", "Sunny in Tokyo", "Sunny in Rome"]
+    );
+
+    let llm = script();
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm)
+        .tool(get_weather)
+        .duplicate_call_ids(DuplicateCallIds::Drop);
+    agent.chat("go").await.unwrap();
+    let (call_ids, result_ids) = ids(&calls);
+    assert_eq!(call_ids, ["call_1", "call_1_2"]);
+    assert_eq!(result_ids, call_ids);
+}
+
+#[tokio::test]
+async fn test_after_tool_exec() {
+    let llm = MockProvider::new()
+        .tool_calls("", &[("call_1", "env", "{}")])
+        .text("done");
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm).after_tool_exec(|results| {
+        for r in results {
+            r.tool_message.content = r
+                .tool_message
+                .content
+                .split_whitespace()
+                .map(|word| {
+                    if word.starts_with("sk-") {
+                        "[REDACTED]"
+                    } else {
+                        word
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+        }
+    });
+    agent.executor.add(
+        "env".into(),
+        Box::new(ClosureTool::boxed(|_| {
+            Box::pin(async { "API_KEY= sk-abc123 USER= alice".into() })
+        })),
+    );
+
+    agent.chat("go").await.unwrap();
+    assert_eq!(
+        tool_contents(&calls, 1),
+        vec!["API_KEY= [REDACTED] USER= alice"]
+    );
+    let history = agent.history.get_all();
+    assert!(matches!(&history[2].message, Message::Tool(m) if !m.content.contains("sk-")));
+}
+
+#[tokio::test]
+async fn test_on_llm_response() {
+    let llm = MockProvider::new()
+        .tool_calls("", &[("call_1", "missing", "{}")])
+        .text("done")
+        .usage(crate::types::Usage {
+            prompt_tokens: 5,
+            completion_tokens: 1,
+            total_tokens: 6,
+        });
+    let responses = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let r = responses.clone();
+    let mut agent = Agent::new(llm).on_llm_response(move |response| {
+        r.lock().unwrap().push(response.clone());
+    });
+
+    agent.chat("go").await.unwrap();
+    let responses = responses.lock().unwrap();
+    assert_eq!(responses.len(), 2);
+    assert_eq!(responses[0].finish_reason, FinishReason::ToolCalls);
+    assert_eq!(
+        responses[0].message.tool_calls.as_ref().unwrap()[0]
+            .function
+            .name,
+        "missing"
+    );
+    assert_eq!(responses[1].message.content, "done");
+    assert_eq!(responses[1].usage.unwrap().total_tokens, 6);
+}
+
+#[tokio::test]
+async fn test_on_assistant_complete_before_tools() {
+    let llm = MockProvider::new()
+        .tool_calls("Checking", &[("call_1", "check", "{}")])
+        .text("done");
+    let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let e = events.clone();
+    let mut agent = Agent::new(llm).on_assistant_complete(move |message| {
+        e.lock()
+            .unwrap()
+            .push(format!("assistant: {}", message.content));
+    });
+    let e = events.clone();
+    agent.executor.add(
+        "check".into(),
+        Box::new(ClosureTool::boxed(move |_| {
+            e.lock().unwrap().push("tool".into());
+            Box::pin(async { "ok".into() })
+        })),
+    );
+
+    agent.chat("go").await.unwrap();
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec!["assistant: Checking", "tool", "assistant: done"]
+    );
+}
+
+#[tokio::test]
+async fn test_max_steps() {
+    let llm = MockProvider::new()
+        .tool_calls("", &[("call_1", "missing", "{}")])
+        .tool_calls("", &[("call_2", "missing", "{}")])
+        .text("done")
+        .text("again");
+    let mut agent = Agent::new(llm).max_steps(2);
+
+    let result = agent.chat("go").await;
+    assert!(matches!(
+        result,
+        Err(crate::Error::MaxSteps {
+            max_steps: 2,
+            partial: None
+        })
+    ));
+    // The count resets for every run
+    assert_eq!(agent.run().await.unwrap(), "done");
+}
+
+#[tokio::test]
+async fn test_max_steps_partial() {
+    let llm = MockProvider::new()
+        .tool_calls("Found 3 flights so far.", &[("call_1", "missing", "{}")])
+        .tool_calls("", &[("call_2", "missing", "{}")]);
+    let mut agent = Agent::new(llm).max_steps(2);
+
+    match agent.chat("Find flights").await {
+        Err(crate::Error::MaxSteps { max_steps, partial }) => {
+            assert_eq!(max_steps, 2);
+            assert_eq!(partial.as_deref(), Some("Found 3 flights so far."));
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_with_config() {
+    let config: AgentConfig = serde_json::from_value(serde_json::json!({
+        "system": "Be brief",
+        "max_steps": 1,
+        "duplicate_call_ids": "drop",
+    }))
+    .unwrap();
+    let llm = MockProvider::new()
+        .tool_calls(
+            "",
+            &[("call_1", "missing", "{}"), ("call_1", "missing", "{}")],
+        )
+        .text("done");
+    let calls = llm.calls();
+    let mut agent = Agent::with_config(llm, config);
+
+    assert!(matches!(
+        agent.chat("go").await,
+        Err(crate::Error::MaxSteps { max_steps: 1, .. })
+    ));
+    let calls = calls.lock().unwrap();
+    assert!(matches!(&calls[0].0[0], Message::System(m) if m.content == "Be brief"));
+    // The duplicate call was dropped
+    assert_eq!(agent.history.get_all().len(), 4);
+
+    let unknown = serde_json::from_value::<AgentConfig>(serde_json::json!({ "max_step": 1 }));
+    assert!(unknown.is_err());
+}
+
+#[tokio::test]
+async fn test_chat_with_context() {
+    use crate::types::SystemMessage;
+
+    let llm = MockProvider::new()
+        .tool_calls("", &[("call_1", "missing", "{}")])
+        .text("It's in the docs")
+        .text("Anything else?");
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm).system("Be brief");
+    let docs = SystemMessage {
+        content: "Docs: press reset".into(),
+    };
+    let is_docs = |m: &Message| matches!(m, Message::System(m) if m.content == docs.content);
+
+    let answer = agent
+        .chat_with_context("How to reset?", vec![docs.clone().into()])
+        .await
+        .unwrap();
+    assert_eq!(answer, "It's in the docs");
+    agent.chat("Thanks").await.unwrap();
+
+    let calls = calls.lock().unwrap();
+    // Sent before the prompt in every call of the turn
+    for call in &calls[..2] {
+        assert!(is_docs(&call.0[1]));
+        assert!(matches!(&call.0[2], Message::User(m) if m.content == "How to reset?"));
+    }
+    assert!(!calls[2].0.iter().any(is_docs));
+    assert!(
+        !agent
+            .history
+            .get_all()
+            .iter()
+            .any(|tm| is_docs(&tm.message))
+    );
+    assert_eq!(agent.history.get_all().len(), 7);
+}
+
+#[tokio::test]
+async fn test_trim_responses() {
+    let llm = MockProvider::new()
+        .tool_calls("  Checking \n", &[("call_1", "missing", "{}")])
+        .text("\n Done. \n");
+    let mut agent = Agent::new(llm).trim_responses(true);
+
+    assert_eq!(agent.chat("go").await.unwrap(), "Done.");
+    let Message::Assistant(m) = &agent.history.get_all().last().unwrap().message else {
+        panic!("expected assistant message");
+    };
+    assert_eq!(m.content, "\n Done. \n");
+
+    let llm = MockProvider::new()
+        .tool_calls("  Checking \n", &[("call_1", "missing", "{}")])
+        .text("\n Done. \n");
+    let mut agent = Agent::new(llm).trim_responses(true);
+    agent.history.add(crate::llm::mock::timed(
+        crate::types::UserMessage {
+            content: "go".into(),
+        }
+        .into(),
+    ));
+    assert_eq!(agent.run_joined().await.unwrap(), "Checking\n\nDone.");
+}
+
+#[tokio::test]
+async fn test_auto_trim_on_overflow() {
+    let overflow = || crate::Error::ApiError {
+        status: 400,
+        body: r#"{"error":{"message":"Too long","code":"context_length_exceeded"}}"#.into(),
+    };
+    let llm = MockProvider::new()
+        .text("One")
+        .fail(overflow())
+        .text("Two")
+        .fail(overflow())
+        .fail(overflow());
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm)
+        .system("Be brief")
+        .auto_trim_on_overflow(true);
+
+    agent.chat("First").await.unwrap();
+    assert_eq!(agent.chat("Second").await.unwrap(), "Two");
+    {
+        let calls = calls.lock().unwrap();
+        let sent = calls[2].0.iter().map(|m| match m {
+            Message::System(m) => m.content.as_str(),
+            Message::User(m) => m.content.as_str(),
+            Message::Assistant(m) => m.content.as_str(),
+            _ => "",
+        });
+        assert_eq!(sent.collect::<Vec<_>>(), vec!["Be brief", "Second"]);
+    }
+
+    // Retried only once
+    let result = agent.chat("Third").await;
+    assert_eq!(
+        result.unwrap_err().api_error_code().unwrap(),
+        "context_length_exceeded"
+    );
+}
+
+#[tokio::test]
+async fn test_tool_alias() {
+    let llm = MockProvider::new()
+        .tool_calls(
+            "",
+            &[
+                ("call_1", "weather", r#"{"city":"Paris"}"#),
+                ("call_2", "get_weather", r#"{"city":"Rome"}"#),
+            ],
+        )
+        .text("done");
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm)
+        .tool(get_weather)
+        .tool_alias("get_weather", "weather");
+
+    agent.chat("go").await.unwrap();
+    assert_eq!(
+        tool_contents(&calls, 1),
+        vec!["Sunny in Paris", "Sunny in Rome"]
+    );
+    // Only the canonical definition is sent
+    let tools = &calls.lock().unwrap()[0].1;
+    assert_eq!(tools.len(), 1);
+    assert_eq!(tools[0].function.name, "get_weather");
+}
+
+#[tokio::test]
+async fn test_tool_alias_call_with_meta() {
+    use crate::tool::{SequentialExecutor, Tool};
+
+    struct CalledAs;
+
+    #[async_trait::async_trait]
+    impl Tool for CalledAs {
+        async fn call(&self, _args: String) -> String {
+            unreachable!()
+        }
+
+        async fn call_with_meta(&self, call: &ToolCall) -> String {
+            format!("called as {}", call.function.name)
+        }
+    }
+
+    let script = || {
+        MockProvider::new()
+            .tool_calls("", &[("call_1", "lookup", "{}"), ("call_2", "find", "{}")])
+            .tool_calls("", &[("call_3", "find", "{}")])
+            .text("done")
+    };
+    let llm = script();
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm)
+        .tool_alias("lookup", "find")
+        .repeat_call_limit(2);
+    agent.executor.add("lookup".into(), Box::new(CalledAs));
+
+    agent.chat("go").await.unwrap();
+    let results = tool_contents(&calls, 2);
+    assert_eq!(results[..2], ["called as lookup", "called as find"]);
+    // Repeats are counted by the canonical name
+    assert!(results[2].starts_with("You already called 'lookup'"));
+
+    // Aliases are registered in executors set later
+    let mut executor = SequentialExecutor::new();
+    executor.add("lookup".into(), Box::new(CalledAs));
+    let llm = script();
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm)
+        .tool_alias("lookup", "find")
+        .executor(executor);
+
+    agent.chat("go").await.unwrap();
+    assert_eq!(
+        tool_contents(&calls, 2),
+        ["called as lookup", "called as find", "called as find"]
+    );
+}
+
+#[tokio::test]
+async fn test_noop_provider_step_timing() {
+    use crate::{llm::mock::NoopProvider, tool::ScriptedExecutor};
+
+    let timings = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let t = timings.clone();
+    let mut agent = Agent::new(NoopProvider::new("Done").tool_calls("search", "{}", 3))
+        .executor(ScriptedExecutor::new())
+        .on_step_timing(move |timing| t.lock().unwrap().push(timing));
+
+    // The script repeats for every run
+    for _ in 0..2 {
+        assert_eq!(agent.chat("go").await.unwrap(), "Done");
+    }
+    assert_eq!(agent.history.get_all().len(), 2 * (1 + 3 * 2 + 1));
+    let timings = timings.lock().unwrap();
+    assert_eq!(timings.len(), 8);
+    assert!(timings.iter().all(|t| t.total >= t.tools));
+}
+
+#[tokio::test]
+async fn test_last_tool_results() {
+    let llm = MockProvider::new()
+        .tool_calls(
+            "",
+            &[
+                ("call_1", "get_weather", r#"{"city":"Paris"}"#),
+                ("call_2", "missing", "{}"),
+            ],
+        )
+        .text("done");
+    let mut agent = Agent::new(llm).tool(get_weather).system("Be brief");
+    agent.history.add(crate::llm::mock::timed(
+        crate::types::UserMessage {
+            content: "go".into(),
+        }
+        .into(),
+    ));
+    assert!(agent.last_tool_results().is_empty());
+
+    assert_eq!(agent.step().await.unwrap(), None);
+    let results = agent.last_tool_results();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].tool_message.tool_call_id, "call_1");
+    assert_eq!(results[0].tool_message.content, "Sunny in Paris");
+    assert_eq!(results[1].tool_message.tool_call_id, "call_2");
+
+    assert_eq!(agent.step().await.unwrap().as_deref(), Some("done"));
+    assert!(agent.last_tool_results().is_empty());
+}
+
+#[tokio::test]
+async fn test_text_tool_calls() {
+    let llm = MockProvider::new()
+        .text("Let me check.\n```json\n{\"tool\": \"get_weather\", \"args\": {\"city\": \"Paris\"}}\n```")
+        .text("It's sunny in Paris.");
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm)
+        .tool(get_weather)
+        .text_tool_calls(crate::tool::parse_fenced_tool_calls);
+
+    assert_eq!(
+        agent.chat("Weather?").await.unwrap(),
+        "It's sunny in Paris."
+    );
+    assert_eq!(tool_contents(&calls, 1), vec!["Sunny in Paris"]);
+    let Message::Assistant(m) = &agent.history.get_all()[1].message else {
+        panic!("expected assistant message");
+    };
+    let tool_calls = m.tool_calls.as_ref().unwrap();
+    assert_eq!(tool_calls[0].function.name, "get_weather");
+
+    // Off by default
+    let llm = MockProvider::new().text("```json\n{\"tool\": \"get_weather\"}\n```");
+    let mut agent = Agent::new(llm).tool(get_weather);
+    assert!(
+        agent
+            .chat("Weather?")
+            .await
+            .unwrap()
+            .contains("get_weather")
+    );
+}
+
+#[tokio::test]
+async fn test_summarize() {
+    let mut agent = Agent::new(MockProvider::new().text("Kyoto in spring")).system("Be brief");
+    agent.chat("Where should I go in Japan?").await.unwrap();
+
+    let mut summarizer = MockProvider::new().text("The user plans a trip to Kyoto.");
+    let calls = summarizer.calls();
+    let summary = agent.summarize(&mut summarizer).await.unwrap();
+
+    assert_eq!(summary, "The user plans a trip to Kyoto.");
+    assert_eq!(agent.history.get_all().len(), 3);
+    let calls = calls.lock().unwrap();
+    let (messages, tools) = &calls[0];
+    assert_eq!(messages.len(), 4);
+    assert!(matches!(&messages[3], Message::User(m) if m.content.starts_with("Summarize")));
+    assert!(tools.is_empty());
+}
+
+#[tokio::test]
+async fn test_request_id() {
+    use crate::llm::{OpenAIProvider, mock::MockServer};
+    use std::sync::Mutex;
+    use tokio::io::AsyncWriteExt;
+    use tracing::{field::Field, span};
+
+    /// Record the fields of new spans
+    struct SpanFields(Arc<Mutex<Vec<(String, String)>>>);
+
+    impl tracing::field::Visit for SpanFields {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            let mut fields = self.0.lock().unwrap();
+            fields.push((field.name().into(), value.into()));
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            let mut fields = self.0.lock().unwrap();
+            fields.push((field.name().into(), format!("{:?}", value)));
+        }
+    }
+
+    impl tracing::Subscriber for SpanFields {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+            attrs.record(&mut SpanFields(self.0.clone()));
+            span::Id::from_u64(1)
+        }
+        fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+        fn event(&self, _: &tracing::Event<'_>) {}
+        fn enter(&self, _: &span::Id) {}
+        fn exit(&self, _: &span::Id) {}
+    }
+
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let r = requests.clone();
+    let url = MockServer::spawn(move |mut stream, request| {
+        r.lock().unwrap().push(request);
+        async move {
+            let body = r#"{"choices":[{"message":{"role":"assistant","content":"Hi"},"finish_reason":"stop"}]}"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    })
+    .await;
+
+    let fields = Arc::new(Mutex::new(Vec::new()));
+    let _guard = tracing::subscriber::set_default(SpanFields(fields.clone()));
+    let mut agent = Agent::new(OpenAIProvider::new().base_url(url)).with_request_id("req-42");
+
+    assert_eq!(agent.chat("Hello").await.unwrap(), "Hi");
+    let request = requests.lock().unwrap()[0].to_lowercase();
+    assert!(request.contains("x-request-id: req-42\r\n"));
+    assert!(
+        fields
+            .lock()
+            .unwrap()
+            .contains(&("request_id".into(), "req-42".into()))
+    );
+}
+
+#[tokio::test]
+async fn test_recall_tool() {
+    let llm = MockProvider::new()
+        .text("Noted, your locker code is 4512.")
+        .text("Sure.")
+        .tool_calls("", &[("call_1", "recall", r#"{"query":"locker"}"#)])
+        .text("Your locker code is 4512.");
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm).recall_tool();
+
+    agent.chat("My locker code is 4512").await.unwrap();
+    agent.chat("Let's talk about something else").await.unwrap();
+    agent.chat("What was my locker code?").await.unwrap();
+
+    assert_eq!(calls.lock().unwrap()[0].1[0].function.name, "recall");
+    assert_eq!(
+        tool_contents(&calls, 3),
+        vec![
+            "#0 user: My locker code is 4512\n\
+             #1 assistant: Noted, your locker code is 4512.\n\
+             #4 user: What was my locker code?"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_recall_keeps_call_order() {
+    let llm = MockProvider::new()
+        .tool_calls(
+            "",
+            &[
+                ("call_1", "get_weather", r#"{"city":"Paris"}"#),
+                ("call_2", "recall", "{}"),
+                ("call_3", "get_weather", r#"{"city":"Oslo"}"#),
+            ],
+        )
+        .text("done");
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm).recall_tool().tool(get_weather);
+
+    agent.chat("go").await.unwrap();
+
+    assert_eq!(tool_ids(&calls, 1), ["call_1", "call_2", "call_3"]);
+    assert_eq!(tool_contents(&calls, 1)[1], "#0 user: go");
+}
+
+#[tokio::test]
+async fn test_external_batch() {
+    use crate::types::{Parameters, ToolFunction, ToolMessage};
+
+    let defs = vec![ToolDefinition {
+        tool_type: "function".into(),
+        function: ToolFunction {
+            name: "lookup".into(),
+            description: "Look up a key".into(),
+            parameters: Parameters::from_object(serde_json::Map::new()),
+        },
+    }];
+    let batches = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let b = batches.clone();
+    let llm = MockProvider::new()
+        .tool_calls(
+            "",
+            &[
+                ("call_1", "lookup", r#"{"key":"a"}"#),
+                ("call_2", "lookup", r#"{"key":"b"}"#),
+                ("call_3", "lookup", r#"{"key":"c"}"#),
+            ],
+        )
+        .text("done");
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm).external_batch(defs, move |calls: Vec<ToolCall>| {
+        b.lock().unwrap().push(calls.len());
+        async move {
+            calls
+                .into_iter()
+                .map(|call| ToolMessage {
+                    content: format!("value of {}", call.function.arguments),
+                    tool_call_id: call.id,
+                })
+                .collect()
+        }
+    });
+
+    assert_eq!(agent.run().await.unwrap(), "done");
+    assert_eq!(*batches.lock().unwrap(), vec![3]);
+    assert_eq!(
+        tool_contents(&calls, 1),
+        vec![
+            r#"value of {"key":"a"}"#,
+            r#"value of {"key":"b"}"#,
+            r#"value of {"key":"c"}"#
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_external_batch_sequential() {
+    use crate::types::{Parameters, ToolFunction, ToolMessage};
+
+    let defs = vec![ToolDefinition {
+        tool_type: "function".into(),
+        function: ToolFunction {
+            name: "lookup".into(),
+            description: "Look up a key".into(),
+            parameters: Parameters::from_object(serde_json::Map::new()),
+        },
+    }];
+    let llm = MockProvider::new()
+        .tool_calls(
+            "",
+            &[
+                ("call_1", "lookup", r#"{"key":"a"}"#),
+                ("call_2", "lookup", r#"{"key":"b"}"#),
+            ],
+        )
+        .text("done");
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm)
+        .executor(crate::tool::SequentialExecutor::new())
+        .external_batch(defs, |calls: Vec<ToolCall>| async move {
+            calls
+                .into_iter()
+                .map(|call| ToolMessage {
+                    content: format!("id {}", call.id),
+                    tool_call_id: call.id,
+                })
+                .collect()
+        });
+
+    assert_eq!(agent.run().await.unwrap(), "done");
+    assert_eq!(tool_contents(&calls, 1), vec!["id call_1", "id call_2"]);
+}
+
+#[tokio::test]
+async fn test_tool_call_finish_reasons() {
+    use crate::{llm::mock::tool_call, types::AssistantMessage};
+
+    let llm = MockProvider::new()
+        .respond(LLMResponse {
+            message: AssistantMessage {
+                content: String::new(),
+                tool_calls: Some(vec![tool_call(
+                    "call_1",
+                    "get_weather",
+                    r#"{"city":"Paris"}"#,
+                )]),
+                refusal: None,
+                annotations: None,
+            },
+            finish_reason: FinishReason::Custom("tool_use".into()),
+            logprobs: None,
+            usage: None,
+        })
+        .text("Sunny.");
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm)
+        .tool(get_weather)
+        .tool_call_finish_reasons(["tool_use"]);
+
+    assert_eq!(agent.chat("Weather in Paris?").await.unwrap(), "Sunny.");
+    assert_eq!(tool_contents(&calls, 1), vec!["Sunny in Paris"]);
+}
+
+#[tokio::test]
+async fn test_chat_with_meta() {
+    let llm = MockProvider::new().text("Hi");
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm);
+
+    let meta = serde_json::json!({ "user_id": "u_123" });
+    agent.chat_with_meta("Hello", meta.clone()).await.unwrap();
+
+    let history = agent.history.get_all();
+    assert_eq!(history[0].metadata, Some(meta));
+    assert!(history[1].metadata.is_none());
+    let sent = serde_json::to_string(&calls.lock().unwrap()[0].0).unwrap();
+    assert!(!sent.contains("u_123"));
+}
+
+#[tokio::test]
+async fn test_retry_recovers_from_chaos() {
+    use crate::llm::mock::ChaosProvider;
+
+    let llm = ChaosProvider::new(MockProvider::new().text("done"), 3)
+        .rate_limit(0.4)
+        .server_error(0.3)
+        .connection_drop(0.2);
+    let errors = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let e = errors.clone();
+    let mut agent = Agent::new(llm).on_error(move |err, _| {
+        e.lock().unwrap().push(err.to_string());
+        ErrorAction::Retry
+    });
+
+    assert_eq!(agent.chat("go").await.unwrap(), "done");
+    assert!(!errors.lock().unwrap().is_empty());
+}
+
+/// Submit the final answer
+#[tiny_loop_macros::tool_internal]
+async fn finish(
+    /// Final answer
+    answer: String,
+) -> String {
+    format!("Final: {}", answer)
+}
+
+#[tokio::test]
+async fn test_terminal_tools() {
+    let llm = MockProvider::new()
+        .tool_calls("", &[("call_1", "get_weather", r#"{"city":"Paris"}"#)])
+        .tool_calls(
+            "",
+            &[
+                ("call_2", "finish", r#"{"answer":"Sunny"}"#),
+                ("call_3", "get_weather", r#"{"city":"Rome"}"#),
+            ],
+        )
+        .text("unreachable");
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm)
+        .tool(get_weather)
+        .tool(finish)
+        .terminal_tools(["finish"]);
+
+    assert_eq!(
+        agent.chat("Weather in Paris?").await.unwrap(),
+        "Final: Sunny"
+    );
+    assert_eq!(calls.lock().unwrap().len(), 2);
+    // Both results of the last step are in the history
+    assert_eq!(agent.last_tool_results().len(), 2);
+}
+
+#[tokio::test]
+async fn test_skip_completed() {
+    let mut agent = Agent::new(MockProvider::new().text("Paris")).skip_completed(true);
+    agent.chat("Capital of France?").await.unwrap();
+
+    // Resume from the persisted history of the finished run
+    let llm = MockProvider::new();
+    let calls = llm.calls();
+    let mut resumed =
+        Agent::from_messages(llm, agent.history.get_all().to_vec()).skip_completed(true);
+
+    assert_eq!(resumed.run().await.unwrap(), "Paris");
+    assert_eq!(resumed.step().await.unwrap().as_deref(), Some("Paris"));
+    assert!(calls.lock().unwrap().is_empty());
+    assert_eq!(resumed.history.get_all().len(), 2);
+}
+
+#[tokio::test]
+async fn test_branch() {
+    let mut agent = Agent::from_factory(|| {
+        Agent::new(MockProvider::new().text("one").text("two")).system("Be brief")
+    });
+    assert!(Agent::new(MockProvider::new()).branch().is_none());
+    agent.chat("Count").await.unwrap();
+
+    let mut branch = agent.branch().unwrap();
+    assert_eq!(branch.history.get_all().len(), 3);
+    branch.chat("Left").await.unwrap();
+    agent.chat("Right").await.unwrap();
+
+    let user_messages = |agent: &Agent| -> Vec<String> {
+        agent
+            .history
+            .get_all()
+            .iter()
+            .filter_map(|m| match &m.message {
+                Message::User(m) => Some(m.content.clone()),
+                _ => None,
+            })
+            .collect()
+    };
+    assert_eq!(user_messages(&agent), ["Count", "Right"]);
+    assert_eq!(user_messages(&branch), ["Count", "Left"]);
+    assert_eq!(agent.history.get_all().len(), 5);
+    assert_eq!(branch.history.get_all().len(), 5);
+    assert!(matches!(
+        &branch.history.get_all()[0].message,
+        Message::System(_)
+    ));
+    assert!(
+        matches!(&branch.history.get_all()[4].message, Message::Assistant(m) if m.content == "one")
+    );
+}
+
+#[tokio::test]
+async fn test_branch_history_type() {
+    use crate::{
+        history::{History, TokenWindowHistory},
+        tokenizer::ApproxTokenizer,
+        types::TimedMessage,
+    };
+
+    /// History without removal support
+    struct AppendOnly(Vec<TimedMessage>);
+
+    impl History for AppendOnly {
+        fn add(&mut self, message: TimedMessage) {
+            self.0.push(message);
+        }
+
+        fn get_all(&self) -> &[TimedMessage] {
+            &self.0
+        }
+    }
+
+    let mut agent = Agent::from_factory(|| {
+        Agent::new(MockProvider::new().text("one"))
+            .history(TokenWindowHistory::new(1000, ApproxTokenizer))
+            .system("Be brief")
+    });
+    agent.chat("Count").await.unwrap();
+    let branch = agent.branch().unwrap();
+    assert_eq!(branch.history.get_all().len(), 3);
+    assert!(matches!(
+        &branch.history.get_all()[0].message,
+        Message::System(_)
+    ));
+
+    // Not replaced by an unbounded history
+    let agent = Agent::from_factory(|| {
+        Agent::new(MockProvider::new())
+            .history(AppendOnly(Vec::new()))
+            .system("Be brief")
+    });
+    assert!(agent.branch().is_none());
+}
+
+#[tokio::test]
+async fn test_clone_config() {
+    let agent = Agent::from_factory(|| {
+        let llm = MockProvider::new()
+            .tool_calls("", &[("call_1", "get_weather", r#"{"city":"Paris"}"#)])
+            .text("done");
+        Agent::new(llm).system("Be brief").tool(get_weather)
+    });
+    assert!(Agent::new(MockProvider::new()).clone_config().is_none());
+
+    let workers: Vec<_> = (0..4)
+        .map(|i| {
+            let mut worker = agent.clone_config().unwrap();
+            async move {
+                let answer = worker.chat(format!("Question {}", i)).await.unwrap();
+                (answer, worker)
+            }
+        })
+        .collect();
+
+    for (i, worker) in futures::future::join_all(workers)
+        .await
+        .into_iter()
+        .enumerate()
+    {
+        let (answer, worker) = worker;
+        assert_eq!(answer, "done");
+        let history = worker.history.get_all();
+        // system, user, tool call, tool result, answer
+        assert_eq!(history.len(), 5);
+        assert!(
+            matches!(&history[1].message, Message::User(m) if m.content == format!("Question {}", i))
+        );
+    }
+    // The original agent is untouched
+    assert_eq!(agent.history.get_all().len(), 1);
+}
+
+#[tokio::test]
+async fn test_prime_tool_result() {
+    let llm = MockProvider::new().text("You are on the pro plan.");
+    let calls = llm.calls();
+    let mut agent = Agent::new(llm).system("Be brief").prime_tool_result(
+        "get_account",
+        r#"{"id":"42"}"#,
+        r#"{"plan":"pro"}"#,
+    );
+
+    let history = agent.history.get_all();
+    let Message::Assistant(call) = &history[1].message else {
+        panic!("expected a tool call message");
+    };
+    let call = &call.tool_calls.as_ref().unwrap()[0];
+    assert_eq!(call.function.name, "get_account");
+    assert_eq!(call.function.arguments, r#"{"id":"42"}"#);
+    assert!(
+        matches!(&history[2].message, Message::Tool(m) if m.tool_call_id == call.id && m.content == r#"{"plan":"pro"}"#)
+    );
+    let mut check = InfiniteHistory::new();
+    check.add_batch(history.to_vec());
+    assert_eq!(check.sanitize(), 0);
+
+    agent.chat("Which plan am I on?").await.unwrap();
+    assert_eq!(tool_contents(&calls, 0), vec![r#"{"plan":"pro"}"#]);
+}
+
+#[tokio::test]
+async fn test_last_streamed_content() {
+    use crate::llm::{OpenAIProvider, mock::MockServer};
+    use std::sync::Mutex;
+    use tokio::io::AsyncWriteExt;
+
+    let url = MockServer::spawn(|mut stream, _| async move {
+        let body = [
+            r#"data: {"choices":[{"delta":{"content":"Hel"}}]}"#,
+            r#"data: {"choices":[{"delta":{"content":"lo "}}]}"#,
+            r#"data: {"choices":[{"delta":{"content":"world"},"finish_reason":"stop"}]}"#,
+            "data: [DONE]",
+        ]
+        .map(|l| format!("{}\n\n", l))
+        .concat();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+    })
+    .await;
+
+    let chunks = Arc::new(Mutex::new(Vec::new()));
+    let c = chunks.clone();
+    let llm = OpenAIProvider::new()
+        .base_url(url)
+        .stream_callback(move |chunk| c.lock().unwrap().push(chunk));
+    let mut agent = Agent::new(llm);
+
+    assert_eq!(agent.last_streamed_content(), "");
+    assert_eq!(agent.chat("Hi").await.unwrap(), "Hello world");
+    assert_eq!(
+        agent.last_streamed_content(),
+        chunks.lock().unwrap().concat()
+    );
+    assert_eq!(agent.last_streamed_content(), "Hello world");
+}
+
+#[tokio::test]
+async fn test_include_timestamps() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let timed = |message: Message, secs: u64| TimedMessage {
+        message,
+        timestamp: UNIX_EPOCH + Duration::from_secs(secs),
+        elapsed: Duration::ZERO,
+        metadata: None,
+    };
+    let history = vec![
+        timed(
+            crate::types::SystemMessage {
+                content: "Be brief".into(),
+            }
+            .into(),
+            0,
+        ),
+        // 2024-02-29T12:34:56Z
+        timed(
+            crate::types::UserMessage {
+                content: "Remind me at noon".into(),
+            }
+            .into(),
+            1709210096,
+        ),
+    ];
+    let llm = MockProvider::new().text("Sure");
+    let calls = llm.calls();
+    let mut agent = Agent::from_messages(llm, history.clone()).include_timestamps(true);
+    agent.run().await.unwrap();
+
+    let sent = calls.lock().unwrap()[0].0.clone();
+    assert!(matches!(&sent[0], Message::System(m) if m.content == "Be brief"));
+    assert!(
+        matches!(&sent[1], Message::User(m) if m.content == "[2024-02-29T12:34:56Z] Remind me at noon")
+    );
+    // The history is unchanged
+    assert!(
+        matches!(&agent.history.get_all()[1].message, Message::User(m) if m.content == "Remind me at noon")
+    );
+
+    let llm = MockProvider::new().text("Sure");
+    let calls = llm.calls();
+    let mut agent = Agent::from_messages(llm, history)
+        .include_timestamps(true)
+        .timestamp_format(|t| format!("t={}", t.duration_since(UNIX_EPOCH).unwrap().as_secs()));
+    agent.run().await.unwrap();
+    assert!(
+        matches!(&calls.lock().unwrap()[0].0[1], Message::User(m) if m.content == "[t=1709210096] Remind me at noon")
+    );
+}
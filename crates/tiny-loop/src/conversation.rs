@@ -0,0 +1,255 @@
+use crate::{
+    Agent,
+    types::{Message, TimedMessage, ToolCall, Usage, UserMessage},
+};
+
+/// Result of one conversation turn
+#[derive(Debug, Clone)]
+pub struct Response {
+    /// Final response of the assistant
+    pub content: String,
+    /// Tool calls made during the turn, in order
+    pub tool_calls: Vec<ToolCall>,
+    /// Token usage of the turn, as reported by the provider
+    pub usage: Usage,
+}
+
+/// Turn-based chat over an [`Agent`], for applications that don't need to customize the loop
+///
+/// # Example
+/// ```
+/// use tiny_loop::{Agent, Conversation, llm::OpenAIProvider};
+///
+/// # async fn example() -> tiny_loop::Result<()> {
+/// let agent = Agent::new(OpenAIProvider::new()).system("You are a helpful assistant");
+/// let mut conversation = Conversation::new(agent);
+///
+/// let response = conversation.send("Hello").await?;
+/// println!("{}", response.content);
+///
+/// // Not happy with the answer
+/// let response = conversation.regenerate().await?;
+/// println!("{}", conversation.history_markdown());
+/// # Ok(())
+/// # }
+/// ```
+pub struct Conversation {
+    agent: Agent,
+}
+
+impl Conversation {
+    /// Create a conversation over the given agent
+    pub fn new(agent: Agent) -> Self {
+        Self { agent }
+    }
+
+    /// Get the underlying agent
+    pub fn agent(&self) -> &Agent {
+        &self.agent
+    }
+
+    /// Get mutable access to the underlying agent
+    pub fn agent_mut(&mut self) -> &mut Agent {
+        &mut self.agent
+    }
+
+    /// Consume the conversation and return the underlying agent
+    pub fn into_agent(self) -> Agent {
+        self.agent
+    }
+
+    /// Send a user message and run the agent until it responds
+    pub async fn send(&mut self, text: impl Into<String>) -> crate::Result<Response> {
+        self.agent.history.add(TimedMessage {
+            message: UserMessage {
+                content: text.into(),
+            }
+            .into(),
+            timestamp: std::time::SystemTime::now(),
+            elapsed: std::time::Duration::ZERO,
+            metadata: None,
+        });
+        self.respond().await
+    }
+
+    /// Discard the response to the last user message and run the agent again
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`Error::Custom`](crate::Error::Custom) if there is no user message
+    /// or the history doesn't support removal.
+    pub async fn regenerate(&mut self) -> crate::Result<Response> {
+        let index = self
+            .last_user_index()
+            .ok_or_else(|| crate::Error::Custom("No user message to regenerate from".into()))?;
+        if !self.truncate(index + 1) {
+            return Err(crate::Error::Custom(
+                "History doesn't support removing messages".into(),
+            ));
+        }
+        self.respond().await
+    }
+
+    /// Remove the last user message and everything after it.
+    /// Returns `false` if there is no user message or the history doesn't support removal
+    pub fn undo_last_turn(&mut self) -> bool {
+        self.last_user_index()
+            .is_some_and(|index| self.truncate(index))
+    }
+
+    /// Render the history as Markdown, one section per message
+    pub fn history_markdown(&self) -> String {
+        let mut sections = Vec::new();
+        for tm in self.agent.history.get_all() {
+            match &tm.message {
+                Message::System(m) => sections.push(format!("## System\n\n{}", m.content)),
+                Message::User(m) => sections.push(format!("## User\n\n{}", m.content)),
+                Message::Assistant(m) => {
+                    let mut section = String::from("## Assistant");
+                    if !m.content.is_empty() {
+                        section.push_str(&format!("\n\n{}", m.content));
+                    }
+                    for call in m.tool_calls.iter().flatten() {
+                        section.push_str(&format!(
+                            "\n\n> Called `{}` with `{}`",
+                            call.function.name, call.function.arguments
+                        ));
+                    }
+                    sections.push(section);
+                }
+                Message::Tool(m) => sections.push(format!(
+                    "## Tool result\n\n```\n{}\n```",
+                    m.content.trim_end()
+                )),
+                Message::Custom(_) => {}
+            }
+        }
+        sections.join("\n\n")
+    }
+
+    /// Run the agent and describe the turn since the last user message
+    async fn respond(&mut self) -> crate::Result<Response> {
+        let usage = self.agent.usage();
+        let content = self.agent.run().await?;
+        let messages = self.agent.history.get_all();
+        let start = self.last_user_index().map_or(0, |i| i + 1);
+        let tool_calls = messages[start..]
+            .iter()
+            .filter_map(|tm| match &tm.message {
+                Message::Assistant(m) => m.tool_calls.clone(),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        let total = self.agent.usage();
+        Ok(Response {
+            content,
+            tool_calls,
+            usage: Usage {
+                prompt_tokens: total.prompt_tokens - usage.prompt_tokens,
+                completion_tokens: total.completion_tokens - usage.completion_tokens,
+                total_tokens: total.total_tokens - usage.total_tokens,
+            },
+        })
+    }
+
+    /// Index of the last user message
+    fn last_user_index(&self) -> Option<usize> {
+        self.agent
+            .history
+            .get_all()
+            .iter()
+            .rposition(|tm| matches!(tm.message, Message::User(_)))
+    }
+
+    /// Remove messages from `len` on. Returns `false` if removal is unsupported
+    fn truncate(&mut self, len: usize) -> bool {
+        while self.agent.history.get_all().len() > len {
+            let last = self.agent.history.get_all().len() - 1;
+            if self.agent.history.remove(last).is_none() {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock::MockProvider;
+
+    fn usage(prompt_tokens: u64, completion_tokens: u64) -> Usage {
+        Usage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send() {
+        let llm = MockProvider::new()
+            .tool_calls("", &[("call_1", "missing", "{}")])
+            .usage(usage(9, 1))
+            .text("Done")
+            .usage(usage(18, 2))
+            .text("Again")
+            .usage(usage(4, 1));
+        let mut conversation = Conversation::new(Agent::new(llm));
+
+        let response = conversation.send("Hi").await.unwrap();
+        assert_eq!(response.content, "Done");
+        assert_eq!(response.tool_calls.len(), 1);
+        assert_eq!(response.tool_calls[0].function.name, "missing");
+        assert_eq!(response.usage, usage(27, 3));
+
+        let response = conversation.send("More").await.unwrap();
+        assert_eq!(response.content, "Again");
+        assert!(response.tool_calls.is_empty());
+        assert_eq!(response.usage, usage(4, 1));
+        assert_eq!(conversation.agent().usage(), usage(31, 4));
+    }
+
+    #[tokio::test]
+    async fn test_regenerate_and_undo() {
+        let llm = MockProvider::new()
+            .tool_calls("", &[("call_1", "missing", "{}")])
+            .text("First")
+            .text("Second");
+        let calls = llm.calls();
+        let mut conversation = Conversation::new(Agent::new(llm).system("Be brief"));
+
+        conversation.send("Hi").await.unwrap();
+        assert_eq!(conversation.agent().history.get_all().len(), 5);
+
+        let response = conversation.regenerate().await.unwrap();
+        assert_eq!(response.content, "Second");
+        // The previous answer was discarded before regenerating
+        assert_eq!(calls.lock().unwrap()[2].0.len(), 2);
+        assert_eq!(conversation.agent().history.get_all().len(), 3);
+
+        assert!(conversation.undo_last_turn());
+        assert_eq!(conversation.agent().history.get_all().len(), 1);
+        assert!(!conversation.undo_last_turn());
+        assert!(conversation.regenerate().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_history_markdown() {
+        let llm = MockProvider::new()
+            .tool_calls("Let me check.", &[("call_1", "missing", r#"{"q":1}"#)])
+            .text("Done");
+        let mut conversation = Conversation::new(Agent::new(llm).system("Be brief"));
+        conversation.send("Hi").await.unwrap();
+
+        assert_eq!(
+            conversation.history_markdown(),
+            "## System\n\nBe brief\n\n\
+             ## User\n\nHi\n\n\
+             ## Assistant\n\nLet me check.\n\n> Called `missing` with `{\"q\":1}`\n\n\
+             ## Tool result\n\n```\nTool 'missing' not found\n```\n\n\
+             ## Assistant\n\nDone"
+        );
+    }
+}
@@ -1,4 +1,5 @@
 #[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
 pub enum Error {
     #[error("LLM API error ({status}): {body}")]
     ApiError { status: u16, body: String },
@@ -12,8 +13,40 @@ pub enum Error {
     Json(#[from] serde_json::Error),
     #[error("Expected Assistant message, got: {0}")]
     UnexpectedMessage(String),
+    #[error("Invalid tool definition '{name}': {reason}")]
+    InvalidToolDefinition { name: String, reason: String },
+    #[error("LLM finished with reason: {0:?}")]
+    FinishReason(crate::types::FinishReason),
+    #[error("Streamed line exceeds the maximum of {0} bytes")]
+    StreamLineTooLong(usize),
+    #[error("LLM refused: {0}")]
+    Refusal(String),
+    #[error("Streaming requires n = 1, got n = {0}")]
+    StreamingMultipleChoices(u64),
+    #[error("Operation cancelled")]
+    Cancelled,
+    #[error("Tool results exceed the budget: {tokens} > {max_tokens} tokens")]
+    ToolResultsTooLarge { tokens: usize, max_tokens: usize },
+    /// `partial` is the last non-empty assistant content of the run, if any
+    #[error("Agent reached the maximum of {max_steps} steps")]
+    MaxSteps {
+        max_steps: usize,
+        partial: Option<String>,
+    },
     #[error("{0}")]
     Custom(String),
 }
 
+impl Error {
+    /// The `error.code` of an [`ApiError`](Self::ApiError) body in the OpenAI format,
+    /// e.g. `context_length_exceeded`
+    pub fn api_error_code(&self) -> Option<String> {
+        let Self::ApiError { body, .. } = self else {
+            return None;
+        };
+        let body: serde_json::Value = serde_json::from_str(body).ok()?;
+        Some(body["error"]["code"].as_str()?.to_string())
+    }
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
@@ -1,8 +1,11 @@
 mod infinite;
+mod token_window;
 
-use crate::types::TimedMessage;
+use crate::types::{AssistantMessage, Message, TimedMessage};
+use std::collections::HashSet;
 
 pub use infinite::*;
+pub use token_window::*;
 
 /// Manages conversation history
 pub trait History {
@@ -18,4 +21,187 @@ pub trait History {
 
     /// Get all messages in history
     fn get_all(&self) -> &[TimedMessage];
+
+    /// Get mutable access to the message at `index` for in-place edits.
+    /// Returns `None` if out of bounds or unsupported (default)
+    fn get_mut(&mut self, _index: usize) -> Option<&mut TimedMessage> {
+        None
+    }
+
+    /// Remove and return the message at `index`.
+    /// Returns `None` if out of bounds or unsupported (default)
+    fn remove(&mut self, _index: usize) -> Option<TimedMessage> {
+        None
+    }
+
+    /// Remove tool messages that don't answer a tool call of the preceding assistant message,
+    /// and assistant tool-call messages missing some results (together with their results),
+    /// so the history forms a sequence providers accept.
+    /// Returns the number of removed messages.
+    ///
+    /// Relies on [`remove`](Self::remove); does nothing if removal is unsupported.
+    fn sanitize(&mut self) -> usize {
+        let invalid = invalid_tool_messages(self.get_all());
+        invalid
+            .iter()
+            .rev()
+            .filter(|&&i| self.remove(i).is_some())
+            .count()
+    }
+}
+
+/// Indices (ascending) of orphaned tool messages and incomplete tool-call messages
+fn invalid_tool_messages(messages: &[TimedMessage]) -> Vec<usize> {
+    let mut invalid = Vec::new();
+    let mut i = 0;
+    while i < messages.len() {
+        match &messages[i].message {
+            Message::Assistant(AssistantMessage {
+                tool_calls: Some(calls),
+                ..
+            }) if !calls.is_empty() => {
+                let ids: HashSet<_> = calls.iter().map(|c| c.id.as_str()).collect();
+                let mut answered = HashSet::new();
+                let mut orphans = Vec::new();
+                let mut j = i + 1;
+                while let Some(Message::Tool(tool)) = messages.get(j).map(|m| &m.message) {
+                    if ids.contains(tool.tool_call_id.as_str()) {
+                        answered.insert(tool.tool_call_id.as_str());
+                    } else {
+                        orphans.push(j);
+                    }
+                    j += 1;
+                }
+                if answered.len() < ids.len() {
+                    invalid.extend(i..j);
+                } else {
+                    invalid.extend(orphans);
+                }
+                i = j;
+            }
+            Message::Tool(_) => {
+                invalid.push(i);
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+    invalid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock::timed;
+    use crate::types::{ToolMessage, UserMessage};
+
+    fn user(content: &str) -> TimedMessage {
+        timed(
+            UserMessage {
+                content: content.into(),
+            }
+            .into(),
+        )
+    }
+
+    fn calls(ids: &[&str]) -> TimedMessage {
+        timed(
+            AssistantMessage {
+                content: String::new(),
+                tool_calls: Some(
+                    ids.iter()
+                        .map(|id| crate::llm::mock::tool_call(id, "tool", "{}"))
+                        .collect(),
+                ),
+                refusal: None,
+                annotations: None,
+            }
+            .into(),
+        )
+    }
+
+    fn result(id: &str) -> TimedMessage {
+        timed(
+            ToolMessage {
+                content: "ok".into(),
+                tool_call_id: id.into(),
+            }
+            .into(),
+        )
+    }
+
+    /// Short description of each message, e.g. `user:hi`, `calls:a,b`, `tool:a`
+    fn describe(history: &InfiniteHistory) -> Vec<String> {
+        history
+            .get_all()
+            .iter()
+            .map(|m| match &m.message {
+                Message::User(m) => format!("user:{}", m.content),
+                Message::Assistant(m) => format!(
+                    "calls:{}",
+                    m.tool_calls
+                        .iter()
+                        .flatten()
+                        .map(|c| c.id.as_str())
+                        .collect::<Vec<_>>()
+                        .join(",")
+                ),
+                Message::Tool(m) => format!("tool:{}", m.tool_call_id),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sanitize_valid_history_unchanged() {
+        let mut history = InfiniteHistory::new();
+        history.add_batch(vec![
+            user("hi"),
+            calls(&["a", "b"]),
+            result("b"),
+            result("a"),
+        ]);
+
+        assert_eq!(history.sanitize(), 0);
+        assert_eq!(
+            describe(&history),
+            ["user:hi", "calls:a,b", "tool:b", "tool:a"]
+        );
+    }
+
+    #[test]
+    fn test_sanitize_orphaned_tool_messages() {
+        let mut history = InfiniteHistory::new();
+        // The assistant message of `x` was trimmed
+        history.add_batch(vec![
+            result("x"),
+            user("hi"),
+            calls(&["a"]),
+            result("a"),
+            result("y"),
+        ]);
+
+        assert_eq!(history.sanitize(), 2);
+        assert_eq!(describe(&history), ["user:hi", "calls:a", "tool:a"]);
+    }
+
+    #[test]
+    fn test_sanitize_orphaned_tool_calls() {
+        let mut history = InfiniteHistory::new();
+        // The result of `b` was trimmed
+        history.add_batch(vec![
+            user("hi"),
+            calls(&["a", "b"]),
+            result("a"),
+            user("next"),
+            calls(&["c"]),
+            result("c"),
+        ]);
+
+        assert_eq!(history.sanitize(), 2);
+        assert_eq!(
+            describe(&history),
+            ["user:hi", "user:next", "calls:c", "tool:c"]
+        );
+    }
 }
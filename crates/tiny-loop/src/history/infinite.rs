@@ -28,4 +28,60 @@ impl History for InfiniteHistory {
     fn get_all(&self) -> &[TimedMessage] {
         &self.messages
     }
+
+    fn get_mut(&mut self, index: usize) -> Option<&mut TimedMessage> {
+        self.messages.get_mut(index)
+    }
+
+    fn remove(&mut self, index: usize) -> Option<TimedMessage> {
+        (index < self.messages.len()).then(|| self.messages.remove(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock::timed;
+    use crate::types::{Message, UserMessage};
+
+    fn user(content: &str) -> TimedMessage {
+        timed(
+            UserMessage {
+                content: content.into(),
+            }
+            .into(),
+        )
+    }
+
+    fn content(m: &TimedMessage) -> &str {
+        match &m.message {
+            Message::User(m) => &m.content,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_get_mut_edits_in_place() {
+        let mut history = InfiniteHistory::new();
+        history.add(user("my key is sk-123"));
+
+        if let Some(Message::User(m)) = history.get_mut(0).map(|m| &mut m.message) {
+            m.content = "my key is [REDACTED]".into();
+        }
+
+        assert_eq!(content(&history.get_all()[0]), "my key is [REDACTED]");
+        assert!(history.get_mut(1).is_none());
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut history = InfiniteHistory::new();
+        history.add(user("a"));
+        history.add(user("b"));
+
+        assert_eq!(content(&history.remove(0).unwrap()), "a");
+        assert!(history.remove(1).is_none());
+        assert_eq!(history.get_all().len(), 1);
+        assert_eq!(content(&history.get_all()[0]), "b");
+    }
 }
@@ -0,0 +1,125 @@
+use super::History;
+use crate::{
+    tokenizer::Tokenizer,
+    types::{Message, TimedMessage},
+};
+
+/// History that keeps the most recent messages within a token budget
+///
+/// When the budget is exceeded, the oldest non-system messages are dropped.
+/// System messages and the latest message are always kept.
+/// Token counts are computed once per message with the shared [`Tokenizer`].
+///
+/// # Example
+/// ```
+/// use tiny_loop::{Agent, history::TokenWindowHistory, llm::OpenAIProvider, tokenizer::ApproxTokenizer};
+///
+/// let agent = Agent::new(OpenAIProvider::new())
+///     .history(TokenWindowHistory::new(8000, ApproxTokenizer));
+/// ```
+pub struct TokenWindowHistory {
+    messages: Vec<TimedMessage>,
+    /// Token count of each message in `messages`
+    tokens: Vec<usize>,
+    max_tokens: usize,
+    tokenizer: Box<dyn Tokenizer>,
+}
+
+impl TokenWindowHistory {
+    /// Create a history holding at most `max_tokens` tokens as counted by `tokenizer`
+    pub fn new(max_tokens: usize, tokenizer: impl Tokenizer + 'static) -> Self {
+        Self {
+            messages: Vec::new(),
+            tokens: Vec::new(),
+            max_tokens,
+            tokenizer: Box::new(tokenizer),
+        }
+    }
+
+    /// Total tokens of all messages currently in history
+    pub fn total_tokens(&self) -> usize {
+        self.tokens.iter().sum()
+    }
+}
+
+impl History for TokenWindowHistory {
+    fn add(&mut self, message: TimedMessage) {
+        self.tokens
+            .push(self.tokenizer.count_message(&message.message));
+        self.messages.push(message);
+
+        let mut total = self.total_tokens();
+        while total > self.max_tokens {
+            let last = self.messages.len() - 1;
+            let Some(oldest) = self.messages[..last]
+                .iter()
+                .position(|m| !matches!(m.message, Message::System(_)))
+            else {
+                break;
+            };
+            tracing::trace!("Dropping message {} to fit token window", oldest);
+            self.messages.remove(oldest);
+            total -= self.tokens.remove(oldest);
+        }
+    }
+
+    fn get_all(&self) -> &[TimedMessage] {
+        &self.messages
+    }
+
+    fn remove(&mut self, index: usize) -> Option<TimedMessage> {
+        (index < self.messages.len()).then(|| {
+            self.tokens.remove(index);
+            self.messages.remove(index)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        llm::mock::timed,
+        tokenizer::ApproxTokenizer,
+        types::{SystemMessage, UserMessage},
+    };
+
+    fn user(content: &str) -> TimedMessage {
+        timed(
+            UserMessage {
+                content: content.into(),
+            }
+            .into(),
+        )
+    }
+
+    #[test]
+    fn test_drops_oldest_non_system_messages() {
+        let system = timed(
+            SystemMessage {
+                content: "system".into(),
+            }
+            .into(),
+        );
+        let per_message = ApproxTokenizer.count_message(&user("0").message);
+        let system_tokens = ApproxTokenizer.count_message(&system.message);
+        let mut history = TokenWindowHistory::new(system_tokens + per_message * 2, ApproxTokenizer);
+
+        history.add(system);
+        for i in 0..5 {
+            history.add(user(&i.to_string()));
+        }
+
+        let contents: Vec<_> = history
+            .get_all()
+            .iter()
+            .map(|m| match &m.message {
+                Message::System(m) => m.content.clone(),
+                Message::User(m) => m.content.clone(),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(contents, vec!["system", "3", "4"]);
+        assert!(history.total_tokens() <= system_tokens + per_message * 2);
+    }
+}
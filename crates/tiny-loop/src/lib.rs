@@ -1,9 +1,12 @@
 mod agent;
+mod conversation;
 mod error;
 
 pub mod history;
 pub mod llm;
+pub mod tokenizer;
 pub mod tool;
 pub mod types;
 pub use agent::*;
+pub use conversation::*;
 pub use error::*;
@@ -1,9 +1,19 @@
+mod cohere;
+#[cfg(any(test, feature = "test-util"))]
+pub mod mock;
 mod openai;
+mod partial_json;
+mod rate_limit;
+mod retry;
 
 use crate::types::{LLMResponse, Message, ToolDefinition};
 use async_trait::async_trait;
 
+pub use cohere::*;
 pub use openai::*;
+pub use partial_json::*;
+pub use rate_limit::*;
+pub use retry::*;
 
 /// LLM provider trait for making API calls
 #[async_trait]
@@ -14,4 +24,53 @@ pub trait LLMProvider: Send + Sync {
         messages: &[Message],
         tools: &[ToolDefinition],
     ) -> crate::Result<LLMResponse>;
+
+    /// Call the LLM like [`call`](Self::call) with per-call options.
+    ///
+    /// The default implementation ignores the options.
+    async fn call_with(
+        &mut self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        options: &CallOptions,
+    ) -> crate::Result<LLMResponse> {
+        let _ = options;
+        self.call(messages, tools).await
+    }
+
+    /// Check that the provider is reachable and the credentials are valid,
+    /// e.g. before routing traffic to it.
+    ///
+    /// The default implementation makes no request and returns `Ok(())`.
+    async fn health_check(&self) -> crate::Result<()> {
+        Ok(())
+    }
+
+    /// The content chunks of the last call concatenated as they were streamed,
+    /// which may differ from the assembled message in edge cases.
+    ///
+    /// Returns `None` if the last call was not streamed.
+    /// The default implementation always returns `None`, for providers that don't stream.
+    fn streamed_content(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Embedding provider trait, e.g. for retrieval-augmented generation
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// Embed every input, returning one vector per input in the same order
+    async fn embed(&self, inputs: Vec<String>) -> crate::Result<Vec<Vec<f32>>>;
+}
+
+/// Per-call options for [`LLMProvider::call_with`]
+#[derive(Debug, Clone, Default)]
+pub struct CallOptions {
+    /// Retries shared with other calls, consumed before each retry
+    pub retry_budget: Option<RetryBudget>,
+    /// Force streaming on or off for this call, regardless of the registered callbacks.
+    /// When disabled, supporting providers invoke their stream callbacks once with the full content
+    pub stream: Option<bool>,
+    /// Correlation id sent with the request, e.g. as the `X-Request-Id` header
+    pub request_id: Option<String>,
 }
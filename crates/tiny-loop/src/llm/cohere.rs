@@ -0,0 +1,552 @@
+use crate::types::{
+    AssistantMessage, FinishReason, FunctionCall, LLMResponse, Message, ReasoningEffort, ToolCall,
+    ToolDefinition,
+};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+/// Callback for streaming Cohere responses
+pub type CohereStreamCallback = Box<dyn FnMut(String) + Send + Sync>;
+
+/// Response from Cohere's `/v2/chat` API
+#[derive(Deserialize)]
+struct ChatResponse {
+    finish_reason: String,
+    message: ResponseMessage,
+}
+
+/// Assistant message in a Cohere response
+#[derive(Deserialize)]
+struct ResponseMessage {
+    #[serde(default)]
+    content: Vec<ContentBlock>,
+    #[serde(default)]
+    tool_plan: Option<String>,
+    #[serde(default)]
+    tool_calls: Option<Vec<ToolCall>>,
+}
+
+/// Content block in a Cohere response
+#[derive(Deserialize)]
+struct ContentBlock {
+    #[serde(default)]
+    text: Option<String>,
+}
+
+/// Cohere chat API (v2) provider
+///
+/// # Examples
+///
+/// ```
+/// use tiny_loop::llm::CohereProvider;
+///
+/// let provider = CohereProvider::new()
+///     .api_key("...")
+///     .model("command-a-03-2025");
+/// ```
+pub struct CohereProvider {
+    /// HTTP client for API requests
+    client: reqwest::Client,
+    /// API base URL
+    base_url: String,
+    /// API authentication key
+    api_key: String,
+    /// Model identifier
+    model: String,
+    /// Stream callback for LLM responses
+    stream_callback: Option<CohereStreamCallback>,
+    /// Reasoning effort, sent as a thinking token budget
+    reasoning_effort: Option<ReasoningEffort>,
+}
+
+impl Default for CohereProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CohereProvider {
+    /// Create a new Cohere provider with default settings
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::CohereProvider;
+    ///
+    /// let provider = CohereProvider::new();
+    /// ```
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: "https://api.cohere.com/v2".into(),
+            api_key: "".into(),
+            model: "command-a-03-2025".into(),
+            stream_callback: None,
+            reasoning_effort: None,
+        }
+    }
+
+    /// Set the base URL for the API endpoint (default: `https://api.cohere.com/v2`)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::CohereProvider;
+    ///
+    /// let provider = CohereProvider::new()
+    ///     .base_url("https://proxy.example.com/v2");
+    /// ```
+    pub fn base_url(mut self, value: impl Into<String>) -> Self {
+        self.base_url = value.into();
+        self
+    }
+
+    /// Set the API key for authentication (default: empty string)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::CohereProvider;
+    ///
+    /// let provider = CohereProvider::new()
+    ///     .api_key("...");
+    /// ```
+    pub fn api_key(mut self, value: impl Into<String>) -> Self {
+        self.api_key = value.into();
+        self
+    }
+
+    /// Set the model name to use (default: `command-a-03-2025`)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::CohereProvider;
+    ///
+    /// let provider = CohereProvider::new()
+    ///     .model("command-r-plus-08-2024");
+    /// ```
+    pub fn model(mut self, value: impl Into<String>) -> Self {
+        self.model = value.into();
+        self
+    }
+
+    /// Set stream callback for LLM responses
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::CohereProvider;
+    ///
+    /// let provider = CohereProvider::new()
+    ///     .stream_callback(|chunk| print!("{}", chunk));
+    /// ```
+    pub fn stream_callback<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(String) + Send + Sync + 'static,
+    {
+        self.stream_callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Enable thinking for reasoning models with the [token budget](ReasoningEffort::token_budget)
+    /// of the given effort (default: unset)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::{llm::CohereProvider, types::ReasoningEffort};
+    ///
+    /// let provider = CohereProvider::new()
+    ///     .model("command-a-reasoning-08-2025")
+    ///     .reasoning_effort(ReasoningEffort::Medium);
+    /// ```
+    pub fn reasoning_effort(mut self, effort: ReasoningEffort) -> Self {
+        self.reasoning_effort = Some(effort);
+        self
+    }
+}
+
+/// Convert a message into Cohere's format.
+/// Assistant text accompanying tool calls is sent as `tool_plan`.
+fn to_cohere_message(message: &Message) -> crate::Result<Value> {
+    Ok(match message {
+        Message::System(m) => json!({ "role": "system", "content": m.content }),
+        Message::User(m) => json!({ "role": "user", "content": m.content }),
+        Message::Assistant(AssistantMessage {
+            content,
+            tool_calls: Some(calls),
+            ..
+        }) if !calls.is_empty() => {
+            let mut msg = json!({ "role": "assistant", "tool_calls": calls });
+            if !content.is_empty() {
+                msg["tool_plan"] = json!(content);
+            }
+            msg
+        }
+        Message::Assistant(m) => json!({ "role": "assistant", "content": m.content }),
+        Message::Tool(m) => json!({
+            "role": "tool",
+            "tool_call_id": m.tool_call_id,
+            "content": m.content,
+        }),
+        Message::Custom(_) => serde_json::to_value(message)?,
+    })
+}
+
+/// Map a Cohere finish reason to [`FinishReason`]
+fn to_finish_reason(reason: &str) -> FinishReason {
+    match reason {
+        "COMPLETE" | "STOP_SEQUENCE" => FinishReason::Stop,
+        "MAX_TOKENS" => FinishReason::Length,
+        "TOOL_CALL" => FinishReason::ToolCalls,
+        other => FinishReason::Custom(other.to_string()),
+    }
+}
+
+#[async_trait]
+impl super::LLMProvider for CohereProvider {
+    async fn call(
+        &mut self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+    ) -> crate::Result<LLMResponse> {
+        tracing::debug!(
+            model = %self.model,
+            messages = messages.len(),
+            tools = tools.len(),
+            streaming = self.stream_callback.is_some(),
+            "Calling Cohere API"
+        );
+
+        let response = self.build_request(messages, tools)?.send().await?;
+
+        let status = response.status();
+        tracing::trace!("Cohere API response status: {}", status);
+
+        if !status.is_success() {
+            let body = response.text().await?;
+            tracing::debug!("Cohere API error: status={}, body={}", status, body);
+            return Err(crate::Error::ApiError {
+                status: status.as_u16(),
+                body,
+            });
+        }
+
+        if self.stream_callback.is_some() {
+            self.handle_stream(response.bytes_stream()).await
+        } else {
+            let body = response.text().await?;
+            Self::parse_response(&body)
+        }
+    }
+}
+
+impl CohereProvider {
+    /// Build the HTTP request for a chat call
+    fn build_request(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+    ) -> crate::Result<reqwest::RequestBuilder> {
+        let mut body = json!({
+            "model": self.model,
+            "messages": messages
+                .iter()
+                .map(to_cohere_message)
+                .collect::<crate::Result<Vec<_>>>()?,
+        });
+        if !tools.is_empty() {
+            body["tools"] = serde_json::to_value(tools)?;
+        }
+        if self.stream_callback.is_some() {
+            body["stream"] = json!(true);
+        }
+        if let Some(effort) = self.reasoning_effort {
+            body["thinking"] = json!({"type": "enabled", "token_budget": effort.token_budget()});
+        }
+
+        Ok(self
+            .client
+            .post(format!("{}/chat", self.base_url))
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("Content-Type", "application/json")
+            .json(&body))
+    }
+
+    /// Parse a non-streaming response body
+    fn parse_response(body: &str) -> crate::Result<LLMResponse> {
+        let response: ChatResponse = serde_json::from_str(body).map_err(|e| {
+            crate::Error::Custom(format!("Failed to parse response: {}. Body: {}", e, body))
+        })?;
+        let text: String = response
+            .message
+            .content
+            .iter()
+            .filter_map(|c| c.text.as_deref())
+            .collect();
+        tracing::debug!("Cohere API call completed successfully");
+        Ok(LLMResponse {
+            message: AssistantMessage {
+                content: if text.is_empty() {
+                    response.message.tool_plan.unwrap_or_default()
+                } else {
+                    text
+                },
+                tool_calls: response.message.tool_calls.filter(|c| !c.is_empty()),
+                refusal: None,
+                annotations: None,
+            },
+            finish_reason: to_finish_reason(&response.finish_reason),
+            logprobs: None,
+            usage: None,
+        })
+    }
+
+    async fn handle_stream<S, B, E>(&mut self, mut stream: S) -> crate::Result<LLMResponse>
+    where
+        S: futures::Stream<Item = Result<B, E>> + Unpin,
+        B: AsRef<[u8]>,
+        crate::Error: From<E>,
+    {
+        use futures::TryStreamExt;
+
+        let mut buffer = Vec::new();
+        let mut content = String::new();
+        let mut tool_plan = String::new();
+        let mut tool_calls: Vec<ToolCall> = Vec::new();
+        let mut finish_reason = FinishReason::Stop;
+
+        while let Some(chunk) = stream.try_next().await? {
+            // Only decode complete lines, so split multibyte characters stay intact
+            buffer.extend_from_slice(chunk.as_ref());
+
+            while let Some(line_end) = buffer.iter().position(|&b| b == b'\n') {
+                let line = String::from_utf8_lossy(&buffer[..line_end])
+                    .trim()
+                    .to_string();
+                buffer.drain(..=line_end);
+
+                let Some(data) = line.strip_prefix("data:") else {
+                    continue;
+                };
+                let Ok(event) = serde_json::from_str::<Value>(data.trim()) else {
+                    continue;
+                };
+                let message = &event["delta"]["message"];
+
+                match event["type"].as_str() {
+                    Some("content-delta") => {
+                        if let Some(text) = message["content"]["text"].as_str() {
+                            content.push_str(text);
+                            if let Some(callback) = &mut self.stream_callback {
+                                callback(text.to_string());
+                            }
+                        }
+                    }
+                    Some("tool-plan-delta") => {
+                        if let Some(plan) = message["tool_plan"].as_str() {
+                            tool_plan.push_str(plan);
+                        }
+                    }
+                    Some("tool-call-start") => {
+                        let call = &message["tool_calls"];
+                        tool_calls.push(ToolCall {
+                            id: call["id"].as_str().unwrap_or_default().to_string(),
+                            call_type: call["type"].as_str().unwrap_or("function").to_string(),
+                            function: FunctionCall {
+                                name: call["function"]["name"]
+                                    .as_str()
+                                    .unwrap_or_default()
+                                    .to_string(),
+                                arguments: call["function"]["arguments"]
+                                    .as_str()
+                                    .unwrap_or_default()
+                                    .to_string(),
+                            },
+                        });
+                    }
+                    Some("tool-call-delta") => {
+                        if let (Some(call), Some(args)) = (
+                            tool_calls.last_mut(),
+                            message["tool_calls"]["function"]["arguments"].as_str(),
+                        ) {
+                            call.function.arguments.push_str(args);
+                        }
+                    }
+                    Some("message-end") => {
+                        if let Some(reason) = event["delta"]["finish_reason"].as_str() {
+                            finish_reason = to_finish_reason(reason);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        tracing::debug!("Streaming completed, total length: {}", content.len());
+        Ok(LLMResponse {
+            message: AssistantMessage {
+                content: if content.is_empty() {
+                    tool_plan
+                } else {
+                    content
+                },
+                tool_calls: if tool_calls.is_empty() {
+                    None
+                } else {
+                    Some(tool_calls)
+                },
+                refusal: None,
+                annotations: None,
+            },
+            finish_reason,
+            logprobs: None,
+            usage: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{SystemMessage, ToolMessage};
+    use std::sync::{Arc, Mutex};
+
+    fn tool_call() -> ToolCall {
+        ToolCall {
+            id: "call_1".into(),
+            call_type: "function".into(),
+            function: FunctionCall {
+                name: "get_weather".into(),
+                arguments: r#"{"city":"Tokyo"}"#.into(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_message_translation() {
+        let messages = [
+            Message::System(SystemMessage {
+                content: "Be brief".into(),
+            }),
+            Message::Assistant(AssistantMessage {
+                content: "I will check the weather".into(),
+                tool_calls: Some(vec![tool_call()]),
+                refusal: None,
+                annotations: None,
+            }),
+            Message::Tool(ToolMessage {
+                content: "Sunny".into(),
+                tool_call_id: "call_1".into(),
+            }),
+        ];
+        let request = CohereProvider::new()
+            .model("command-r")
+            .build_request(&messages, &[])
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!(request.url().as_str().ends_with("/v2/chat"));
+        let body: Value =
+            serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+
+        assert_eq!(body["model"], "command-r");
+        assert!(body.get("tools").is_none());
+        assert!(body.get("thinking").is_none());
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["messages"][1]["tool_plan"], "I will check the weather");
+        assert!(body["messages"][1].get("content").is_none());
+        assert_eq!(
+            body["messages"][1]["tool_calls"][0]["function"]["name"],
+            "get_weather"
+        );
+        assert_eq!(body["messages"][2]["role"], "tool");
+        assert_eq!(body["messages"][2]["tool_call_id"], "call_1");
+    }
+
+    #[test]
+    fn test_reasoning_effort() {
+        let request = CohereProvider::new()
+            .reasoning_effort(ReasoningEffort::Low)
+            .build_request(&[], &[])
+            .unwrap()
+            .build()
+            .unwrap();
+        let body: Value =
+            serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert_eq!(
+            body["thinking"],
+            json!({"type": "enabled", "token_budget": 1024})
+        );
+    }
+
+    #[test]
+    fn test_parse_tool_call_response() {
+        let body = r#"{
+            "id": "abc",
+            "finish_reason": "TOOL_CALL",
+            "message": {
+                "role": "assistant",
+                "tool_plan": "I will check the weather",
+                "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "get_weather", "arguments": "{\"city\":\"Tokyo\"}"}
+                }]
+            }
+        }"#;
+        let response = CohereProvider::parse_response(body).unwrap();
+        assert_eq!(response.message.content, "I will check the weather");
+        assert_eq!(response.message.tool_calls.unwrap()[0].id, "call_1");
+        assert_eq!(response.finish_reason, FinishReason::ToolCalls);
+    }
+
+    #[test]
+    fn test_parse_text_response() {
+        let body = r#"{
+            "id": "abc",
+            "finish_reason": "COMPLETE",
+            "message": {"role": "assistant", "content": [{"type": "text", "text": "Hello"}]}
+        }"#;
+        let response = CohereProvider::parse_response(body).unwrap();
+        assert_eq!(response.message.content, "Hello");
+        assert!(response.message.tool_calls.is_none());
+        assert_eq!(response.finish_reason, FinishReason::Stop);
+    }
+
+    #[tokio::test]
+    async fn test_stream_content_and_tool_calls() {
+        let chunks = Arc::new(Mutex::new(Vec::new()));
+        let c = chunks.clone();
+        let mut provider =
+            CohereProvider::new().stream_callback(move |s| c.lock().unwrap().push(s));
+        let events = [
+            r#"event: message-start"#,
+            r#"data: {"type":"message-start","id":"abc","delta":{"message":{"role":"assistant"}}}"#,
+            r#"data: {"type":"content-delta","index":0,"delta":{"message":{"content":{"text":"Let me "}}}}"#,
+            r#"data: {"type":"content-delta","index":0,"delta":{"message":{"content":{"text":"check"}}}}"#,
+            r#"data: {"type":"tool-call-start","index":0,"delta":{"message":{"tool_calls":{"id":"call_1","type":"function","function":{"name":"get_weather","arguments":""}}}}}"#,
+            r#"data: {"type":"tool-call-delta","index":0,"delta":{"message":{"tool_calls":{"function":{"arguments":"{\"city\":"}}}}}"#,
+            r#"data: {"type":"tool-call-delta","index":0,"delta":{"message":{"tool_calls":{"function":{"arguments":"\"Tokyo\"}"}}}}}"#,
+            r#"data: {"type":"tool-call-end","index":0}"#,
+            r#"data: {"type":"message-end","delta":{"finish_reason":"TOOL_CALL"}}"#,
+        ];
+        let stream = futures::stream::iter(
+            events
+                .iter()
+                .map(|e| Ok::<_, crate::Error>(format!("{}\n", e)))
+                .collect::<Vec<_>>(),
+        );
+
+        let response = provider.handle_stream(stream).await.unwrap();
+
+        assert_eq!(*chunks.lock().unwrap(), vec!["Let me ", "check"]);
+        assert_eq!(response.message.content, "Let me check");
+        let calls = response.message.tool_calls.unwrap();
+        assert_eq!(calls[0].function.name, "get_weather");
+        assert_eq!(calls[0].function.arguments, r#"{"city":"Tokyo"}"#);
+        assert_eq!(response.finish_reason, FinishReason::ToolCalls);
+    }
+}
@@ -0,0 +1,501 @@
+//! Scripted LLM provider for testing agent loops. Requires the `test-util` feature.
+
+use crate::types::{
+    AssistantMessage, FinishReason, FunctionCall, LLMResponse, Message, TimedMessage, ToolCall,
+    ToolDefinition, Usage,
+};
+use async_trait::async_trait;
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+/// Requests received by a [`MockProvider`]
+pub type MockCalls = Arc<Mutex<Vec<(Vec<Message>, Vec<ToolDefinition>)>>>;
+
+/// Provider returning scripted responses in order, recording every request.
+/// Fails with [`Error::Custom`](crate::Error::Custom) once the responses run out.
+///
+/// # Example
+/// ```
+/// use tiny_loop::{Agent, llm::mock::MockProvider};
+///
+/// # async fn example() -> tiny_loop::Result<()> {
+/// let llm = MockProvider::new()
+///     .tool_calls("", &[("call_1", "search", r#"{"query":"rust"}"#)])
+///     .text("Done");
+/// let calls = llm.calls();
+/// let mut agent = Agent::new(llm);
+/// assert_eq!(agent.chat("Search for rust").await?, "Done");
+/// assert_eq!(calls.lock().unwrap().len(), 2);
+/// # Ok(())
+/// # }
+/// ```
+pub struct MockProvider {
+    responses: VecDeque<crate::Result<LLMResponse>>,
+    calls: MockCalls,
+}
+
+impl Default for MockProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockProvider {
+    /// Create a provider without responses
+    pub fn new() -> Self {
+        Self {
+            responses: VecDeque::new(),
+            calls: MockCalls::default(),
+        }
+    }
+
+    /// Queue a response
+    pub fn respond(mut self, response: LLMResponse) -> Self {
+        self.responses.push_back(Ok(response));
+        self
+    }
+
+    /// Queue an error
+    pub fn fail(mut self, error: crate::Error) -> Self {
+        self.responses.push_back(Err(error));
+        self
+    }
+
+    /// Queue a final text response
+    pub fn text(self, content: impl Into<String>) -> Self {
+        self.finish(content, FinishReason::Stop)
+    }
+
+    /// Queue a text response with the given finish reason
+    pub fn finish(self, content: impl Into<String>, finish_reason: FinishReason) -> Self {
+        self.respond(LLMResponse {
+            message: AssistantMessage {
+                content: content.into(),
+                tool_calls: None,
+                refusal: None,
+                annotations: None,
+            },
+            finish_reason,
+            logprobs: None,
+            usage: None,
+        })
+    }
+
+    /// Queue a response requesting the given `(id, name, arguments)` tool calls
+    pub fn tool_calls(self, content: impl Into<String>, calls: &[(&str, &str, &str)]) -> Self {
+        self.respond(LLMResponse {
+            message: AssistantMessage {
+                content: content.into(),
+                tool_calls: Some(calls.iter().map(|(id, n, a)| tool_call(id, n, a)).collect()),
+                refusal: None,
+                annotations: None,
+            },
+            finish_reason: FinishReason::ToolCalls,
+            logprobs: None,
+            usage: None,
+        })
+    }
+
+    /// Report `usage` for the last queued response
+    pub fn usage(mut self, usage: Usage) -> Self {
+        if let Some(Ok(response)) = self.responses.back_mut() {
+            response.usage = Some(usage);
+        }
+        self
+    }
+
+    /// Shared handle to the recorded requests
+    pub fn calls(&self) -> MockCalls {
+        self.calls.clone()
+    }
+}
+
+/// Build a function tool call
+pub(crate) fn tool_call(id: &str, name: &str, arguments: &str) -> ToolCall {
+    ToolCall {
+        id: id.into(),
+        call_type: "function".into(),
+        function: FunctionCall {
+            name: name.into(),
+            arguments: arguments.into(),
+        },
+    }
+}
+
+/// Wrap a message as if it was just added to the history
+#[cfg(test)]
+pub(crate) fn timed(message: Message) -> TimedMessage {
+    TimedMessage {
+        message,
+        timestamp: std::time::SystemTime::now(),
+        elapsed: std::time::Duration::ZERO,
+        metadata: None,
+    }
+}
+
+#[async_trait]
+impl super::LLMProvider for MockProvider {
+    async fn call(
+        &mut self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+    ) -> crate::Result<LLMResponse> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push((messages.to_vec(), tools.to_vec()));
+        self.responses
+            .pop_front()
+            .unwrap_or_else(|| Err(crate::Error::Custom("No more mock responses".into())))
+    }
+}
+
+/// Provider answering instantly without recording requests, for benchmarking the loop machinery.
+/// Unlike [`MockProvider`], its script repeats forever.
+///
+/// # Example
+/// ```
+/// use tiny_loop::{Agent, llm::mock::NoopProvider, tool::ScriptedExecutor};
+///
+/// # async fn example() -> tiny_loop::Result<()> {
+/// // Every run makes 10 tool calls in a row, then answers "Done"
+/// let llm = NoopProvider::new("Done").tool_calls("search", "{}", 10);
+/// let mut agent = Agent::new(llm).executor(ScriptedExecutor::new());
+/// agent.chat("Go").await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct NoopProvider {
+    content: String,
+    call: Option<(String, String)>,
+    depth: usize,
+    step: usize,
+}
+
+impl NoopProvider {
+    /// Create a provider always answering `content`
+    pub fn new(content: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            call: None,
+            depth: 0,
+            step: 0,
+        }
+    }
+
+    /// Request `depth` calls of the tool `name` with `arguments` in a row before every answer
+    pub fn tool_calls(
+        mut self,
+        name: impl Into<String>,
+        arguments: impl Into<String>,
+        depth: usize,
+    ) -> Self {
+        self.call = Some((name.into(), arguments.into()));
+        self.depth = depth;
+        self
+    }
+}
+
+#[async_trait]
+impl super::LLMProvider for NoopProvider {
+    async fn call(
+        &mut self,
+        _messages: &[Message],
+        _tools: &[ToolDefinition],
+    ) -> crate::Result<LLMResponse> {
+        let step = self.step;
+        self.step = (step + 1) % (self.depth + 1);
+        let (tool_calls, finish_reason) = match &self.call {
+            Some((name, arguments)) if step < self.depth => (
+                Some(vec![tool_call(&format!("call_{}", step), name, arguments)]),
+                FinishReason::ToolCalls,
+            ),
+            _ => (None, FinishReason::Stop),
+        };
+        Ok(LLMResponse {
+            message: AssistantMessage {
+                content: if tool_calls.is_some() {
+                    String::new()
+                } else {
+                    self.content.clone()
+                },
+                tool_calls,
+                refusal: None,
+                annotations: None,
+            },
+            finish_reason,
+            logprobs: None,
+            usage: None,
+        })
+    }
+}
+
+/// Provider wrapper injecting delays and failures around another provider,
+/// to test retry, fallback and timeout handling.
+///
+/// Failures are drawn from a pseudo-random sequence seeded with `seed`,
+/// so the same seed always fails the same calls. A failed call doesn't reach the inner provider.
+/// Health checks draw from the same sequence and can fail too.
+///
+/// # Example
+/// ```
+/// use tiny_loop::{Agent, ErrorAction, llm::mock::{ChaosProvider, MockProvider}};
+/// use std::time::Duration;
+///
+/// let llm = ChaosProvider::new(MockProvider::new().text("Done"), 42)
+///     .delay(0.5, Duration::from_millis(100))
+///     .rate_limit(0.2)
+///     .server_error(0.1);
+/// let agent = Agent::new(llm).on_error(|_, _| ErrorAction::Retry);
+/// ```
+pub struct ChaosProvider<P> {
+    inner: P,
+    /// State of the pseudo-random sequence
+    state: AtomicU64,
+    delay: Option<(f64, std::time::Duration)>,
+    rate_limit: f64,
+    server_error: f64,
+    connection_drop: f64,
+}
+
+impl<P: super::LLMProvider> ChaosProvider<P> {
+    /// Wrap `inner` without injecting anything yet
+    pub fn new(inner: P, seed: u64) -> Self {
+        Self {
+            inner,
+            state: AtomicU64::new(seed),
+            delay: None,
+            rate_limit: 0.0,
+            server_error: 0.0,
+            connection_drop: 0.0,
+        }
+    }
+
+    /// Delay calls by `duration` with the given probability
+    pub fn delay(mut self, probability: f64, duration: std::time::Duration) -> Self {
+        self.delay = Some((probability, duration));
+        self
+    }
+
+    /// Fail calls with a `429` [`Error::ApiError`](crate::Error::ApiError) with the given probability
+    pub fn rate_limit(mut self, probability: f64) -> Self {
+        self.rate_limit = probability;
+        self
+    }
+
+    /// Fail calls with a `500` [`Error::ApiError`](crate::Error::ApiError) with the given probability
+    pub fn server_error(mut self, probability: f64) -> Self {
+        self.server_error = probability;
+        self
+    }
+
+    /// Fail calls as if the connection dropped with the given probability.
+    /// The error is an [`Error::Custom`](crate::Error::Custom)
+    pub fn connection_drop(mut self, probability: f64) -> Self {
+        self.connection_drop = probability;
+        self
+    }
+
+    /// The wrapped provider
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    /// Next number in `[0, 1)` of the pseudo-random sequence (SplitMix64)
+    fn roll(&self) -> f64 {
+        let mut z = self
+            .state
+            .fetch_add(0x9E37_79B9_7F4A_7C15, Ordering::Relaxed)
+            .wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        (z ^ (z >> 31)) as f64 / (u64::MAX as f64 + 1.0)
+    }
+
+    /// Sleep or fail according to the configured probabilities
+    async fn inject(&self) -> crate::Result<()> {
+        if let Some((probability, duration)) = self.delay
+            && self.roll() < probability
+        {
+            tracing::debug!("Chaos: delaying call by {:?}", duration);
+            tokio::time::sleep(duration).await;
+        }
+        let roll = self.roll();
+        let api_error = |status: u16, body: &str| crate::Error::ApiError {
+            status,
+            body: body.into(),
+        };
+        let error = if roll < self.rate_limit {
+            api_error(429, "Chaos: rate limited")
+        } else if roll < self.rate_limit + self.server_error {
+            api_error(500, "Chaos: server error")
+        } else if roll < self.rate_limit + self.server_error + self.connection_drop {
+            crate::Error::Custom("Chaos: connection dropped".into())
+        } else {
+            return Ok(());
+        };
+        tracing::debug!("Chaos: failing call with {}", error);
+        Err(error)
+    }
+}
+
+#[async_trait]
+impl<P: super::LLMProvider> super::LLMProvider for ChaosProvider<P> {
+    async fn call(
+        &mut self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+    ) -> crate::Result<LLMResponse> {
+        self.inject().await?;
+        self.inner.call(messages, tools).await
+    }
+
+    async fn call_with(
+        &mut self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        options: &super::CallOptions,
+    ) -> crate::Result<LLMResponse> {
+        self.inject().await?;
+        self.inner.call_with(messages, tools, options).await
+    }
+
+    async fn health_check(&self) -> crate::Result<()> {
+        self.inject().await?;
+        self.inner.health_check().await
+    }
+
+    fn streamed_content(&self) -> Option<&str> {
+        self.inner.streamed_content()
+    }
+}
+
+/// Minimal HTTP server for provider tests
+#[cfg(test)]
+pub(crate) struct MockServer;
+
+#[cfg(test)]
+impl MockServer {
+    /// Listen on a random local port and run `handler` for every connection with the raw request.
+    /// Returns the base URL of the server.
+    pub async fn spawn<F, Fut>(handler: F) -> String
+    where
+        F: Fn(tokio::net::TcpStream, String) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let request = read_request(&mut stream).await;
+                tokio::spawn(handler(stream, request));
+            }
+        });
+        format!("http://{}", addr)
+    }
+}
+
+/// Read an HTTP request (headers and `Content-Length` body) from the stream
+#[cfg(test)]
+async fn read_request(stream: &mut tokio::net::TcpStream) -> String {
+    use tokio::io::AsyncReadExt;
+
+    let mut data = Vec::new();
+    let mut buf = [0; 4096];
+    loop {
+        let n = stream.read(&mut buf).await.unwrap_or(0);
+        if n == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..n]);
+        let text = String::from_utf8_lossy(&data);
+        if let Some(header_end) = text.find("\r\n\r\n") {
+            let content_length = text[..header_end]
+                .lines()
+                .find_map(|l| {
+                    let (k, v) = l.split_once(':')?;
+                    k.eq_ignore_ascii_case("content-length")
+                        .then(|| v.trim().parse::<usize>().ok())?
+                })
+                .unwrap_or(0);
+            if data.len() >= header_end + 4 + content_length {
+                break;
+            }
+        }
+    }
+    String::from_utf8_lossy(&data).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::LLMProvider;
+
+    /// Outcome of `count` calls: the status of failed calls, or 200
+    async fn outcomes(seed: u64, count: usize) -> Vec<u16> {
+        let mut llm = ChaosProvider::new(NoopProvider::new("ok"), seed)
+            .rate_limit(0.3)
+            .server_error(0.2);
+        let mut outcomes = Vec::new();
+        for _ in 0..count {
+            outcomes.push(match llm.call(&[], &[]).await {
+                Ok(_) => 200,
+                Err(crate::Error::ApiError { status, .. }) => status,
+                Err(e) => panic!("unexpected error: {}", e),
+            });
+        }
+        outcomes
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_chaos_provider_is_deterministic() {
+        let first = outcomes(7, 50).await;
+        assert_eq!(first, outcomes(7, 50).await);
+        assert_ne!(first, outcomes(8, 50).await);
+        for status in [200, 429, 500] {
+            assert!(first.contains(&status), "{:?}", first);
+        }
+
+        let mut llm = ChaosProvider::new(NoopProvider::new("ok"), 7)
+            .delay(1.0, std::time::Duration::from_secs(30));
+        let start = tokio::time::Instant::now();
+        llm.call(&[], &[]).await.unwrap();
+        assert_eq!(start.elapsed(), std::time::Duration::from_secs(30));
+    }
+
+    #[tokio::test]
+    async fn test_chaos_provider_health_check() {
+        struct Unhealthy;
+
+        #[async_trait]
+        impl LLMProvider for Unhealthy {
+            async fn call(
+                &mut self,
+                _messages: &[Message],
+                _tools: &[ToolDefinition],
+            ) -> crate::Result<LLMResponse> {
+                unreachable!()
+            }
+
+            async fn health_check(&self) -> crate::Result<()> {
+                Err(crate::Error::Custom("down".into()))
+            }
+        }
+
+        let llm = ChaosProvider::new(Unhealthy, 7);
+        assert_eq!(llm.health_check().await.unwrap_err().to_string(), "down");
+
+        let llm = ChaosProvider::new(NoopProvider::new("ok"), 7).server_error(1.0);
+        assert!(matches!(
+            llm.health_check().await,
+            Err(crate::Error::ApiError { status: 500, .. })
+        ));
+        let llm = ChaosProvider::new(NoopProvider::new("ok"), 7);
+        llm.health_check().await.unwrap();
+    }
+}
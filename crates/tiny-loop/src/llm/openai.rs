@@ -1,8 +1,16 @@
-use crate::types::{FinishReason, LLMResponse, Message, ToolDefinition};
+use crate::types::{
+    FinishReason, LLMResponse, Logprobs, Message, ReasoningEffort, ToolChoice, ToolDefinition,
+    Usage,
+};
 use async_trait::async_trait;
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Default maximum size of a single unterminated SSE line (16 MiB)
+const DEFAULT_MAX_LINE_BYTES: usize = 16 * 1024 * 1024;
 
 /// Callback for streaming OpenAI responses
 pub type OpenAIStreamCallback = Box<dyn FnMut(String) + Send + Sync>;
@@ -16,9 +24,27 @@ struct ChatRequest {
     messages: Vec<Message>,
     /// Available tools for the model
     tools: Vec<ToolDefinition>,
+    /// Which tool the model must call
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<Value>,
     /// Enable streaming
     #[serde(skip_serializing_if = "Option::is_none")]
     stream: Option<bool>,
+    /// Store the completion for later retrieval
+    #[serde(skip_serializing_if = "Option::is_none")]
+    store: Option<bool>,
+    /// Return log probabilities of the output tokens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logprobs: Option<bool>,
+    /// Number of most likely tokens to return at each position
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_logprobs: Option<u8>,
+    /// Reasoning effort of reasoning models
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<ReasoningEffort>,
+    /// Metadata attached to stored completions
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    metadata: HashMap<String, String>,
 }
 
 /// Response from OpenAI chat completions API
@@ -26,12 +52,32 @@ struct ChatRequest {
 struct ChatResponse {
     /// List of completion choices
     choices: Vec<Choice>,
+    /// Token usage of the call
+    #[serde(default)]
+    usage: Option<Usage>,
+}
+
+/// Response from OpenAI embeddings API
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<Embedding>,
+}
+
+/// One embedding of an [`EmbeddingResponse`]
+#[derive(Deserialize)]
+struct Embedding {
+    /// Position of the input
+    index: usize,
+    embedding: Vec<f32>,
 }
 
 /// Streaming response chunk
 #[derive(Deserialize)]
 struct StreamChunk {
     choices: Vec<StreamChoice>,
+    /// Token usage, sent in the last chunk if requested with `stream_options`
+    #[serde(default)]
+    usage: Option<Usage>,
 }
 
 /// Streaming choice
@@ -40,6 +86,8 @@ struct StreamChoice {
     delta: Delta,
     #[serde(default)]
     finish_reason: Option<FinishReason>,
+    #[serde(default)]
+    logprobs: Option<Logprobs>,
 }
 
 /// Delta content in streaming
@@ -47,6 +95,15 @@ struct StreamChoice {
 struct Delta {
     #[serde(default)]
     content: Option<String>,
+    /// Reasoning fragment from reasoning models (`reasoning_content` on DeepSeek-style APIs)
+    #[serde(default, alias = "reasoning_content")]
+    reasoning: Option<String>,
+    /// Refusal fragment returned instead of content
+    #[serde(default)]
+    refusal: Option<String>,
+    /// Citations, usually sent in the last content chunk
+    #[serde(default)]
+    annotations: Option<Vec<crate::types::Annotation>>,
     #[serde(default)]
     tool_calls: Option<Vec<crate::types::ToolCall>>,
 }
@@ -58,6 +115,9 @@ struct Choice {
     message: Message,
     /// Reason the completion finished
     finish_reason: FinishReason,
+    /// Log probabilities of the output tokens
+    #[serde(default)]
+    logprobs: Option<Logprobs>,
 }
 
 /// OpenAI-compatible LLM provider
@@ -80,16 +140,65 @@ pub struct OpenAIProvider {
     api_key: String,
     /// Model identifier
     model: String,
+    /// Model identifier for embeddings
+    embedding_model: String,
+    /// `OpenAI-Organization` header value
+    organization: Option<String>,
+    /// `OpenAI-Project` header value
+    project: Option<String>,
+    /// `User-Agent` header value
+    user_agent: String,
     /// Additional HTTP headers
     custom_headers: HeaderMap,
     /// Maximum number of retries on failure
     max_retries: u32,
     /// Delay between retries in milliseconds
     retry_delay_ms: u64,
+    /// Custom retry policy replacing `max_retries` and `retry_delay_ms`
+    retry_policy: Option<Box<dyn super::RetryPolicy>>,
     /// Custom body fields to merge into the request
     custom_body: Map<String, Value>,
     /// Stream callback for LLM responses
     stream_callback: Option<OpenAIStreamCallback>,
+    /// Content chunks of the last call, as passed to the stream callback.
+    /// `None` if the last call was not streamed
+    streamed_content: Option<String>,
+    /// Stream callback for reasoning fragments
+    reasoning_callback: Option<OpenAIStreamCallback>,
+    /// Stream callback for complete lines of content
+    line_callback: Option<OpenAIStreamCallback>,
+    /// Stream callback for the content parsed as partial JSON
+    partial_json_callback: Option<Box<dyn FnMut(Value) + Send + Sync>>,
+    /// Last error from the writer set by `stream_to_writer`
+    stream_write_error: Arc<Mutex<Option<std::io::Error>>>,
+    /// Whether to store completions on the provider side
+    store: Option<bool>,
+    /// Metadata attached to stored completions
+    metadata: HashMap<String, String>,
+    /// Maximum size of a single unterminated SSE line in bytes
+    max_line_bytes: usize,
+    /// Stop sequences checked by the client
+    client_stop: Vec<String>,
+    /// JSON Pointer to the chat completion in non-streamed response bodies
+    response_pointer: Option<String>,
+    /// Which tool the model must call
+    tool_choice: Option<ToolChoice>,
+    /// Return log probabilities of the output tokens
+    logprobs: Option<bool>,
+    /// Number of most likely tokens to return at each position
+    top_logprobs: Option<u8>,
+    /// Reasoning effort of reasoning models
+    reasoning_effort: Option<ReasoningEffort>,
+    /// System prompt prepended when the messages don't start with one
+    default_system: Option<String>,
+    /// Max reconnections to resume a dropped stream with `Last-Event-ID`
+    stream_resume_retries: u32,
+    /// Send tool results as legacy `function` role messages
+    legacy_function_role: bool,
+    /// Omit `parameters` of tools without parameters
+    omit_empty_parameters: bool,
+    /// Hook transforming the final request body
+    on_serialize: Option<Box<dyn Fn(Value) -> Value + Send + Sync>>,
 }
 
 impl Default for OpenAIProvider {
@@ -114,12 +223,81 @@ impl OpenAIProvider {
             base_url: "https://api.openai.com/v1".into(),
             api_key: "".into(),
             model: "gpt-4o".into(),
+            embedding_model: "text-embedding-3-small".into(),
+            organization: None,
+            project: None,
+            user_agent: concat!("tiny-loop/", env!("CARGO_PKG_VERSION")).into(),
             custom_headers: HeaderMap::new(),
             max_retries: 3,
             retry_delay_ms: 1000,
+            retry_policy: None,
             custom_body: Map::new(),
             stream_callback: None,
+            streamed_content: None,
+            reasoning_callback: None,
+            line_callback: None,
+            partial_json_callback: None,
+            stream_write_error: Arc::new(Mutex::new(None)),
+            store: None,
+            metadata: HashMap::new(),
+            max_line_bytes: DEFAULT_MAX_LINE_BYTES,
+            client_stop: Vec::new(),
+            response_pointer: None,
+            stream_resume_retries: 0,
+            legacy_function_role: false,
+            omit_empty_parameters: false,
+            on_serialize: None,
+            tool_choice: None,
+            logprobs: None,
+            top_logprobs: None,
+            reasoning_effort: None,
+            default_system: None,
+        }
+    }
+
+    /// Create a provider configured from the environment
+    ///
+    /// Reads `OPENAI_API_KEY`, `OPENAI_BASE_URL`, `OPENAI_ORG` and `OPENAI_MODEL`.
+    /// Unset or empty variables keep the defaults of [`new`](Self::new).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    ///
+    /// let provider = OpenAIProvider::from_env();
+    /// ```
+    pub fn from_env() -> Self {
+        Self::from_env_with(|name| std::env::var(name).ok())
+    }
+
+    /// Create a provider configured like [`from_env`](Self::from_env),
+    /// looking up the variables with `lookup` instead of the process environment
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    ///
+    /// let provider = OpenAIProvider::from_env_with(|name| match name {
+    ///     "OPENAI_MODEL" => Some("gpt-4o-mini".into()),
+    ///     _ => None,
+    /// });
+    /// ```
+    pub fn from_env_with(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let var = |name| lookup(name).filter(|v| !v.is_empty());
+        let mut provider = Self::new();
+        if let Some(api_key) = var("OPENAI_API_KEY") {
+            provider.api_key = api_key;
+        }
+        if let Some(base_url) = var("OPENAI_BASE_URL") {
+            provider.base_url = base_url;
+        }
+        if let Some(model) = var("OPENAI_MODEL") {
+            provider.model = model;
         }
+        provider.organization = var("OPENAI_ORG");
+        provider
     }
 
     /// Set the base URL for the API endpoint (default: `https://api.openai.com/v1`)
@@ -167,7 +345,69 @@ impl OpenAIProvider {
         self
     }
 
-    /// Add a custom HTTP header to requests
+    /// Set the model used by [`EmbeddingProvider::embed`](super::EmbeddingProvider::embed)
+    /// (default: `text-embedding-3-small`)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .embedding_model("text-embedding-3-large");
+    /// ```
+    pub fn embedding_model(mut self, value: impl Into<String>) -> Self {
+        self.embedding_model = value.into();
+        self
+    }
+
+    /// Set the organization used for billing attribution (`OpenAI-Organization` header)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .organization("org-123");
+    /// ```
+    pub fn organization(mut self, value: impl Into<String>) -> Self {
+        self.organization = Some(value.into());
+        self
+    }
+
+    /// Set the project used for billing attribution (`OpenAI-Project` header)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .project("proj_123");
+    /// ```
+    pub fn project(mut self, value: impl Into<String>) -> Self {
+        self.project = Some(value.into());
+        self
+    }
+
+    /// Set the `User-Agent` header identifying the client (default: `tiny-loop/<version>`)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .user_agent("my-app/1.0 tiny-loop");
+    /// ```
+    pub fn user_agent(mut self, value: impl Into<String>) -> Self {
+        self.user_agent = value.into();
+        self
+    }
+
+    /// Add a custom HTTP header to requests.
+    /// Replaces the default `Content-Type` and `Accept` headers if set
     ///
     /// # Examples
     ///
@@ -198,6 +438,8 @@ impl OpenAIProvider {
 
     /// Set maximum number of retries on failure (default: 3)
     ///
+    /// Ignored if a [`retry_policy`](Self::retry_policy) is set.
+    ///
     /// # Examples
     ///
     /// ```
@@ -213,6 +455,8 @@ impl OpenAIProvider {
 
     /// Set delay between retries in milliseconds (default: 1000)
     ///
+    /// Ignored if a [`retry_policy`](Self::retry_policy) is set.
+    ///
     /// # Examples
     ///
     /// ```
@@ -226,6 +470,44 @@ impl OpenAIProvider {
         self
     }
 
+    /// Set a custom retry policy (default: [`ConstantBackoff`](super::ConstantBackoff)
+    /// from [`max_retries`](Self::max_retries) and [`retry_delay`](Self::retry_delay))
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::{ExponentialBackoff, OpenAIProvider};
+    /// use std::time::Duration;
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .retry_policy(ExponentialBackoff::new(Duration::from_millis(500), 5));
+    ///
+    /// // Don't retry authentication errors
+    /// let provider = OpenAIProvider::new()
+    ///     .retry_policy(|attempt: u32, status: Option<u16>| match status {
+    ///         Some(401) => None,
+    ///         _ => (attempt <= 3).then_some(Duration::from_secs(1)),
+    ///     });
+    /// ```
+    pub fn retry_policy(mut self, policy: impl super::RetryPolicy + 'static) -> Self {
+        self.retry_policy = Some(Box::new(policy));
+        self
+    }
+
+    /// Delay before retrying after the given failed attempt, or `None` to stop
+    fn retry_delay_for(&self, attempt: u32, status: Option<u16>) -> Option<std::time::Duration> {
+        use super::RetryPolicy;
+
+        match &self.retry_policy {
+            Some(policy) => policy.delay(attempt, status),
+            None => super::ConstantBackoff::new(
+                std::time::Duration::from_millis(self.retry_delay_ms),
+                self.max_retries,
+            )
+            .delay(attempt, status),
+        }
+    }
+
     /// Set custom body fields to merge into the request
     ///
     /// # Examples
@@ -252,6 +534,10 @@ impl OpenAIProvider {
 
     /// Set stream callback for LLM responses
     ///
+    /// Streaming requires `n = 1`; calls fail with
+    /// [`Error::StreamingMultipleChoices`](crate::Error::StreamingMultipleChoices)
+    /// if a larger `n` is set via [`body`](Self::body).
+    ///
     /// # Examples
     ///
     /// ```
@@ -267,162 +553,2027 @@ impl OpenAIProvider {
         self.stream_callback = Some(Box::new(callback));
         self
     }
-}
-
-#[async_trait]
-impl super::LLMProvider for OpenAIProvider {
-    async fn call(
-        &mut self,
-        messages: &[Message],
-        tools: &[ToolDefinition],
-    ) -> crate::Result<LLMResponse> {
-        let mut attempt = 0;
-        loop {
-            attempt += 1;
-            tracing::debug!(
-                model = %self.model,
-                messages = messages.len(),
-                tools = tools.len(),
-                streaming = self.stream_callback.is_some(),
-                attempt = attempt,
-                max_retries = self.max_retries,
-                "Calling LLM API"
-            );
 
-            match self.call_once(messages, tools).await {
-                Ok(response) => return Ok(response),
-                Err(e) if attempt > self.max_retries => {
-                    tracing::debug!("Max retries exceeded");
-                    return Err(e);
-                }
-                Err(e) => {
-                    tracing::debug!("API call failed, retrying: {}", e);
-                    tokio::time::sleep(tokio::time::Duration::from_millis(self.retry_delay_ms))
-                        .await;
-                }
+    /// Stream the content to a writer, flushing after each chunk
+    ///
+    /// Replaces the [`stream_callback`](Self::stream_callback).
+    /// Write errors don't interrupt the call; the last one is kept and can be
+    /// retrieved with [`take_stream_write_error`](Self::take_stream_write_error).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .stream_to_writer(std::io::stdout());
+    /// ```
+    pub fn stream_to_writer(self, writer: impl std::io::Write + Send + 'static) -> Self {
+        let writer = Mutex::new(writer);
+        let last_error = self.stream_write_error.clone();
+        self.stream_callback(move |chunk| {
+            let mut writer = writer.lock().unwrap();
+            if let Err(e) = writer
+                .write_all(chunk.as_bytes())
+                .and_then(|_| writer.flush())
+            {
+                tracing::warn!("Failed to write stream chunk: {}", e);
+                *last_error.lock().unwrap() = Some(e);
             }
-        }
+        })
     }
-}
-
-impl OpenAIProvider {
-    async fn call_once(
-        &mut self,
-        messages: &[Message],
-        tools: &[ToolDefinition],
-    ) -> crate::Result<LLMResponse> {
-        let request = ChatRequest {
-            model: self.model.clone(),
-            messages: messages.to_vec(),
-            tools: tools.to_vec(),
-            stream: if self.stream_callback.is_some() {
-                Some(true)
-            } else {
-                None
-            },
-        };
-
-        let mut body = serde_json::to_value(&request)?.as_object().unwrap().clone();
-        body.extend(self.custom_body.clone());
-
-        let response = self
-            .client
-            .post(format!("{}/chat/completions", self.base_url))
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .headers(self.custom_headers.clone())
-            .json(&body)
-            .send()
-            .await?;
-
-        let status = response.status();
-        tracing::trace!("LLM API response status: {}", status);
 
-        if !status.is_success() {
-            let body = response.text().await?;
-            tracing::debug!("LLM API error: status={}, body={}", status, body);
-            return Err(crate::Error::ApiError {
-                status: status.as_u16(),
-                body,
-            });
-        }
+    /// Take the last write error from the writer set by
+    /// [`stream_to_writer`](Self::stream_to_writer), if any
+    pub fn take_stream_write_error(&self) -> Option<std::io::Error> {
+        self.stream_write_error.lock().unwrap().take()
+    }
 
-        if self.stream_callback.is_some() {
-            self.handle_stream(response).await
-        } else {
-            let body = response.text().await?;
-            let chat_response: ChatResponse = serde_json::from_str(&body).map_err(|e| {
-                crate::Error::Custom(format!("Failed to parse response: {}. Body: {}", e, body))
-            })?;
-            tracing::debug!("LLM API call completed successfully");
-            let choice = &chat_response.choices[0];
-            let Message::Assistant(msg) = &choice.message else {
-                return Err(crate::Error::UnexpectedMessage(format!(
-                    "{:?}",
-                    choice.message
-                )));
-            };
-            Ok(LLMResponse {
-                message: msg.clone(),
-                finish_reason: choice.finish_reason.clone(),
-            })
-        }
+    /// Set stream callback for reasoning fragments from reasoning models (e.g. o1, R1)
+    ///
+    /// Fires independently of [`stream_callback`](Self::stream_callback),
+    /// so reasoning and the answer can be rendered separately.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .reasoning_callback(|chunk| eprint!("{}", chunk))
+    ///     .stream_callback(|chunk| print!("{}", chunk));
+    /// ```
+    pub fn reasoning_callback<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(String) + Send + Sync + 'static,
+    {
+        self.reasoning_callback = Some(Box::new(callback));
+        self
     }
 
-    async fn handle_stream(&mut self, response: reqwest::Response) -> crate::Result<LLMResponse> {
-        use futures::TryStreamExt;
+    /// Set stream callback receiving the content one complete line at a time
+    ///
+    /// Streamed content is buffered until a newline, then the line is passed
+    /// without the trailing newline. The remainder is flushed when the stream ends.
+    /// Useful for rendering markdown line by line. Can be combined with
+    /// [`stream_callback`](Self::stream_callback).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .line_callback(|line| println!("{}", line));
+    /// ```
+    pub fn line_callback<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(String) + Send + Sync + 'static,
+    {
+        self.line_callback = Some(Box::new(callback));
+        self
+    }
 
-        let mut stream = response.bytes_stream();
-        let mut buffer = String::new();
-        let mut content = String::new();
-        let mut tool_calls = Vec::new();
-        let mut finish_reason = FinishReason::Stop;
+    /// Set stream callback receiving the content received so far parsed as JSON,
+    /// for structured output (default: none)
+    ///
+    /// Called after every content fragment with a best-effort value, see
+    /// [`parse_partial_json`](super::parse_partial_json), so a UI can render fields
+    /// as they're generated. Can be combined with [`stream_callback`](Self::stream_callback),
+    /// which receives the raw fragments.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    /// use serde_json::json;
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .body(json!({"response_format": {"type": "json_object"}}))
+    ///     .unwrap()
+    ///     .partial_json_callback(|value| println!("{}", value));
+    /// ```
+    pub fn partial_json_callback<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(Value) + Send + Sync + 'static,
+    {
+        self.partial_json_callback = Some(Box::new(callback));
+        self
+    }
 
-        while let Some(chunk) = stream.try_next().await? {
-            buffer.push_str(&String::from_utf8_lossy(&chunk));
+    /// Set whether to store completions for the provider's dashboard and later retrieval (default: unset)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .store(true);
+    /// ```
+    pub fn store(mut self, store: bool) -> Self {
+        self.store = Some(store);
+        self
+    }
 
-            while let Some(line_end) = buffer.find('\n') {
-                let line = buffer[..line_end].trim().to_string();
-                buffer.drain(..=line_end);
+    /// Set metadata attached to stored completions (default: empty)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    /// use std::collections::HashMap;
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .store(true)
+    ///     .metadata(HashMap::from([("team".to_string(), "search".to_string())]));
+    /// ```
+    pub fn metadata(mut self, metadata: HashMap<String, String>) -> Self {
+        self.metadata = metadata;
+        self
+    }
 
-                if let Some(data) = line.strip_prefix("data: ") {
-                    if data == "[DONE]" {
-                        break;
-                    }
+    /// Set the maximum size in bytes of a single streamed line (default: 16 MiB)
+    ///
+    /// Streaming fails with [`Error::StreamLineTooLong`](crate::Error::StreamLineTooLong)
+    /// if the server sends a longer line without a newline, instead of buffering without bound.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .max_line_bytes(1024 * 1024);
+    /// ```
+    pub fn max_line_bytes(mut self, limit: usize) -> Self {
+        self.max_line_bytes = limit;
+        self
+    }
 
-                    if let Ok(chunk) = serde_json::from_str::<StreamChunk>(data) {
-                        if let Some(choice) = chunk.choices.first() {
-                            if let Some(delta_content) = &choice.delta.content {
-                                content.push_str(delta_content);
-                                if let Some(callback) = &mut self.stream_callback {
-                                    callback(delta_content.clone());
-                                }
-                            }
-
-                            if let Some(delta_tool_calls) = &choice.delta.tool_calls {
-                                tool_calls.extend(delta_tool_calls.clone());
-                            }
-
-                            if let Some(reason) = &choice.finish_reason {
-                                finish_reason = reason.clone();
-                            }
-                        }
-                    }
-                }
-            }
-        }
+    /// Stop generating when the content contains one of these strings, checked by the client
+    /// (default: none)
+    ///
+    /// Unlike a provider-side `stop` parameter, this works with any backend.
+    /// The content is cut before the stop string and the stream is closed early,
+    /// with [`FinishReason::Stop`]. Stream callbacks don't receive the stop string,
+    /// unless it is split across chunks, in which case its beginning has already been passed on.
+    /// Non-streamed responses are cut as well, keeping the finish reason if they call tools.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .client_stop(["</answer>"]);
+    /// ```
+    pub fn client_stop(mut self, stops: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.client_stop = stops
+            .into_iter()
+            .map(Into::into)
+            .filter(|s: &String| !s.is_empty())
+            .collect();
+        self
+    }
+
+    /// Read the chat completion at this [JSON Pointer](https://www.rfc-editor.org/rfc/rfc6901)
+    /// in non-streamed response bodies (default: the root)
+    ///
+    /// For gateways wrapping the standard response, e.g. `{"data": {"choices": [...]}}`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .response_pointer("/data");
+    /// ```
+    pub fn response_pointer(mut self, pointer: impl Into<String>) -> Self {
+        self.response_pointer = Some(pointer.into());
+        self
+    }
+
+    /// Cut the content of a non-streamed response before the first client stop sequence.
+    /// The finish reason becomes [`FinishReason::Stop`] unless the response calls tools
+    fn cut_at_client_stop(&self, response: &mut LLMResponse) {
+        let Some(index) = self.find_client_stop(&response.message.content, 0) else {
+            return;
+        };
+        response.message.content.truncate(index);
+        if response
+            .message
+            .tool_calls
+            .as_ref()
+            .is_none_or(|calls| calls.is_empty())
+        {
+            response.finish_reason = FinishReason::Stop;
+        }
+    }
+
+    /// Byte index of the first client stop sequence in `content`,
+    /// only searching the part that may contain a match not in `content[..from]`
+    fn find_client_stop(&self, content: &str, from: usize) -> Option<usize> {
+        let longest = self.client_stop.iter().map(String::len).max()?;
+        let mut from = from.saturating_sub(longest - 1);
+        while !content.is_char_boundary(from) {
+            from -= 1;
+        }
+        self.client_stop
+            .iter()
+            .filter_map(|stop| content[from..].find(stop.as_str()))
+            .min()
+            .map(|index| from + index)
+    }
+
+    /// Set the max number of reconnections to resume a dropped stream (default: 0, disabled)
+    ///
+    /// When a streamed response fails mid-stream, the request is reissued with the
+    /// SSE `Last-Event-ID` header and the resumed events are appended to the
+    /// partial response. Only enable this for providers that support resumption.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .stream_callback(|chunk| print!("{}", chunk))
+    ///     .stream_resume_retries(3);
+    /// ```
+    pub fn stream_resume_retries(mut self, retries: u32) -> Self {
+        self.stream_resume_retries = retries;
+        self
+    }
+
+    /// Set which tool the model must call (default: unset, the API decides)
+    ///
+    /// This applies to every call, so a forcing choice like [`ToolChoice::Required`]
+    /// prevents the agent loop from finishing; change it between steps as needed.
+    /// [`ToolChoice::RequiredFrom`] is emulated by sending `"required"` and
+    /// filtering the `tools` array to the named tools.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::{llm::OpenAIProvider, types::ToolChoice};
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .tool_choice(ToolChoice::RequiredFrom(vec!["search".into(), "fetch".into()]));
+    /// ```
+    pub fn tool_choice(mut self, choice: ToolChoice) -> Self {
+        self.tool_choice = Some(choice);
+        self
+    }
+
+    /// Send tool results as legacy `function` role messages (default: `false`)
+    ///
+    /// For backends that only support the deprecated function calling format,
+    /// where results use `"role": "function"` with the function `name`
+    /// instead of `"role": "tool"` with a `tool_call_id`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .legacy_function_role(true);
+    /// ```
+    pub fn legacy_function_role(mut self, enabled: bool) -> Self {
+        self.legacy_function_role = enabled;
+        self
+    }
+
+    /// Set the reasoning effort of reasoning models, sent as `reasoning_effort` (default: unset)
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::{llm::OpenAIProvider, types::ReasoningEffort};
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .model("o3-mini")
+    ///     .reasoning_effort(ReasoningEffort::High);
+    /// ```
+    pub fn reasoning_effort(mut self, effort: ReasoningEffort) -> Self {
+        self.reasoning_effort = Some(effort);
+        self
+    }
+
+    /// Omit the `parameters` field of tools without parameters (default: `false`)
+    ///
+    /// By default tools without parameters are sent with an empty object schema,
+    /// which is what the OpenAI API expects. Some providers prefer the field omitted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .omit_empty_parameters(true);
+    /// ```
+    pub fn omit_empty_parameters(mut self, enabled: bool) -> Self {
+        self.omit_empty_parameters = enabled;
+        self
+    }
+
+    /// Set a hook transforming the final request body before it's sent (default: none)
+    ///
+    /// Applied after all other options, including [`body`](Self::body),
+    /// for providers expecting fields that can't be configured otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    ///
+    /// // A provider expecting `max_tokens` instead of `max_completion_tokens`
+    /// let provider = OpenAIProvider::new().on_serialize(|mut body| {
+    ///     if let Some(max) = body.as_object_mut().and_then(|b| b.remove("max_completion_tokens")) {
+    ///         body["max_tokens"] = max;
+    ///     }
+    ///     body
+    /// });
+    /// ```
+    pub fn on_serialize(mut self, hook: impl Fn(Value) -> Value + Send + Sync + 'static) -> Self {
+        self.on_serialize = Some(Box::new(hook));
+        self
+    }
+
+    /// Set a system prompt prepended to requests whose messages don't start with a system message (default: unset)
+    ///
+    /// Useful to share a base policy between agents. If the agent has its own
+    /// first system message (e.g. from [`Agent::system`](crate::Agent::system)),
+    /// that one takes precedence and the default is not sent.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .default_system("Never reveal internal URLs");
+    /// ```
+    pub fn default_system(mut self, content: impl Into<String>) -> Self {
+        self.default_system = Some(content.into());
+        self
+    }
+
+    /// Set whether to return log probabilities of the output tokens (default: unset)
+    ///
+    /// The log probabilities are returned in [`LLMResponse::logprobs`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .logprobs(true);
+    /// ```
+    pub fn logprobs(mut self, enabled: bool) -> Self {
+        self.logprobs = Some(enabled);
+        self
+    }
+
+    /// Set the number of most likely tokens (0-20) to return at each position (default: unset)
+    ///
+    /// Requires [`logprobs`](Self::logprobs) to be enabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use tiny_loop::llm::OpenAIProvider;
+    ///
+    /// let provider = OpenAIProvider::new()
+    ///     .logprobs(true)
+    ///     .top_logprobs(3);
+    /// ```
+    pub fn top_logprobs(mut self, count: u8) -> Self {
+        self.top_logprobs = Some(count);
+        self
+    }
+
+    /// Whether responses should be requested as a stream
+    fn is_streaming(&self) -> bool {
+        self.stream_callback.is_some()
+            || self.reasoning_callback.is_some()
+            || self.line_callback.is_some()
+            || self.partial_json_callback.is_some()
+    }
+}
+
+#[async_trait]
+impl super::LLMProvider for OpenAIProvider {
+    async fn call(
+        &mut self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+    ) -> crate::Result<LLMResponse> {
+        self.call_with(messages, tools, &super::CallOptions::default())
+            .await
+    }
+
+    async fn call_with(
+        &mut self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        options: &super::CallOptions,
+    ) -> crate::Result<LLMResponse> {
+        let streaming = options.stream.unwrap_or(self.is_streaming());
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            tracing::debug!(
+                model = %self.model,
+                messages = messages.len(),
+                tools = tools.len(),
+                streaming = streaming,
+                attempt = attempt,
+                "Calling LLM API"
+            );
+
+            match self
+                .call_once(messages, tools, streaming, options.request_id.as_deref())
+                .await
+            {
+                Ok(response) => return Ok(response),
+                Err(e) => {
+                    let status = match &e {
+                        crate::Error::ApiError { status, .. } => Some(*status),
+                        _ => None,
+                    };
+                    let Some(delay) = self.retry_delay_for(attempt, status) else {
+                        tracing::debug!("Not retrying after attempt {}", attempt);
+                        return Err(e);
+                    };
+                    if let Some(budget) = &options.retry_budget
+                        && !budget.try_consume()
+                    {
+                        tracing::debug!("Retry budget exhausted after attempt {}", attempt);
+                        return Err(e);
+                    }
+                    tracing::debug!("API call failed, retrying in {:?}: {}", delay, e);
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        }
+    }
+
+    fn streamed_content(&self) -> Option<&str> {
+        self.streamed_content.as_deref()
+    }
+
+    /// List the models (`GET /models`), which validates the API key without generating tokens
+    async fn health_check(&self) -> crate::Result<()> {
+        tracing::debug!("Checking LLM API health");
+        let request = self.authorize(self.client.get(format!("{}/models", self.base_url)));
+        Self::send(request).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl super::EmbeddingProvider for OpenAIProvider {
+    async fn embed(&self, inputs: Vec<String>) -> crate::Result<Vec<Vec<f32>>> {
+        tracing::debug!(
+            model = %self.embedding_model,
+            inputs = inputs.len(),
+            "Calling embeddings API"
+        );
+        let body = serde_json::json!({
+            "model": self.embedding_model,
+            "input": inputs,
+        });
+        let request = self
+            .client
+            .post(format!("{}/embeddings", self.base_url))
+            .header("Content-Type", "application/json");
+        let body = Self::send(self.authorize(request).json(&body))
+            .await?
+            .text()
+            .await?;
+        let mut response: EmbeddingResponse = serde_json::from_str(&body).map_err(|e| {
+            crate::Error::Custom(format!(
+                "Failed to parse embeddings response: {}. Body: {}",
+                e, body
+            ))
+        })?;
+        response.data.sort_by_key(|e| e.index);
+        Ok(response.data.into_iter().map(|e| e.embedding).collect())
+    }
+}
+
+impl OpenAIProvider {
+    /// Build the HTTP request for a chat completion call
+    #[cfg(test)]
+    fn build_request(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+    ) -> crate::Result<reqwest::RequestBuilder> {
+        self.build_request_with(messages, tools, self.is_streaming(), None)
+    }
+
+    /// Build the HTTP request for a chat completion call, streamed or not,
+    /// with the `X-Request-Id` header if `request_id` is set
+    fn build_request_with(
+        &self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        streaming: bool,
+        request_id: Option<&str>,
+    ) -> crate::Result<reqwest::RequestBuilder> {
+        let tools: Vec<_> = match &self.tool_choice {
+            Some(ToolChoice::RequiredFrom(names)) => tools
+                .iter()
+                .filter(|t| names.contains(&t.function.name))
+                .cloned()
+                .collect(),
+            _ => tools.to_vec(),
+        };
+        let empty_parameters: Vec<_> = tools
+            .iter()
+            .map(|t| t.function.parameters.is_empty())
+            .collect();
+        let tool_choice = self.tool_choice.as_ref().map(|choice| match choice {
+            ToolChoice::Auto => Value::from("auto"),
+            ToolChoice::None => Value::from("none"),
+            ToolChoice::Required | ToolChoice::RequiredFrom(_) => Value::from("required"),
+            ToolChoice::Function(name) => {
+                serde_json::json!({"type": "function", "function": {"name": name}})
+            }
+        });
+        let mut messages = messages.to_vec();
+        for message in &mut messages {
+            if let Message::Assistant(m) = message {
+                m.annotations = None;
+            }
+        }
+        if let Some(content) = &self.default_system
+            && !matches!(messages.first(), Some(Message::System(_)))
+        {
+            messages.insert(
+                0,
+                crate::types::SystemMessage {
+                    content: content.clone(),
+                }
+                .into(),
+            );
+        }
+        let request = ChatRequest {
+            model: self.model.clone(),
+            messages,
+            tools,
+            tool_choice,
+            stream: if streaming { Some(true) } else { None },
+            store: self.store,
+            logprobs: self.logprobs,
+            top_logprobs: self.top_logprobs,
+            reasoning_effort: self.reasoning_effort,
+            metadata: self.metadata.clone(),
+        };
+
+        let mut body = serde_json::to_value(&request)?.as_object().unwrap().clone();
+        if self.legacy_function_role
+            && let Some(Value::Array(messages)) = body.get_mut("messages")
+        {
+            to_function_role(messages);
+        }
+        if self.omit_empty_parameters
+            && let Some(Value::Array(tools)) = body.get_mut("tools")
+        {
+            for (tool, empty) in tools.iter_mut().zip(empty_parameters) {
+                if empty && let Some(function) = tool["function"].as_object_mut() {
+                    function.remove("parameters");
+                }
+            }
+        }
+        body.extend(self.custom_body.clone());
+        let body = match &self.on_serialize {
+            Some(hook) => hook(Value::Object(body)),
+            None => Value::Object(body),
+        };
+
+        // Most providers reject streaming multiple choices with an opaque error
+        if streaming
+            && let Some(n) = body.get("n").and_then(Value::as_u64)
+            && n > 1
+        {
+            return Err(crate::Error::StreamingMultipleChoices(n));
+        }
+
+        let mut request = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .header("Content-Type", "application/json");
+        if streaming {
+            // Some strict SSE proxies only stream when asked for events explicitly
+            request = request.header("Accept", "text/event-stream");
+        }
+        if let Some(request_id) = request_id {
+            request = request.header("X-Request-Id", request_id);
+        }
+        Ok(self.authorize(request).json(&body))
+    }
+
+    /// Add the authorization, billing attribution, user agent and custom headers
+    fn authorize(&self, mut request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        request = request
+            .header("Authorization", format!("Bearer {}", self.api_key))
+            .header("User-Agent", &self.user_agent);
+        if let Some(organization) = &self.organization {
+            request = request.header("OpenAI-Organization", organization);
+        }
+        if let Some(project) = &self.project {
+            request = request.header("OpenAI-Project", project);
+        }
+        request.headers(self.custom_headers.clone())
+    }
+
+    /// Send the request and turn non-success statuses into [`Error::ApiError`](crate::Error::ApiError)
+    async fn send(request: reqwest::RequestBuilder) -> crate::Result<reqwest::Response> {
+        let response = request.send().await?;
+
+        let status = response.status();
+        tracing::trace!("LLM API response status: {}", status);
+
+        if !status.is_success() {
+            let body = response.text().await?;
+            tracing::debug!("LLM API error: status={}, body={}", status, body);
+            return Err(crate::Error::ApiError {
+                status: status.as_u16(),
+                body,
+            });
+        }
+
+        Ok(response)
+    }
+
+    async fn call_once(
+        &mut self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        streaming: bool,
+        request_id: Option<&str>,
+    ) -> crate::Result<LLMResponse> {
+        self.streamed_content = None;
+        let mut response =
+            Self::send(self.build_request_with(messages, tools, streaming, request_id)?).await?;
+
+        if streaming {
+            self.streamed_content = Some(String::new());
+            let mut state = StreamState::default();
+            let mut resumes = 0;
+            loop {
+                match self.read_stream(&mut state, response.bytes_stream()).await {
+                    Ok(()) => return Ok(state.into_response()),
+                    Err(crate::Error::Http(e)) if resumes < self.stream_resume_retries => {
+                        let Some(id) = state.last_event_id.clone() else {
+                            return Err(e.into());
+                        };
+                        resumes += 1;
+                        tracing::debug!("Stream dropped, resuming from event {}: {}", id, e);
+                        state.buffer.clear();
+                        response = Self::send(
+                            self.build_request_with(messages, tools, streaming, request_id)?
+                                .header("Last-Event-ID", id),
+                        )
+                        .await?;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        } else {
+            let body = response.text().await?;
+            let mut response = parse_response(&body, self.response_pointer.as_deref())?;
+            self.cut_at_client_stop(&mut response);
+            tracing::debug!("LLM API call completed successfully");
+            self.replay_callbacks(&response.message.content);
+            Ok(response)
+        }
+    }
+
+    /// Pass the full content of a non-streamed response to the stream callbacks at once
+    fn replay_callbacks(&mut self, content: &str) {
+        if content.is_empty() {
+            return;
+        }
+        if let Some(callback) = &mut self.stream_callback {
+            callback(content.to_string());
+        }
+        if let Some(callback) = &mut self.line_callback {
+            content.lines().for_each(|line| callback(line.to_string()));
+        }
+        if let Some(callback) = &mut self.partial_json_callback
+            && let Some(value) = super::parse_partial_json(content)
+        {
+            callback(value);
+        }
+    }
+
+    /// Read a whole stream into a response, without resumption
+    #[cfg(test)]
+    async fn handle_stream<S, B, E>(&mut self, stream: S) -> crate::Result<LLMResponse>
+    where
+        S: futures::Stream<Item = Result<B, E>> + Unpin,
+        B: AsRef<[u8]>,
+        crate::Error: From<E>,
+    {
+        self.streamed_content = Some(String::new());
+        let mut state = StreamState::default();
+        self.read_stream(&mut state, stream).await?;
+        Ok(state.into_response())
+    }
+
+    /// Read SSE events from the stream, accumulating them into `state`
+    async fn read_stream<S, B, E>(
+        &mut self,
+        state: &mut StreamState,
+        mut stream: S,
+    ) -> crate::Result<()>
+    where
+        S: futures::Stream<Item = Result<B, E>> + Unpin,
+        B: AsRef<[u8]>,
+        crate::Error: From<E>,
+    {
+        use futures::TryStreamExt;
+
+        'read: while let Some(chunk) = stream.try_next().await? {
+            // Buffer raw bytes and only decode complete lines,
+            // so multibyte characters split across chunks stay intact
+            state.buffer.extend_from_slice(chunk.as_ref());
+
+            while let Some(line_end) = state.buffer.iter().position(|&b| b == b'\n') {
+                let line = String::from_utf8_lossy(&state.buffer[..line_end])
+                    .trim()
+                    .to_string();
+                state.buffer.drain(..=line_end);
+
+                if let Some(id) = line.strip_prefix("id:") {
+                    state.last_event_id = Some(id.trim().to_string());
+                    continue;
+                }
+                let Some(data) = line.strip_prefix("data: ") else {
+                    continue;
+                };
+                if data == "[DONE]" {
+                    break;
+                }
+
+                let Ok(chunk) = serde_json::from_str::<StreamChunk>(data) else {
+                    continue;
+                };
+                if chunk.usage.is_some() {
+                    state.usage = chunk.usage;
+                }
+                let Some(choice) = chunk.choices.first() else {
+                    continue;
+                };
+
+                if let Some(delta_reasoning) = &choice.delta.reasoning
+                    && let Some(callback) = &mut self.reasoning_callback
+                {
+                    callback(delta_reasoning.clone());
+                }
+
+                let mut stopped = false;
+                if let Some(delta_content) = &choice.delta.content {
+                    let start = state.content.len();
+                    state.content.push_str(delta_content);
+                    let mut delta_content = delta_content.clone();
+                    if let Some(index) = self.find_client_stop(&state.content, start) {
+                        tracing::debug!("Client stop sequence found, closing the stream");
+                        state.content.truncate(index);
+                        delta_content = state.content[index.min(start)..].to_string();
+                        stopped = true;
+                    }
+                    self.streamed_content
+                        .get_or_insert_default()
+                        .push_str(&delta_content);
+                    if let Some(callback) = &mut self.stream_callback
+                        && !delta_content.is_empty()
+                    {
+                        callback(delta_content.clone());
+                    }
+                    if let Some(callback) = &mut self.line_callback {
+                        state.pending_line.push_str(&delta_content);
+                        while let Some(line_end) = state.pending_line.find('\n') {
+                            let line = state.pending_line[..line_end].to_string();
+                            state.pending_line.drain(..=line_end);
+                            callback(line);
+                        }
+                    }
+                    if let Some(callback) = &mut self.partial_json_callback
+                        && let Some(value) = super::parse_partial_json(&state.content)
+                    {
+                        callback(value);
+                    }
+                }
+
+                if let Some(delta_refusal) = &choice.delta.refusal {
+                    state
+                        .refusal
+                        .get_or_insert_default()
+                        .push_str(delta_refusal);
+                }
 
-        tracing::debug!("Streaming completed, total length: {}", content.len());
-        Ok(LLMResponse {
+                if let Some(annotations) = &choice.delta.annotations {
+                    state
+                        .annotations
+                        .get_or_insert_default()
+                        .extend(annotations.iter().cloned());
+                }
+
+                if let Some(delta_tool_calls) = &choice.delta.tool_calls {
+                    state.tool_calls.extend(delta_tool_calls.clone());
+                }
+
+                if let Some(reason) = &choice.finish_reason {
+                    state.finish_reason = Some(reason.clone());
+                }
+
+                if let Some(logprobs) = &choice.logprobs {
+                    let acc = state.logprobs.get_or_insert_default();
+                    for (acc, delta) in [
+                        (&mut acc.content, &logprobs.content),
+                        (&mut acc.refusal, &logprobs.refusal),
+                    ] {
+                        if let Some(delta) = delta {
+                            acc.get_or_insert_default().extend(delta.iter().cloned());
+                        }
+                    }
+                }
+
+                if stopped {
+                    state.finish_reason = Some(FinishReason::Stop);
+                    break 'read;
+                }
+            }
+
+            if state.buffer.len() > self.max_line_bytes {
+                tracing::debug!("SSE line exceeds {} bytes", self.max_line_bytes);
+                return Err(crate::Error::StreamLineTooLong(self.max_line_bytes));
+            }
+        }
+
+        if let Some(callback) = &mut self.line_callback
+            && !state.pending_line.is_empty()
+        {
+            callback(std::mem::take(&mut state.pending_line));
+        }
+
+        tracing::debug!("Streaming completed, total length: {}", state.content.len());
+        Ok(())
+    }
+}
+
+/// Parse a non-streaming chat completion response body,
+/// reading the chat completion at `pointer` if set
+fn parse_response(body: &str, pointer: Option<&str>) -> crate::Result<LLMResponse> {
+    let parse_error =
+        |e| crate::Error::Custom(format!("Failed to parse response: {}. Body: {}", e, body));
+    let chat_response: ChatResponse = match pointer {
+        None => serde_json::from_str(body).map_err(parse_error)?,
+        Some(pointer) => {
+            let mut value: Value = serde_json::from_str(body).map_err(parse_error)?;
+            let Some(value) = value.pointer_mut(pointer) else {
+                return Err(crate::Error::Custom(format!(
+                    "Response has nothing at '{}'. Body: {}",
+                    pointer, body
+                )));
+            };
+            serde_json::from_value(value.take()).map_err(parse_error)?
+        }
+    };
+    let Some(choice) = chat_response.choices.into_iter().next() else {
+        return Err(crate::Error::Custom(format!(
+            "Response has no choices. Body: {}",
+            body
+        )));
+    };
+    let Message::Assistant(message) = choice.message else {
+        return Err(crate::Error::UnexpectedMessage(format!(
+            "{:?}",
+            choice.message
+        )));
+    };
+    Ok(LLMResponse {
+        message,
+        finish_reason: choice.finish_reason,
+        logprobs: choice.logprobs,
+        usage: chat_response.usage,
+    })
+}
+
+/// Partial response accumulated while reading a stream
+#[derive(Default)]
+struct StreamState {
+    /// Bytes received after the last complete line
+    buffer: Vec<u8>,
+    content: String,
+    tool_calls: Vec<crate::types::ToolCall>,
+    finish_reason: Option<FinishReason>,
+    /// ID of the last SSE event, used to resume the stream
+    last_event_id: Option<String>,
+    logprobs: Option<Logprobs>,
+    /// Content after the last newline, not yet passed to the line callback
+    pending_line: String,
+    refusal: Option<String>,
+    annotations: Option<Vec<crate::types::Annotation>>,
+    usage: Option<Usage>,
+}
+
+impl StreamState {
+    fn into_response(self) -> LLMResponse {
+        LLMResponse {
             message: crate::types::AssistantMessage {
-                content,
-                tool_calls: if tool_calls.is_empty() {
+                content: self.content,
+                tool_calls: if self.tool_calls.is_empty() {
                     None
                 } else {
-                    Some(tool_calls)
+                    Some(self.tool_calls)
+                },
+                refusal: self.refusal,
+                annotations: self.annotations,
+            },
+            finish_reason: self.finish_reason.unwrap_or(FinishReason::Stop),
+            logprobs: self.logprobs,
+            usage: self.usage,
+        }
+    }
+}
+
+/// Convert serialized tool messages to legacy `function` role messages,
+/// naming them after the matching call of a previous assistant message
+fn to_function_role(messages: &mut [Value]) {
+    let mut names = HashMap::new();
+    for message in messages {
+        if let Some(Value::Array(calls)) = message.get("tool_calls") {
+            for call in calls {
+                if let (Some(id), Some(name)) =
+                    (call["id"].as_str(), call["function"]["name"].as_str())
+                {
+                    names.insert(id.to_string(), name.to_string());
+                }
+            }
+        }
+        if message["role"] == "tool" {
+            let name = message["tool_call_id"]
+                .as_str()
+                .and_then(|id| names.get(id))
+                .cloned()
+                .unwrap_or_default();
+            *message = serde_json::json!({
+                "role": "function",
+                "name": name,
+                "content": message["content"].take(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a byte stream from SSE lines, one chunk per line
+    fn sse(lines: &[&str]) -> impl futures::Stream<Item = crate::Result<String>> + Unpin {
+        futures::stream::iter(
+            lines
+                .iter()
+                .map(|l| Ok(format!("{}\n", l)))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// Build the request body JSON without sending it
+    fn request_body(provider: &OpenAIProvider) -> Value {
+        request_body_with_tools(provider, &[])
+    }
+
+    /// Build the request body JSON for the given tools without sending it
+    fn request_body_with_tools(provider: &OpenAIProvider, tools: &[ToolDefinition]) -> Value {
+        let request = provider.build_request(&[], tools).unwrap().build().unwrap();
+        serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_plain_request_has_no_optional_fields() {
+        // Text-only backends reject unknown fields such as `modalities` or `audio`
+        let body = request_body(&OpenAIProvider::new());
+        let mut keys: Vec<_> = body
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort();
+        assert_eq!(keys, vec!["messages", "model", "tools"]);
+    }
+
+    #[test]
+    fn test_from_env() {
+        let mut env = HashMap::from([
+            ("OPENAI_API_KEY", "sk-test"),
+            ("OPENAI_BASE_URL", "http://localhost:8080/v1"),
+            ("OPENAI_ORG", "org-test"),
+            ("OPENAI_MODEL", "gpt-4o-mini"),
+        ]);
+        let from_env = |env: &HashMap<_, &str>| {
+            OpenAIProvider::from_env_with(|name| env.get(name).map(|v| v.to_string()))
+        };
+        let provider = from_env(&env);
+        assert_eq!(provider.api_key, "sk-test");
+        assert_eq!(provider.base_url, "http://localhost:8080/v1");
+        assert_eq!(provider.organization.as_deref(), Some("org-test"));
+        assert_eq!(provider.model, "gpt-4o-mini");
+
+        env.insert("OPENAI_MODEL", "");
+        env.remove("OPENAI_BASE_URL");
+        env.remove("OPENAI_ORG");
+        let provider = from_env(&env);
+        assert_eq!(provider.api_key, "sk-test");
+        assert_eq!(provider.base_url, "https://api.openai.com/v1");
+        assert_eq!(provider.organization, None);
+        assert_eq!(provider.model, "gpt-4o");
+    }
+
+    #[test]
+    fn test_on_serialize() {
+        let provider = OpenAIProvider::new()
+            .body(serde_json::json!({"max_completion_tokens": 100}))
+            .unwrap()
+            .on_serialize(|mut body| {
+                let max = body
+                    .as_object_mut()
+                    .unwrap()
+                    .remove("max_completion_tokens");
+                body["max_tokens"] = max.unwrap();
+                body
+            });
+        let body = request_body(&provider);
+        assert!(body.get("max_completion_tokens").is_none());
+        assert_eq!(body["max_tokens"], 100);
+        assert_eq!(body["model"], "gpt-4o");
+    }
+
+    #[test]
+    fn test_store_and_metadata_omitted_by_default() {
+        let body = request_body(&OpenAIProvider::new());
+        assert!(body.get("store").is_none());
+        assert!(body.get("metadata").is_none());
+    }
+
+    #[test]
+    fn test_store_and_metadata_serialized() {
+        let body = request_body(
+            &OpenAIProvider::new()
+                .store(true)
+                .metadata(HashMap::from([("team".into(), "search".into())])),
+        );
+        assert_eq!(body["store"], true);
+        assert_eq!(body["metadata"]["team"], "search");
+    }
+
+    #[test]
+    fn test_tool_choice() {
+        let def = |name: &str| ToolDefinition {
+            tool_type: "function".into(),
+            function: crate::types::ToolFunction {
+                name: name.into(),
+                description: String::new(),
+                parameters: crate::types::Parameters::from_object(Default::default()),
+            },
+        };
+        let tools = [def("search"), def("fetch"), def("delete")];
+        let names = |body: &Value| -> Vec<String> {
+            body["tools"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|t| t["function"]["name"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        let body = request_body_with_tools(&OpenAIProvider::new(), &tools);
+        assert!(body.get("tool_choice").is_none());
+        assert_eq!(names(&body).len(), 3);
+
+        let body = request_body_with_tools(
+            &OpenAIProvider::new().tool_choice(ToolChoice::RequiredFrom(vec![
+                "search".into(),
+                "fetch".into(),
+            ])),
+            &tools,
+        );
+        assert_eq!(body["tool_choice"], "required");
+        assert_eq!(names(&body), vec!["search", "fetch"]);
+
+        let body = request_body_with_tools(
+            &OpenAIProvider::new().tool_choice(ToolChoice::Function("fetch".into())),
+            &tools,
+        );
+        assert_eq!(body["tool_choice"]["function"]["name"], "fetch");
+        assert_eq!(names(&body).len(), 3);
+    }
+
+    #[test]
+    fn test_logprobs_request() {
+        let body = request_body(&OpenAIProvider::new());
+        assert!(body.get("logprobs").is_none());
+        assert!(body.get("top_logprobs").is_none());
+
+        let body = request_body(&OpenAIProvider::new().logprobs(true).top_logprobs(2));
+        assert_eq!(body["logprobs"], true);
+        assert_eq!(body["top_logprobs"], 2);
+    }
+
+    #[test]
+    fn test_parse_response_with_logprobs() {
+        let body = serde_json::json!({
+            "id": "chatcmpl-123",
+            "object": "chat.completion",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "Yes"},
+                "logprobs": {
+                    "content": [{
+                        "token": "Yes",
+                        "logprob": -0.031,
+                        "bytes": [89, 101, 115],
+                        "top_logprobs": [
+                            {"token": "Yes", "logprob": -0.031, "bytes": [89, 101, 115]},
+                            {"token": "No", "logprob": -3.5, "bytes": [78, 111]}
+                        ]
+                    }],
+                    "refusal": null
                 },
+                "finish_reason": "stop"
+            }]
+        });
+
+        let response = parse_response(&body.to_string(), None).unwrap();
+        let content = response.logprobs.unwrap().content.unwrap();
+        assert_eq!(content.len(), 1);
+        assert_eq!(content[0].token, "Yes");
+        assert_eq!(content[0].logprob, -0.031);
+        assert_eq!(content[0].bytes, Some(vec![89, 101, 115]));
+        assert_eq!(content[0].top_logprobs[1].token, "No");
+
+        let body = r#"{"choices":[{"message":{"role":"assistant","content":"Hi"},"finish_reason":"stop"}]}"#;
+        assert!(parse_response(body, None).unwrap().logprobs.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stream_logprobs() {
+        let mut provider = OpenAIProvider::new().stream_callback(|_| {}).logprobs(true);
+
+        let response = provider
+            .handle_stream(sse(&[
+                r#"data: {"choices":[{"delta":{"content":"Hel"},"logprobs":{"content":[{"token":"Hel","logprob":-0.5,"top_logprobs":[]}]}}]}"#,
+                r#"data: {"choices":[{"delta":{"content":"lo"},"logprobs":{"content":[{"token":"lo","logprob":-0.1,"top_logprobs":[]}]}}]}"#,
+                r#"data: {"choices":[{"delta":{},"finish_reason":"stop"}]}"#,
+                "data: [DONE]",
+            ]))
+            .await
+            .unwrap();
+
+        let content = response.logprobs.unwrap().content.unwrap();
+        let tokens: Vec<_> = content.iter().map(|t| t.token.as_str()).collect();
+        assert_eq!(tokens, vec!["Hel", "lo"]);
+    }
+
+    #[test]
+    fn test_streaming_rejects_multiple_choices() {
+        let provider = OpenAIProvider::new()
+            .body(serde_json::json!({"n": 2}))
+            .unwrap();
+        assert!(provider.build_request(&[], &[]).is_ok());
+
+        let err = provider
+            .stream_callback(|_| {})
+            .build_request(&[], &[])
+            .unwrap_err();
+        assert!(matches!(err, crate::Error::StreamingMultipleChoices(2)));
+        assert_eq!(err.to_string(), "Streaming requires n = 1, got n = 2");
+
+        let provider = OpenAIProvider::new()
+            .body(serde_json::json!({"n": 1}))
+            .unwrap()
+            .stream_callback(|_| {});
+        assert!(provider.build_request(&[], &[]).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_retry_policy_stops_on_status() {
+        use crate::llm::{LLMProvider, mock::MockServer};
+        use tokio::io::AsyncWriteExt;
+
+        let requests = Arc::new(Mutex::new(0));
+        let r = requests.clone();
+        let url = MockServer::spawn(move |mut stream, _| {
+            let status = {
+                let mut requests = r.lock().unwrap();
+                *requests += 1;
+                if *requests == 1 {
+                    "429 Too Many Requests"
+                } else {
+                    "401 Unauthorized"
+                }
+            };
+            async move {
+                let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        })
+        .await;
+
+        let mut provider =
+            OpenAIProvider::new()
+                .base_url(url)
+                .retry_policy(|_: u32, status: Option<u16>| match status {
+                    Some(401) => None,
+                    _ => Some(std::time::Duration::ZERO),
+                });
+
+        let err = provider.call(&[], &[]).await.unwrap_err();
+        assert!(matches!(err, crate::Error::ApiError { status: 401, .. }));
+        assert_eq!(*requests.lock().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_embed() {
+        use crate::llm::{EmbeddingProvider, mock::MockServer};
+        use tokio::io::AsyncWriteExt;
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let r = requests.clone();
+        let url = MockServer::spawn(move |mut stream, request| {
+            r.lock().unwrap().push(request);
+            async move {
+                let body = r#"{"object":"list","data":[
+                    {"object":"embedding","index":1,"embedding":[0.5,-1.0]},
+                    {"object":"embedding","index":0,"embedding":[0.25,0.0]}
+                ],"model":"text-embedding-3-large"}"#;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        })
+        .await;
+
+        let provider = OpenAIProvider::new()
+            .base_url(url)
+            .embedding_model("text-embedding-3-large");
+        let embeddings = provider
+            .embed(vec!["first".into(), "second".into()])
+            .await
+            .unwrap();
+
+        assert_eq!(embeddings, vec![vec![0.25, 0.0], vec![0.5, -1.0]]);
+        let request = requests.lock().unwrap()[0].clone();
+        assert!(request.starts_with("POST /embeddings "));
+        let body: Value = serde_json::from_str(request.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"model": "text-embedding-3-large", "input": ["first", "second"]})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_streamed_content() {
+        use crate::llm::{LLMProvider, mock::MockServer};
+        use tokio::io::AsyncWriteExt;
+
+        let url = MockServer::spawn(|mut stream, _| async move {
+            let body = "data: [DONE]\n\n";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        })
+        .await;
+
+        let mut provider = OpenAIProvider::new()
+            .base_url(url)
+            .max_retries(0)
+            .stream_callback(|_| {});
+        assert_eq!(provider.streamed_content(), None);
+        provider.call(&[], &[]).await.unwrap();
+        // An empty stream is still a stream
+        assert_eq!(provider.streamed_content(), Some(""));
+    }
+
+    #[tokio::test]
+    async fn test_response_pointer() {
+        use crate::llm::{LLMProvider, mock::MockServer};
+        use tokio::io::AsyncWriteExt;
+
+        let url = MockServer::spawn(|mut stream, _| async move {
+            let body = r#"{"data":{"choices":[{"message":{"role":"assistant","content":"Hi"},"finish_reason":"stop"}]}}"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        })
+        .await;
+
+        let mut provider = OpenAIProvider::new()
+            .base_url(&url)
+            .max_retries(0)
+            .response_pointer("/data");
+        let response = provider.call(&[], &[]).await.unwrap();
+        assert_eq!(response.message.content, "Hi");
+        assert_eq!(response.finish_reason, FinishReason::Stop);
+
+        let mut provider = OpenAIProvider::new()
+            .base_url(url)
+            .max_retries(0)
+            .response_pointer("/result");
+        let err = provider.call(&[], &[]).await.unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Response has nothing at '/result'")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_health_check() {
+        use crate::llm::{LLMProvider, mock::MockServer};
+        use tokio::io::AsyncWriteExt;
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let r = requests.clone();
+        let url = MockServer::spawn(move |mut stream, request| {
+            let status = if request.contains("Bearer valid-key") {
+                "200 OK"
+            } else {
+                "401 Unauthorized"
+            };
+            r.lock().unwrap().push(request);
+            async move {
+                let body = r#"{"object":"list","data":[]}"#;
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        })
+        .await;
+
+        let provider = OpenAIProvider::new().base_url(&url).api_key("valid-key");
+        assert!(provider.health_check().await.is_ok());
+        assert!(requests.lock().unwrap()[0].starts_with("GET /models "));
+
+        let provider = OpenAIProvider::new().base_url(url).api_key("revoked-key");
+        let err = provider.health_check().await.unwrap_err();
+        assert!(matches!(err, crate::Error::ApiError { status: 401, .. }));
+    }
+
+    #[test]
+    fn test_default_system() {
+        let provider = OpenAIProvider::new().default_system("Base policy");
+        let user: Message = crate::types::UserMessage {
+            content: "Hi".into(),
+        }
+        .into();
+        let system: Message = crate::types::SystemMessage {
+            content: "Agent prompt".into(),
+        }
+        .into();
+        let messages = |msgs: &[Message]| -> Value {
+            let request = provider.build_request(msgs, &[]).unwrap().build().unwrap();
+            let body: Value =
+                serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+            body["messages"].clone()
+        };
+
+        let sent = messages(std::slice::from_ref(&user));
+        assert_eq!(sent.as_array().unwrap().len(), 2);
+        assert_eq!(sent[0]["role"], "system");
+        assert_eq!(sent[0]["content"], "Base policy");
+        assert_eq!(sent[1]["content"], "Hi");
+
+        let sent = messages(&[system, user]);
+        assert_eq!(sent.as_array().unwrap().len(), 2);
+        assert_eq!(sent[0]["content"], "Agent prompt");
+    }
+
+    #[test]
+    fn test_user_agent_header() {
+        let request = OpenAIProvider::new()
+            .build_request(&[], &[])
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(
+            request.headers()["User-Agent"],
+            format!("tiny-loop/{}", env!("CARGO_PKG_VERSION"))
+        );
+
+        let request = OpenAIProvider::new()
+            .user_agent("my-app/1.0")
+            .build_request(&[], &[])
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(request.headers()["User-Agent"], "my-app/1.0");
+    }
+
+    #[test]
+    fn test_accept_header() {
+        let provider = OpenAIProvider::new();
+        let request = provider
+            .build_request_with(&[], &[], true, None)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(request.headers()["Accept"], "text/event-stream");
+        assert_eq!(request.headers()["Content-Type"], "application/json");
+
+        let request = provider.build_request(&[], &[]).unwrap().build().unwrap();
+        assert!(request.headers().get("Accept").is_none());
+
+        let request = OpenAIProvider::new()
+            .header("Accept", "application/x-ndjson")
+            .unwrap()
+            .build_request_with(&[], &[], true, None)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(request.headers()["Accept"], "application/x-ndjson");
+    }
+
+    #[test]
+    fn test_organization_and_project_headers() {
+        let request = OpenAIProvider::new()
+            .build_request(&[], &[])
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!(request.headers().get("OpenAI-Organization").is_none());
+        assert!(request.headers().get("OpenAI-Project").is_none());
+
+        let request = OpenAIProvider::new()
+            .organization("org-123")
+            .project("proj_456")
+            .build_request(&[], &[])
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(request.headers()["OpenAI-Organization"], "org-123");
+        assert_eq!(request.headers()["OpenAI-Project"], "proj_456");
+    }
+
+    #[tokio::test]
+    async fn test_unterminated_line_exceeding_limit() {
+        let mut provider = OpenAIProvider::new()
+            .stream_callback(|_| {})
+            .max_line_bytes(1024);
+        let huge = "x".repeat(512);
+        let stream = futures::stream::iter(
+            (0..4)
+                .map(|_| Ok::<_, crate::Error>(huge.clone()))
+                .collect::<Vec<_>>(),
+        );
+
+        let err = provider.handle_stream(stream).await.unwrap_err();
+        assert!(matches!(err, crate::Error::StreamLineTooLong(1024)));
+    }
+
+    #[tokio::test]
+    async fn test_terminated_lines_within_limit() {
+        let mut provider = OpenAIProvider::new()
+            .stream_callback(|_| {})
+            .max_line_bytes(128);
+        let line = r#"data: {"choices":[{"delta":{"content":"abc"}}]}"#;
+
+        let response = provider.handle_stream(sse(&[line; 10])).await.unwrap();
+        assert_eq!(response.message.content, "abc".repeat(10));
+    }
+
+    #[tokio::test]
+    async fn test_usage() {
+        let body = r#"{"choices":[{"message":{"role":"assistant","content":"Hi"},"finish_reason":"stop"}],"usage":{"prompt_tokens":9,"completion_tokens":1,"total_tokens":10}}"#;
+        let usage = parse_response(body, None).unwrap().usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (9, 1));
+
+        // Sent in a last chunk without choices when `stream_options.include_usage` is set
+        let mut provider = OpenAIProvider::new().stream_callback(|_| {});
+        let response = provider
+            .handle_stream(sse(&[
+                r#"data: {"choices":[{"delta":{"content":"Hi"},"finish_reason":"stop"}],"usage":null}"#,
+                r#"data: {"choices":[],"usage":{"prompt_tokens":9,"completion_tokens":1,"total_tokens":10}}"#,
+                "data: [DONE]",
+            ]))
+            .await
+            .unwrap();
+        assert_eq!(response.message.content, "Hi");
+        assert_eq!(response.usage.unwrap().total_tokens, 10);
+    }
+
+    #[test]
+    fn test_parse_response_with_refusal() {
+        let body = r#"{"choices":[{"message":{"role":"assistant","content":null,"refusal":"I can't help with that."},"finish_reason":"stop"}]}"#;
+
+        let response = parse_response(body, None).unwrap();
+        assert_eq!(response.message.content, "");
+        assert_eq!(
+            response.message.refusal.as_deref(),
+            Some("I can't help with that.")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_refusal() {
+        let mut provider = OpenAIProvider::new().stream_callback(|_| {});
+
+        let response = provider
+            .handle_stream(sse(&[
+                r#"data: {"choices":[{"delta":{"role":"assistant","content":null,"refusal":""}}]}"#,
+                r#"data: {"choices":[{"delta":{"refusal":"I can't "}}]}"#,
+                r#"data: {"choices":[{"delta":{"refusal":"help."},"finish_reason":"stop"}]}"#,
+                "data: [DONE]",
+            ]))
+            .await
+            .unwrap();
+
+        assert_eq!(response.message.refusal.as_deref(), Some("I can't help."));
+    }
+
+    #[tokio::test]
+    async fn test_annotations() {
+        let annotation = r#"{"type":"url_citation","url_citation":{"start_index":10,"end_index":25,"url":"https://www.rust-lang.org","title":"Rust"}}"#;
+        let expected = crate::types::Annotation {
+            annotation_type: "url_citation".into(),
+            url_citation: Some(crate::types::UrlCitation {
+                start_index: 10,
+                end_index: 25,
+                url: "https://www.rust-lang.org".into(),
+                title: "Rust".into(),
+            }),
+        };
+
+        let body = format!(
+            r#"{{"choices":[{{"message":{{"role":"assistant","content":"Rust 1.0 was released in 2015.","annotations":[{}]}},"finish_reason":"stop"}}]}}"#,
+            annotation
+        );
+        let response = parse_response(&body, None).unwrap();
+        assert_eq!(response.message.annotations, Some(vec![expected.clone()]));
+
+        let mut provider = OpenAIProvider::new().stream_callback(|_| {});
+        let last = format!(
+            r#"data: {{"choices":[{{"delta":{{"annotations":[{}]}},"finish_reason":"stop"}}]}}"#,
+            annotation
+        );
+        let streamed = provider
+            .handle_stream(sse(&[
+                r#"data: {"choices":[{"delta":{"content":"Rust 1.0 was released in 2015."}}]}"#,
+                &last,
+                "data: [DONE]",
+            ]))
+            .await
+            .unwrap();
+        assert_eq!(streamed.message.annotations, Some(vec![expected]));
+
+        // Annotations are not sent back
+        let request = provider
+            .build_request(&[response.message.into()], &[])
+            .unwrap()
+            .build()
+            .unwrap();
+        let body = std::str::from_utf8(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert!(!body.contains("annotations"));
+    }
+
+    #[tokio::test]
+    async fn test_multibyte_split_across_chunks() {
+        let mut provider = OpenAIProvider::new().stream_callback(|_| {});
+        let line = "data: {\"choices\":[{\"delta\":{\"content\":\"Hi 🦀!\"}}]}\n";
+        let bytes = line.as_bytes();
+        // Split inside the 4-byte crab emoji
+        let split = line.find('🦀').unwrap() + 2;
+        let stream = futures::stream::iter(vec![
+            Ok::<_, crate::Error>(bytes[..split].to_vec()),
+            Ok(bytes[split..].to_vec()),
+        ]);
+
+        let response = provider.handle_stream(stream).await.unwrap();
+        assert_eq!(response.message.content, "Hi 🦀!");
+    }
+
+    /// Writer appending to a shared buffer, failing once `fail` is set
+    #[derive(Clone, Default)]
+    struct SharedWriter {
+        buf: Arc<Mutex<Vec<u8>>>,
+        fail: bool,
+    }
+
+    impl std::io::Write for SharedWriter {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            if self.fail {
+                return Err(std::io::Error::other("broken pipe"));
+            }
+            self.buf.lock().unwrap().extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_reasoning_effort() {
+        let sent = |provider: OpenAIProvider| -> Value {
+            let request = provider.build_request(&[], &[]).unwrap().build().unwrap();
+            let body: Value =
+                serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+            body.get("reasoning_effort").cloned().unwrap_or_default()
+        };
+
+        assert_eq!(sent(OpenAIProvider::new()), Value::Null);
+        for (effort, expected) in [
+            (ReasoningEffort::Low, "low"),
+            (ReasoningEffort::Medium, "medium"),
+            (ReasoningEffort::High, "high"),
+        ] {
+            assert_eq!(
+                sent(OpenAIProvider::new().reasoning_effort(effort)),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_omit_empty_parameters() {
+        let tool = |name: &str, schema: Value| ToolDefinition {
+            tool_type: "function".into(),
+            function: crate::types::ToolFunction {
+                name: name.into(),
+                description: "".into(),
+                parameters: crate::types::Parameters::from_object(
+                    schema.as_object().unwrap().clone(),
+                ),
             },
-            finish_reason,
+        };
+        let tools = [
+            tool(
+                "now",
+                serde_json::json!({"type": "object", "properties": {}}),
+            ),
+            tool(
+                "search",
+                serde_json::json!({"type": "object", "properties": {"q": {"type": "string"}}}),
+            ),
+        ];
+        let sent = |provider: OpenAIProvider| -> Value {
+            let request = provider
+                .build_request(&[], &tools)
+                .unwrap()
+                .build()
+                .unwrap();
+            let body: Value =
+                serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+            body["tools"].clone()
+        };
+
+        let tools = sent(OpenAIProvider::new());
+        assert_eq!(
+            tools[0]["function"]["parameters"],
+            serde_json::json!({"type": "object", "properties": {}})
+        );
+
+        let tools = sent(OpenAIProvider::new().omit_empty_parameters(true));
+        assert!(tools[0]["function"].get("parameters").is_none());
+        assert!(tools[1]["function"].get("parameters").is_some());
+    }
+
+    #[test]
+    fn test_legacy_function_role() {
+        let messages: Vec<Message> = vec![
+            crate::types::AssistantMessage {
+                content: "".into(),
+                tool_calls: Some(vec![crate::llm::mock::tool_call(
+                    "call_1",
+                    "get_weather",
+                    r#"{"city":"Paris"}"#,
+                )]),
+                refusal: None,
+                annotations: None,
+            }
+            .into(),
+            crate::types::ToolMessage {
+                tool_call_id: "call_1".into(),
+                content: "Sunny".into(),
+            }
+            .into(),
+        ];
+        let sent = |provider: OpenAIProvider| -> Value {
+            let request = provider
+                .build_request(&messages, &[])
+                .unwrap()
+                .build()
+                .unwrap();
+            let body: Value =
+                serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+            body["messages"][1].clone()
+        };
+
+        assert_eq!(
+            sent(OpenAIProvider::new()),
+            serde_json::json!({"role": "tool", "tool_call_id": "call_1", "content": "Sunny"})
+        );
+        assert_eq!(
+            sent(OpenAIProvider::new().legacy_function_role(true)),
+            serde_json::json!({"role": "function", "name": "get_weather", "content": "Sunny"})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_to_writer() {
+        let writer = SharedWriter::default();
+        let mut provider = OpenAIProvider::new().stream_to_writer(writer.clone());
+
+        provider
+            .handle_stream(sse(&[
+                r#"data: {"choices":[{"delta":{"content":"Hello, "}}]}"#,
+                r#"data: {"choices":[{"delta":{"content":"world!"},"finish_reason":"stop"}]}"#,
+                "data: [DONE]",
+            ]))
+            .await
+            .unwrap();
+
+        assert_eq!(&*writer.buf.lock().unwrap(), b"Hello, world!");
+        assert!(provider.take_stream_write_error().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stream_to_writer_error() {
+        let writer = SharedWriter {
+            fail: true,
+            ..Default::default()
+        };
+        let mut provider = OpenAIProvider::new().stream_to_writer(writer);
+
+        let response = provider
+            .handle_stream(sse(&[
+                r#"data: {"choices":[{"delta":{"content":"Hi"},"finish_reason":"stop"}]}"#,
+                "data: [DONE]",
+            ]))
+            .await
+            .unwrap();
+
+        assert_eq!(response.message.content, "Hi");
+        let error = provider.take_stream_write_error().unwrap();
+        assert_eq!(error.to_string(), "broken pipe");
+        assert!(provider.take_stream_write_error().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_line_callback() {
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let l = lines.clone();
+        let mut provider = OpenAIProvider::new().line_callback(move |s| l.lock().unwrap().push(s));
+
+        let response = provider
+            .handle_stream(sse(&[
+                r##"data: {"choices":[{"delta":{"content":"# Ti"}}]}"##,
+                r#"data: {"choices":[{"delta":{"content":"tle\n\nFirst "}}]}"#,
+                r#"data: {"choices":[{"delta":{"content":"line\nSecond"}}]}"#,
+                r#"data: {"choices":[{"delta":{"content":" line"},"finish_reason":"stop"}]}"#,
+                "data: [DONE]",
+            ]))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *lines.lock().unwrap(),
+            vec!["# Title", "", "First line", "Second line"]
+        );
+        assert_eq!(
+            response.message.content,
+            "# Title\n\nFirst line\nSecond line"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_client_stop() {
+        let chunks = Arc::new(Mutex::new(Vec::new()));
+        let c = chunks.clone();
+        let mut provider = OpenAIProvider::new()
+            .client_stop(["</answer>"])
+            .stream_callback(move |s| c.lock().unwrap().push(s));
+
+        let lines = [
+            r#"data: {"choices":[{"delta":{"content":"The answer"}}]}"#,
+            r#"data: {"choices":[{"delta":{"content":" is 42</ans"}}]}"#,
+            r#"data: {"choices":[{"delta":{"content":"wer> and more"}}]}"#,
+        ];
+        let stream = futures::stream::iter(
+            lines
+                .iter()
+                .map(|l| Ok(format!("{}\n", l)))
+                // The stream must be closed before reading further
+                .chain([Err(crate::Error::Custom("read after stop".into()))])
+                .collect::<Vec<_>>(),
+        );
+        let response = provider.handle_stream(stream).await.unwrap();
+
+        assert_eq!(response.message.content, "The answer is 42");
+        assert!(matches!(response.finish_reason, FinishReason::Stop));
+        assert_eq!(*chunks.lock().unwrap(), vec!["The answer", " is 42</ans"]);
+
+        let response = provider
+            .handle_stream(sse(&[
+                r#"data: {"choices":[{"delta":{"content":"Done</answer>ignored"}}]}"#,
+            ]))
+            .await
+            .unwrap();
+        assert_eq!(response.message.content, "Done");
+        assert_eq!(chunks.lock().unwrap()[2], "Done");
+    }
+
+    #[test]
+    fn test_client_stop_keeps_tool_calls() {
+        let provider = OpenAIProvider::new().client_stop(["</answer>"]);
+        let body = r#"{"choices":[{"message":{"role":"assistant","content":"Let me check</answer> more",
+            "tool_calls":[{"id":"call_1","type":"function","function":{"name":"search","arguments":"{}"}}]},
+            "finish_reason":"tool_calls"}]}"#;
+        let mut response = parse_response(body, None).unwrap();
+        provider.cut_at_client_stop(&mut response);
+        assert_eq!(response.message.content, "Let me check");
+        assert_eq!(response.finish_reason, FinishReason::ToolCalls);
+        assert_eq!(response.message.tool_calls.unwrap().len(), 1);
+
+        let body = r#"{"choices":[{"message":{"role":"assistant","content":"Done</answer> more"},
+            "finish_reason":"length"}]}"#;
+        let mut response = parse_response(body, None).unwrap();
+        provider.cut_at_client_stop(&mut response);
+        assert_eq!(response.message.content, "Done");
+        assert_eq!(response.finish_reason, FinishReason::Stop);
+    }
+
+    #[tokio::test]
+    async fn test_stream_override() {
+        use crate::llm::{CallOptions, LLMProvider, mock::MockServer};
+        use tokio::io::AsyncWriteExt;
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let r = requests.clone();
+        let url = MockServer::spawn(move |mut stream, request| {
+            r.lock().unwrap().push(request);
+            async move {
+                let body = r#"{"choices":[{"message":{"role":"assistant","content":"Hello\nworld"},"finish_reason":"stop"}]}"#;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        })
+        .await;
+
+        let chunks = Arc::new(Mutex::new(Vec::new()));
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let (c, l) = (chunks.clone(), lines.clone());
+        let mut provider = OpenAIProvider::new()
+            .base_url(url)
+            .stream_callback(move |chunk| c.lock().unwrap().push(chunk))
+            .line_callback(move |line| l.lock().unwrap().push(line));
+
+        let options = CallOptions {
+            stream: Some(false),
+            ..Default::default()
+        };
+        let response = provider.call_with(&[], &[], &options).await.unwrap();
+
+        assert_eq!(response.message.content, "Hello\nworld");
+        assert!(!requests.lock().unwrap()[0].contains("\"stream\""));
+        assert_eq!(*chunks.lock().unwrap(), vec!["Hello\nworld"]);
+        assert_eq!(*lines.lock().unwrap(), vec!["Hello", "world"]);
+        // Callbacks are replayed, but nothing was streamed
+        assert_eq!(provider.streamed_content(), None);
+    }
+
+    #[tokio::test]
+    async fn test_partial_json_callback() {
+        let values = Arc::new(Mutex::new(Vec::new()));
+        let v = values.clone();
+        let mut provider =
+            OpenAIProvider::new().partial_json_callback(move |value| v.lock().unwrap().push(value));
+
+        let response = provider
+            .handle_stream(sse(&[
+                r#"data: {"choices":[{"delta":{"content":"{\"name\": \"Al"}}]}"#,
+                r#"data: {"choices":[{"delta":{"content":"ice\", \"tags\": [\"a\","}}]}"#,
+                r#"data: {"choices":[{"delta":{"content":" \"b\"]}"},"finish_reason":"stop"}]}"#,
+                "data: [DONE]",
+            ]))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *values.lock().unwrap(),
+            vec![
+                serde_json::json!({"name": "Al"}),
+                serde_json::json!({"name": "Alice", "tags": ["a"]}),
+                serde_json::json!({"name": "Alice", "tags": ["a", "b"]}),
+            ]
+        );
+        let parsed: Value = serde_json::from_str(&response.message.content).unwrap();
+        assert_eq!(parsed["tags"][1], "b");
+    }
+
+    #[tokio::test]
+    async fn test_reasoning_and_content_callbacks() {
+        let reasoning = Arc::new(Mutex::new(Vec::new()));
+        let content = Arc::new(Mutex::new(Vec::new()));
+        let r = reasoning.clone();
+        let c = content.clone();
+        let mut provider = OpenAIProvider::new()
+            .reasoning_callback(move |s| r.lock().unwrap().push(s))
+            .stream_callback(move |s| c.lock().unwrap().push(s));
+
+        let response = provider
+            .handle_stream(sse(&[
+                r#"data: {"choices":[{"delta":{"reasoning":"think 1"}}]}"#,
+                r#"data: {"choices":[{"delta":{"content":"Hello"}}]}"#,
+                r#"data: {"choices":[{"delta":{"reasoning_content":"think 2"}}]}"#,
+                r#"data: {"choices":[{"delta":{"content":" world"},"finish_reason":"stop"}]}"#,
+                "data: [DONE]",
+            ]))
+            .await
+            .unwrap();
+
+        assert_eq!(*reasoning.lock().unwrap(), vec!["think 1", "think 2"]);
+        assert_eq!(*content.lock().unwrap(), vec!["Hello", " world"]);
+        assert_eq!(response.message.content, "Hello world");
+    }
+
+    #[tokio::test]
+    async fn test_stream_resumes_with_last_event_id() {
+        use crate::llm::{LLMProvider, mock::MockServer};
+        use tokio::io::AsyncWriteExt;
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let r = requests.clone();
+        let url = MockServer::spawn(move |mut stream, request| {
+            let attempt = {
+                let mut requests = r.lock().unwrap();
+                requests.push(request);
+                requests.len()
+            };
+            async move {
+                let events = if attempt == 1 {
+                    "id: 1\ndata: {\"choices\":[{\"delta\":{\"content\":\"Hello\"}}]}\n\n"
+                } else {
+                    "id: 2\ndata: {\"choices\":[{\"delta\":{\"content\":\" world\"}}]}\n\ndata: [DONE]\n\n"
+                };
+                let mut response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n",
+                    events.len(),
+                    events
+                );
+                // Drop the first connection mid-stream, without the final chunk
+                if attempt > 1 {
+                    response.push_str("0\r\n\r\n");
+                }
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
         })
+        .await;
+
+        let chunks = Arc::new(Mutex::new(Vec::new()));
+        let c = chunks.clone();
+        let mut provider = OpenAIProvider::new()
+            .base_url(url)
+            .max_retries(0)
+            .stream_callback(move |s| c.lock().unwrap().push(s))
+            .stream_resume_retries(1);
+
+        let response = provider.call(&[], &[]).await.unwrap();
+        assert_eq!(response.message.content, "Hello world");
+        assert_eq!(*chunks.lock().unwrap(), vec!["Hello", " world"]);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(!requests[0].to_lowercase().contains("last-event-id"));
+        assert!(requests[1].to_lowercase().contains("last-event-id: 1"));
     }
 }
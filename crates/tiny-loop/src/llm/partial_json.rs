@@ -0,0 +1,163 @@
+use serde_json::Value;
+
+/// Incremental parser for JSON streamed in fragments, e.g. structured output.
+/// See [`parse_partial_json`]
+///
+/// # Example
+/// ```
+/// use tiny_loop::llm::{OpenAIProvider, PartialJson};
+/// use std::sync::{Arc, Mutex};
+///
+/// let parser = Arc::new(Mutex::new(PartialJson::new()));
+/// let provider = OpenAIProvider::new().stream_callback(move |fragment| {
+///     if let Some(value) = parser.lock().unwrap().push(&fragment) {
+///         println!("{}", value);
+///     }
+/// });
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PartialJson {
+    buffer: String,
+}
+
+impl PartialJson {
+    /// Create an empty parser
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a fragment and parse everything received so far
+    pub fn push(&mut self, fragment: &str) -> Option<Value> {
+        self.buffer.push_str(fragment);
+        parse_partial_json(&self.buffer)
+    }
+
+    /// Text received so far
+    pub fn buffer(&self) -> &str {
+        &self.buffer
+    }
+}
+
+/// Parse the beginning of a JSON document on a best-effort basis
+///
+/// Open strings, arrays and objects are closed and incomplete trailing tokens are dropped,
+/// so `{"name": "Ali` parses as `{"name": "Ali"}`. Returns `None` if nothing can be parsed yet.
+///
+/// # Example
+/// ```
+/// use tiny_loop::llm::parse_partial_json;
+/// use serde_json::json;
+///
+/// assert_eq!(
+///     parse_partial_json(r#"{"name": "Ali", "tags": ["a", "b"#),
+///     Some(json!({"name": "Ali", "tags": ["a", "b"]}))
+/// );
+/// assert_eq!(parse_partial_json(r#"{"name": "Alice", "ag"#), Some(json!({"name": "Alice"})));
+/// ```
+pub fn parse_partial_json(text: &str) -> Option<Value> {
+    if let Some(value) = complete(text) {
+        return Some(value);
+    }
+    // Cut back to the last position between two values
+    safe_points(text)
+        .into_iter()
+        .rev()
+        .find_map(|end| complete(&text[..end]))
+}
+
+/// Close open strings and containers of `text` and parse it
+fn complete(text: &str) -> Option<Value> {
+    let mut closers = Vec::new();
+    let (mut in_string, mut escape) = (false, false);
+    for c in text.chars() {
+        if in_string {
+            match c {
+                _ if escape => escape = false,
+                '\\' => escape = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => closers.push('}'),
+            '[' => closers.push(']'),
+            '}' | ']' => {
+                closers.pop()?;
+            }
+            _ => {}
+        }
+    }
+
+    let mut completed = text.to_string();
+    if in_string {
+        if escape {
+            completed.pop();
+        }
+        completed.push('"');
+    }
+    let trimmed = completed.trim_end();
+    if let Some(rest) = trimmed.strip_suffix(',') {
+        completed = rest.to_string();
+    } else if trimmed.ends_with(':') {
+        completed = format!("{}null", trimmed);
+    }
+    completed.extend(closers.iter().rev());
+    serde_json::from_str(&completed).ok()
+}
+
+/// Positions outside strings right after an opening bracket or before a comma
+fn safe_points(text: &str) -> Vec<usize> {
+    let mut points = Vec::new();
+    let (mut in_string, mut escape) = (false, false);
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escape => escape = false,
+                '\\' => escape = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => points.push(i + 1),
+            ',' => points.push(i),
+            _ => {}
+        }
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_partial_json() {
+        let full = r#"{"name": "Alice \"A\"", "age": 30, "tags": ["a", "b"], "address": {"city": "Paris"}, "active": true}"#;
+        let mut parser = PartialJson::new();
+        let mut last = None;
+        for chunk in full.as_bytes().chunks(7) {
+            if let Some(value) = parser.push(std::str::from_utf8(chunk).unwrap()) {
+                assert!(value.is_object());
+                last = Some(value);
+            }
+        }
+        assert_eq!(parser.buffer(), full);
+        assert_eq!(last, serde_json::from_str(full).ok());
+
+        assert_eq!(parse_partial_json(""), None);
+        assert_eq!(parse_partial_json("{"), Some(json!({})));
+        assert_eq!(parse_partial_json(r#"{"a": "x\"#), Some(json!({"a": "x"})));
+        assert_eq!(parse_partial_json(r#"{"a":"#), Some(json!({"a": null})));
+        assert_eq!(
+            parse_partial_json(r#"{"a": 1, "b": tr"#),
+            Some(json!({"a": 1}))
+        );
+        assert_eq!(parse_partial_json(r#"[1, 2, "#), Some(json!([1, 2])));
+    }
+}
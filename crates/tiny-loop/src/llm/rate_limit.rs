@@ -0,0 +1,219 @@
+use super::{CallOptions, LLMProvider};
+use crate::types::{LLMResponse, Message, ToolDefinition};
+use async_trait::async_trait;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::Instant;
+
+/// Token bucket refilled continuously up to one minute's worth of capacity
+#[derive(Debug)]
+struct Bucket {
+    per_minute: f64,
+    available: f64,
+}
+
+impl Bucket {
+    fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute: per_minute as f64,
+            available: per_minute as f64,
+        }
+    }
+
+    fn refill(&mut self, elapsed: Duration) {
+        self.available =
+            (self.available + elapsed.as_secs_f64() * self.per_minute / 60.0).min(self.per_minute);
+    }
+
+    /// Time until `amount` is available
+    fn wait(&self, amount: f64) -> Duration {
+        Duration::from_secs_f64(((amount - self.available) * 60.0 / self.per_minute).max(0.0))
+    }
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    requests: Bucket,
+    tokens: Option<Bucket>,
+    refilled_at: Instant,
+}
+
+/// Shared rate limit for LLM calls. See [`RateLimitedProvider`]
+///
+/// Cloning the limiter is cheap and all clones share one budget,
+/// so multiple agents can respect the same global limit.
+///
+/// # Example
+/// ```
+/// use tiny_loop::llm::RateLimiter;
+///
+/// let limiter = RateLimiter::new(60).tokens_per_minute(100_000);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RateLimiter(Arc<Mutex<LimiterState>>);
+
+impl RateLimiter {
+    /// Create a limiter allowing `requests_per_minute` requests, with bursts up to that many
+    pub fn new(requests_per_minute: u32) -> Self {
+        Self(Arc::new(Mutex::new(LimiterState {
+            requests: Bucket::new(requests_per_minute.max(1)),
+            tokens: None,
+            refilled_at: Instant::now(),
+        })))
+    }
+
+    /// Also limit the tokens per minute, based on the usage reported in responses (default: unlimited)
+    ///
+    /// Requests wait until the budget is positive again after previous responses consumed it.
+    pub fn tokens_per_minute(self, tokens_per_minute: u32) -> Self {
+        self.0.lock().unwrap().tokens = Some(Bucket::new(tokens_per_minute.max(1)));
+        self
+    }
+
+    /// Wait until a request is allowed and consume it
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.0.lock().unwrap();
+                let now = Instant::now();
+                let elapsed = now - state.refilled_at;
+                state.refilled_at = now;
+                state.requests.refill(elapsed);
+                if let Some(tokens) = &mut state.tokens {
+                    tokens.refill(elapsed);
+                }
+                let wait = state.requests.wait(1.0).max(
+                    state
+                        .tokens
+                        .as_ref()
+                        .map_or(Duration::ZERO, |t| t.wait(1.0)),
+                );
+                if wait.is_zero() {
+                    state.requests.available -= 1.0;
+                    return;
+                }
+                wait
+            };
+            tracing::debug!("Rate limited, waiting {:?}", wait);
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Consume `tokens` from the tokens-per-minute budget, if any
+    pub fn record_usage(&self, tokens: u64) {
+        if let Some(bucket) = &mut self.0.lock().unwrap().tokens {
+            bucket.available -= tokens as f64;
+        }
+    }
+}
+
+/// Provider decorator that waits for a [`RateLimiter`] before every call
+///
+/// # Example
+/// ```
+/// use tiny_loop::{Agent, llm::{OpenAIProvider, RateLimitedProvider, RateLimiter}};
+///
+/// let limiter = RateLimiter::new(60);
+/// let researcher = Agent::new(RateLimitedProvider::new(OpenAIProvider::new(), limiter.clone()));
+/// let writer = Agent::new(RateLimitedProvider::new(OpenAIProvider::new(), limiter));
+/// ```
+pub struct RateLimitedProvider<P> {
+    inner: P,
+    limiter: RateLimiter,
+}
+
+impl<P: LLMProvider> RateLimitedProvider<P> {
+    /// Wrap `inner` so its calls respect `limiter`
+    pub fn new(inner: P, limiter: RateLimiter) -> Self {
+        Self { inner, limiter }
+    }
+
+    /// Get the wrapped provider
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    /// Get mutable access to the wrapped provider
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.inner
+    }
+}
+
+#[async_trait]
+impl<P: LLMProvider> LLMProvider for RateLimitedProvider<P> {
+    async fn call(
+        &mut self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+    ) -> crate::Result<LLMResponse> {
+        self.call_with(messages, tools, &CallOptions::default())
+            .await
+    }
+
+    async fn call_with(
+        &mut self,
+        messages: &[Message],
+        tools: &[ToolDefinition],
+        options: &CallOptions,
+    ) -> crate::Result<LLMResponse> {
+        self.limiter.acquire().await;
+        let response = self.inner.call_with(messages, tools, options).await?;
+        if let Some(usage) = &response.usage {
+            self.limiter.record_usage(usage.total_tokens);
+        }
+        Ok(response)
+    }
+
+    async fn health_check(&self) -> crate::Result<()> {
+        self.inner.health_check().await
+    }
+
+    fn streamed_content(&self) -> Option<&str> {
+        self.inner.streamed_content()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock::MockProvider;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_request_pacing() {
+        let limiter = RateLimiter::new(2);
+        let mut a =
+            RateLimitedProvider::new(MockProvider::new().text("1").text("3"), limiter.clone());
+        let mut b = RateLimitedProvider::new(MockProvider::new().text("2").text("4"), limiter);
+        let start = Instant::now();
+
+        let mut times = Vec::new();
+        for i in 0..4 {
+            let provider = if i % 2 == 0 { &mut a } else { &mut b };
+            provider.call(&[], &[]).await.unwrap();
+            times.push((Instant::now() - start).as_secs());
+        }
+        // Burst of two, then one request every 30s
+        assert_eq!(times, vec![0, 0, 30, 60]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_tokens_per_minute() {
+        let usage = crate::types::Usage {
+            prompt_tokens: 150,
+            completion_tokens: 50,
+            total_tokens: 200,
+        };
+        let llm = MockProvider::new().text("1").usage(usage).text("2");
+        let mut provider =
+            RateLimitedProvider::new(llm, RateLimiter::new(100).tokens_per_minute(100));
+        let start = Instant::now();
+
+        provider.call(&[], &[]).await.unwrap();
+        assert_eq!((Instant::now() - start).as_secs(), 0);
+        // 200 tokens used from a budget of 100: wait until it's positive again
+        provider.call(&[], &[]).await.unwrap();
+        assert_eq!((Instant::now() - start).as_secs(), 60);
+    }
+}
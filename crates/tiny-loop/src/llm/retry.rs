@@ -0,0 +1,186 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::Duration,
+};
+
+/// Decides whether and when a failed LLM call is retried
+pub trait RetryPolicy: Send + Sync {
+    /// Delay before retrying after the given failed attempt (starting at 1),
+    /// or `None` to stop retrying.
+    /// `status` is the HTTP status code if the API responded with an error.
+    fn delay(&self, attempt: u32, status: Option<u16>) -> Option<Duration>;
+}
+
+impl<F> RetryPolicy for F
+where
+    F: Fn(u32, Option<u16>) -> Option<Duration> + Send + Sync,
+{
+    fn delay(&self, attempt: u32, status: Option<u16>) -> Option<Duration> {
+        self(attempt, status)
+    }
+}
+
+/// Retry up to `max_retries` times with the same delay
+///
+/// # Example
+/// ```
+/// use tiny_loop::llm::{ConstantBackoff, RetryPolicy};
+/// use std::time::Duration;
+///
+/// let policy = ConstantBackoff::new(Duration::from_secs(1), 3);
+/// assert_eq!(policy.delay(3, None), Some(Duration::from_secs(1)));
+/// assert_eq!(policy.delay(4, None), None);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConstantBackoff {
+    delay: Duration,
+    max_retries: u32,
+}
+
+impl ConstantBackoff {
+    /// Create a constant backoff policy
+    pub fn new(delay: Duration, max_retries: u32) -> Self {
+        Self { delay, max_retries }
+    }
+}
+
+impl RetryPolicy for ConstantBackoff {
+    fn delay(&self, attempt: u32, _status: Option<u16>) -> Option<Duration> {
+        (attempt <= self.max_retries).then_some(self.delay)
+    }
+}
+
+/// Retry up to `max_retries` times, multiplying the delay by `factor` after each attempt
+///
+/// # Example
+/// ```
+/// use tiny_loop::llm::{ExponentialBackoff, RetryPolicy};
+/// use std::time::Duration;
+///
+/// let policy = ExponentialBackoff::new(Duration::from_millis(500), 5)
+///     .factor(2.0)
+///     .max_delay(Duration::from_secs(4));
+/// assert_eq!(policy.delay(1, None), Some(Duration::from_millis(500)));
+/// assert_eq!(policy.delay(3, None), Some(Duration::from_secs(2)));
+/// assert_eq!(policy.delay(5, None), Some(Duration::from_secs(4)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ExponentialBackoff {
+    initial: Duration,
+    factor: f64,
+    max_delay: Duration,
+    max_retries: u32,
+}
+
+impl ExponentialBackoff {
+    /// Create an exponential backoff policy doubling the delay after each attempt
+    pub fn new(initial: Duration, max_retries: u32) -> Self {
+        Self {
+            initial,
+            factor: 2.0,
+            max_delay: Duration::MAX,
+            max_retries,
+        }
+    }
+
+    /// Set the multiplier applied to the delay after each attempt (default: 2.0)
+    pub fn factor(mut self, factor: f64) -> Self {
+        self.factor = factor;
+        self
+    }
+
+    /// Set the upper bound of the delay (default: unbounded)
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+}
+
+impl RetryPolicy for ExponentialBackoff {
+    fn delay(&self, attempt: u32, _status: Option<u16>) -> Option<Duration> {
+        if attempt > self.max_retries {
+            return None;
+        }
+        let scale = self.factor.powi(attempt.saturating_sub(1) as i32);
+        let delay = Duration::try_from_secs_f64(self.initial.as_secs_f64() * scale)
+            .unwrap_or(Duration::MAX);
+        Some(delay.min(self.max_delay))
+    }
+}
+
+/// Number of retries shared by several LLM calls, e.g. all steps of one agent run
+///
+/// Clones share the same counter. Providers consume one retry from the budget
+/// before each retry and stop retrying once it's exhausted.
+///
+/// # Example
+/// ```
+/// use tiny_loop::llm::RetryBudget;
+///
+/// let budget = RetryBudget::new(1);
+/// let shared = budget.clone();
+/// assert!(shared.try_consume());
+/// assert!(!budget.try_consume());
+/// assert_eq!(budget.remaining(), 0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RetryBudget(Arc<AtomicU32>);
+
+impl RetryBudget {
+    /// Create a budget allowing `max_retries` retries in total
+    pub fn new(max_retries: u32) -> Self {
+        Self(Arc::new(AtomicU32::new(max_retries)))
+    }
+
+    /// Number of retries left
+    pub fn remaining(&self) -> u32 {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Take one retry from the budget. Returns `false` if it's exhausted
+    pub fn try_consume(&self) -> bool {
+        self.0
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_backoff() {
+        let policy = ConstantBackoff::new(Duration::from_millis(100), 2);
+        assert_eq!(policy.delay(1, Some(500)), Some(Duration::from_millis(100)));
+        assert_eq!(policy.delay(2, None), Some(Duration::from_millis(100)));
+        assert_eq!(policy.delay(3, None), None);
+        assert_eq!(ConstantBackoff::new(Duration::ZERO, 0).delay(1, None), None);
+    }
+
+    #[test]
+    fn test_exponential_backoff() {
+        let policy = ExponentialBackoff::new(Duration::from_millis(100), 4)
+            .factor(3.0)
+            .max_delay(Duration::from_secs(1));
+        assert_eq!(policy.delay(1, None), Some(Duration::from_millis(100)));
+        assert_eq!(policy.delay(2, None), Some(Duration::from_millis(300)));
+        assert_eq!(policy.delay(3, None), Some(Duration::from_millis(900)));
+        assert_eq!(policy.delay(4, None), Some(Duration::from_secs(1)));
+        assert_eq!(policy.delay(5, None), None);
+    }
+
+    #[test]
+    fn test_closure_policy() {
+        let policy = |attempt: u32, status: Option<u16>| match status {
+            Some(401) => None,
+            _ => (attempt <= 1).then_some(Duration::ZERO),
+        };
+        assert_eq!(policy.delay(1, Some(401)), None);
+        assert_eq!(policy.delay(1, Some(429)), Some(Duration::ZERO));
+        assert_eq!(policy.delay(2, None), None);
+    }
+}
@@ -0,0 +1,74 @@
+#[cfg(feature = "tiktoken")]
+mod tiktoken;
+
+use crate::types::Message;
+use std::sync::Arc;
+
+#[cfg(feature = "tiktoken")]
+pub use tiktoken::*;
+
+/// Counts tokens in text.
+///
+/// Tokenizers are meant to be constructed once and shared,
+/// e.g. via [`Arc`], since loading an encoding can be expensive.
+pub trait Tokenizer: Send + Sync {
+    /// Count the tokens in the given text
+    fn count(&self, text: &str) -> usize;
+
+    /// Count the tokens of a message, including its role and structure
+    fn count_message(&self, message: &Message) -> usize {
+        self.count(&serde_json::to_string(message).unwrap_or_default())
+    }
+}
+
+impl<T: Tokenizer + ?Sized> Tokenizer for Arc<T> {
+    fn count(&self, text: &str) -> usize {
+        (**self).count(text)
+    }
+}
+
+impl<T: Tokenizer + ?Sized> Tokenizer for Box<T> {
+    fn count(&self, text: &str) -> usize {
+        (**self).count(text)
+    }
+}
+
+/// Cheap tokenizer estimating one token per 4 characters
+///
+/// Useful when an exact count isn't needed or no encoding is available.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ApproxTokenizer;
+
+/// Average number of characters per token for English text
+const CHARS_PER_TOKEN: usize = 4;
+
+impl Tokenizer for ApproxTokenizer {
+    fn count(&self, text: &str) -> usize {
+        text.chars().count().div_ceil(CHARS_PER_TOKEN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::UserMessage;
+
+    #[test]
+    fn test_approx_tokenizer() {
+        assert_eq!(ApproxTokenizer.count(""), 0);
+        assert_eq!(ApproxTokenizer.count("abcd"), 1);
+        assert_eq!(ApproxTokenizer.count("abcde"), 2);
+    }
+
+    #[test]
+    fn test_shared_tokenizer() {
+        let tokenizer: Arc<dyn Tokenizer> = Arc::new(ApproxTokenizer);
+        let message = Message::User(UserMessage {
+            content: "hello".into(),
+        });
+        assert_eq!(
+            tokenizer.count_message(&message),
+            ApproxTokenizer.count_message(&message)
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use super::Tokenizer;
+use std::sync::Arc;
+use tiktoken_rs::CoreBPE;
+
+/// Exact tokenizer for OpenAI models backed by [`tiktoken-rs`](https://docs.rs/tiktoken-rs)
+///
+/// The encoding is loaded once on construction; clones share it.
+///
+/// # Example
+/// ```
+/// use tiny_loop::tokenizer::{TiktokenTokenizer, Tokenizer};
+///
+/// let tokenizer = TiktokenTokenizer::for_model("gpt-4o").unwrap();
+/// assert!(tokenizer.count("Hello, world!") > 0);
+/// ```
+#[derive(Clone)]
+pub struct TiktokenTokenizer {
+    bpe: Arc<CoreBPE>,
+}
+
+impl TiktokenTokenizer {
+    /// Load the encoding used by the given model
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the model is unknown or the encoding fails to load.
+    pub fn for_model(model: &str) -> crate::Result<Self> {
+        let bpe = tiktoken_rs::get_bpe_from_model(model)
+            .map_err(|e| crate::Error::Custom(format!("Failed to load tokenizer: {}", e)))?;
+        Ok(Self { bpe: Arc::new(bpe) })
+    }
+
+    /// Load the `cl100k_base` encoding (GPT-4, GPT-3.5)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the encoding fails to load.
+    pub fn cl100k_base() -> crate::Result<Self> {
+        let bpe = tiktoken_rs::cl100k_base()
+            .map_err(|e| crate::Error::Custom(format!("Failed to load tokenizer: {}", e)))?;
+        Ok(Self { bpe: Arc::new(bpe) })
+    }
+
+    /// Load the `o200k_base` encoding (GPT-4o and newer)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the encoding fails to load.
+    pub fn o200k_base() -> crate::Result<Self> {
+        let bpe = tiktoken_rs::o200k_base()
+            .map_err(|e| crate::Error::Custom(format!("Failed to load tokenizer: {}", e)))?;
+        Ok(Self { bpe: Arc::new(bpe) })
+    }
+}
+
+impl Tokenizer for TiktokenTokenizer {
+    fn count(&self, text: &str) -> usize {
+        self.bpe.encode_with_special_tokens(text).len()
+    }
+}
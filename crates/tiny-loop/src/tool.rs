@@ -1,6 +1,10 @@
 mod args;
 mod closure;
 mod executor;
+#[cfg(feature = "http-tool")]
+mod http;
+mod rate_limit;
+mod utils;
 
 use crate::types::{ToolCall, ToolResult};
 use async_trait::async_trait;
@@ -9,7 +13,16 @@ use futures::future::join_all;
 pub use args::*;
 pub(crate) use closure::*;
 pub use executor::*;
+#[cfg(feature = "http-tool")]
+pub use http::*;
+pub use rate_limit::*;
 pub use tiny_loop_macros::tool;
+pub use utils::*;
+
+/// Span for the execution of one tool call, carrying its ID and tool name for log correlation
+fn tool_call_span(call_id: &str, tool_name: &str) -> tracing::Span {
+    tracing::debug_span!("tool_call", tool_call_id = call_id, tool_name = tool_name)
+}
 
 /// A trait for tools that can be called with JSON string arguments.
 ///
@@ -21,11 +34,23 @@ pub trait Tool {
     /// Calls the tool with JSON arguments and returns the result.
     async fn call(&self, args: String) -> String;
 
-    /// Calls the tool with timing measurement
+    /// Calls the tool with the whole tool call, for tools that need its ID or name.
+    /// Executors prefer this over [`call`](Self::call).
+    ///
+    /// The default implementation calls [`call`](Self::call) with the arguments.
+    async fn call_with_meta(&self, call: &ToolCall) -> String {
+        self.call(call.function.arguments.clone()).await
+    }
+
+    /// Calls the tool with timing measurement within the `tool_call` tracing span
     async fn call_timed(&self, call: ToolCall) -> ToolResult {
+        use tracing::Instrument;
+
+        let span = tool_call_span(&call.id, &call.function.name);
         let start = std::time::SystemTime::now();
-        let content = self.call(call.function.arguments).await;
+        let content = self.call_with_meta(&call).instrument(span.clone()).await;
         let elapsed = start.elapsed().unwrap();
+        span.in_scope(|| tracing::debug!("Tool call completed in {:?}", elapsed));
         ToolResult {
             tool_message: crate::types::ToolMessage {
                 tool_call_id: call.id,
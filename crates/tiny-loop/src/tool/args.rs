@@ -19,3 +19,37 @@ pub trait ToolArgs: JsonSchema + for<'a> Deserialize<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Clone, Debug, schemars::JsonSchema)]
+    struct Address {
+        /// City name
+        city: String,
+        /// Street name
+        street: String,
+    }
+
+    /// Look up a person
+    #[tiny_loop_macros::tool_internal]
+    async fn lookup(
+        /// Person name
+        name: String,
+        /// Home address
+        home: Address,
+        /// Previous addresses
+        previous: Vec<Address>,
+    ) -> String {
+        format!("{} {} {} {}", name, home.city, home.street, previous.len())
+    }
+
+    #[test]
+    fn test_nested_params_are_inlined() {
+        let json = serde_json::to_string(&<LookupArgs as super::ToolArgs>::definition()).unwrap();
+        assert!(!json.contains("$ref"), "{}", json);
+        assert!(!json.contains("$defs"), "{}", json);
+        assert!(json.contains("City name"), "{}", json);
+    }
+}
@@ -1,18 +1,25 @@
 use crate::tool::Tool;
+use crate::types::{ToolCall, ToolMessage, ToolResult};
 use async_trait::async_trait;
 use std::pin::Pin;
 
+/// Boxed async closure taking JSON arguments and returning the tool result.
+type ClosureFn = Box<dyn Fn(String) -> Pin<Box<dyn Future<Output = String> + Send>> + Sync>;
+
+/// Boxed async closure handling all calls of one batch at once.
+type BatchFn =
+    Box<dyn Fn(Vec<ToolCall>) -> Pin<Box<dyn Future<Output = Vec<ToolMessage>> + Send>> + Sync>;
+
 /// A tool that wraps an async closure for dynamic tool execution.
 pub struct ClosureTool {
-    func: Box<dyn Fn(String) -> Pin<Box<dyn Future<Output = String> + Send>> + Sync>,
+    func: ClosureFn,
+    batch: Option<BatchFn>,
 }
 
 impl ClosureTool {
     /// Creates a new ClosureTool with the given async closure.
-    pub fn new(
-        func: Box<dyn Fn(String) -> Pin<Box<dyn Future<Output = String> + Send>> + Sync>,
-    ) -> Self {
-        Self { func }
+    pub fn new(func: ClosureFn) -> Self {
+        Self { func, batch: None }
     }
 
     /// Creates a new ClosureTool from a closure, automatically boxing it.
@@ -21,6 +28,20 @@ impl ClosureTool {
     ) -> Self {
         Self::new(Box::new(func))
     }
+
+    /// Handles [`Tool::call_batch`] with the given closure instead of calling the tool once per call.
+    /// Single calls through [`Tool::call_with_meta`] are passed to it as a batch of one.
+    ///
+    /// Calls the closure returns no [`ToolMessage`] for are answered with an error message.
+    pub fn batch(
+        mut self,
+        batch: impl Fn(Vec<ToolCall>) -> Pin<Box<dyn Future<Output = Vec<ToolMessage>> + Send>>
+        + Sync
+        + 'static,
+    ) -> Self {
+        self.batch = Some(Box::new(batch));
+        self
+    }
 }
 
 #[async_trait]
@@ -28,4 +49,92 @@ impl Tool for ClosureTool {
     async fn call(&self, args: String) -> String {
         (self.func)(args).await
     }
+
+    async fn call_with_meta(&self, call: &ToolCall) -> String {
+        let Some(batch) = &self.batch else {
+            return self.call(call.function.arguments.clone()).await;
+        };
+        batch(vec![call.clone()])
+            .await
+            .into_iter()
+            .find(|m| m.tool_call_id == call.id)
+            .map_or_else(missing_result, |m| m.content)
+    }
+
+    async fn call_batch(&self, calls: Vec<ToolCall>) -> Vec<ToolResult> {
+        let Some(batch) = &self.batch else {
+            return futures::future::join_all(calls.into_iter().map(|call| self.call_timed(call)))
+                .await;
+        };
+        let ids: Vec<_> = calls.iter().map(|c| c.id.clone()).collect();
+        let start = std::time::SystemTime::now();
+        let mut messages = batch(calls).await;
+        let elapsed = start.elapsed().unwrap();
+        for id in ids {
+            if !messages.iter().any(|m| m.tool_call_id == id) {
+                tracing::debug!("Batch returned no result for tool call '{}'", id);
+                messages.push(ToolMessage {
+                    tool_call_id: id,
+                    content: missing_result(),
+                });
+            }
+        }
+        messages
+            .into_iter()
+            .map(|tool_message| ToolResult {
+                tool_message,
+                timestamp: start + elapsed,
+                elapsed,
+            })
+            .collect()
+    }
+}
+
+/// Result content for a call the batch closure returned nothing for
+fn missing_result() -> String {
+    "Error: the tool returned no result for this call".into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock::tool_call;
+
+    #[tokio::test]
+    async fn test_batch_missing_results() {
+        // Only answers the first call
+        let tool = ClosureTool::boxed(|_| Box::pin(async { unreachable!() })).batch(|calls| {
+            Box::pin(async move {
+                vec![ToolMessage {
+                    tool_call_id: calls[0].id.clone(),
+                    content: format!("ok {}", calls[0].id),
+                }]
+            })
+        });
+
+        let results = tool
+            .call_batch(vec![tool_call("1", "t", "{}"), tool_call("2", "t", "{}")])
+            .await;
+        let results: Vec<_> = results
+            .iter()
+            .map(|r| {
+                (
+                    r.tool_message.tool_call_id.as_str(),
+                    r.tool_message.content.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            results,
+            [
+                ("1", "ok 1"),
+                ("2", "Error: the tool returned no result for this call")
+            ]
+        );
+
+        assert_eq!(
+            tool.call_with_meta(&tool_call("3", "t", "{}")).await,
+            "ok 3"
+        );
+    }
 }
@@ -1,12 +1,21 @@
+mod composite;
 mod parallel;
+#[cfg(any(test, feature = "test-util"))]
+mod scripted;
 mod sequential;
+mod trace;
 
 use super::Tool;
 use crate::types::{ToolCall, ToolResult};
 use async_trait::async_trait;
+use std::{pin::Pin, sync::Mutex};
 
+pub use composite::*;
 pub use parallel::*;
+#[cfg(any(test, feature = "test-util"))]
+pub use scripted::*;
 pub use sequential::*;
+pub use trace::*;
 
 /// Executes tool calls with different strategies (parallel, sequential, etc.)
 #[async_trait]
@@ -14,18 +23,277 @@ pub trait ToolExecutor {
     /// Adds a tool to the executor. Returns the previous tool with the same name if it exists.
     fn add(&mut self, name: String, tool: Box<dyn Tool + Sync>) -> Option<Box<dyn Tool + Sync>>;
 
+    /// Makes calls to `alias` run the tool registered as `name`.
+    /// The tool still receives the call with the alias, e.g. in [`Tool::call_with_meta`].
+    ///
+    /// Returns `false` if aliases are unsupported (default),
+    /// in which case the [`Agent`](crate::Agent) renames aliased calls before executing them.
+    fn add_alias(&mut self, _alias: String, _name: String) -> bool {
+        false
+    }
+
     /// Executes the given tool calls and returns the results with timing metadata.
+    ///
+    /// Implementations should be cancellation-safe: if the returned future is dropped,
+    /// results of calls that already completed are kept for [`shutdown`](Self::shutdown).
     async fn execute(&self, calls: Vec<ToolCall>) -> Vec<ToolResult>;
+
+    /// Waits for in-flight executions to finish and returns the results of
+    /// completed calls whose [`execute`](Self::execute) future was dropped.
+    ///
+    /// The default implementation returns nothing. Implement it as an
+    /// `async fn` in an `#[async_trait]` impl like [`execute`](Self::execute).
+    // Written out so the default doesn't require `Self: Sync`,
+    // which would make it uncallable on `dyn ToolExecutor`
+    fn shutdown<'life0, 'async_trait>(
+        &'life0 self,
+    ) -> Pin<Box<dyn Future<Output = Vec<ToolResult>> + Send + 'async_trait>>
+    where
+        'life0: 'async_trait,
+        Self: 'async_trait,
+    {
+        Box::pin(async { Vec::new() })
+    }
+}
+
+/// Tracks in-flight executions and keeps the results of interrupted ones
+#[derive(Default)]
+struct InFlight {
+    count: Mutex<usize>,
+    idle: tokio::sync::Notify,
+    abandoned: Mutex<Vec<ToolResult>>,
+}
+
+impl InFlight {
+    /// Register an execution. Results pushed to the returned batch are kept
+    /// if it's dropped before [`Batch::finish`]
+    fn start(&self) -> Batch<'_> {
+        *self.count.lock().unwrap() += 1;
+        Batch {
+            in_flight: self,
+            results: Vec::new(),
+        }
+    }
+
+    /// Wait until no execution is in flight and take the abandoned results
+    async fn shutdown(&self) -> Vec<ToolResult> {
+        loop {
+            let idle = self.idle.notified();
+            if *self.count.lock().unwrap() == 0 {
+                break;
+            }
+            idle.await;
+        }
+        std::mem::take(&mut *self.abandoned.lock().unwrap())
+    }
 }
 
-/// Creates a ToolResult for a tool not found error
-fn tool_not_found_result(call_id: String, tool_name: &str) -> ToolResult {
+/// Results of one in-flight execution
+struct Batch<'a> {
+    in_flight: &'a InFlight,
+    results: Vec<ToolResult>,
+}
+
+impl Batch<'_> {
+    /// Complete the execution and return its results
+    fn finish(mut self) -> Vec<ToolResult> {
+        std::mem::take(&mut self.results)
+    }
+}
+
+impl Drop for Batch<'_> {
+    fn drop(&mut self) {
+        if !self.results.is_empty() {
+            tracing::debug!(
+                "Keeping {} results of an interrupted execution",
+                self.results.len()
+            );
+            self.in_flight
+                .abandoned
+                .lock()
+                .unwrap()
+                .append(&mut self.results);
+        }
+        *self.in_flight.count.lock().unwrap() -= 1;
+        self.in_flight.idle.notify_waiters();
+    }
+}
+
+/// Builds the result content for an unknown tool name
+type NotFoundMessage = Box<dyn Fn(&str) -> String + Send + Sync>;
+
+/// Creates a ToolResult for a tool not found error,
+/// using the custom message if set or `Tool '{name}' not found` otherwise
+fn tool_not_found_result(
+    call_id: String,
+    tool_name: &str,
+    message: Option<&NotFoundMessage>,
+) -> ToolResult {
+    let _span = super::tool_call_span(&call_id, tool_name).entered();
+    tracing::debug!("Tool '{}' not found", tool_name);
     ToolResult {
         tool_message: crate::types::ToolMessage {
             tool_call_id: call_id,
-            content: format!("Tool '{}' not found", tool_name),
+            content: match message {
+                Some(message) => message(tool_name),
+                None => format!("Tool '{}' not found", tool_name),
+            },
         },
         timestamp: std::time::SystemTime::now(),
         elapsed: std::time::Duration::ZERO,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock::tool_call;
+
+    fn contents(results: Vec<ToolResult>) -> Vec<String> {
+        results
+            .into_iter()
+            .map(|r| r.tool_message.content)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_not_found_message() {
+        let calls = vec![tool_call("call_1", "missing", "{}")];
+
+        let default = ParallelExecutor::new().execute(calls.clone()).await;
+        assert_eq!(contents(default), vec!["Tool 'missing' not found"]);
+
+        let message = |name: &str| format!("No tool '{}'. Use `search` instead.", name);
+        let parallel = ParallelExecutor::new()
+            .with_not_found_message(message)
+            .execute(calls.clone())
+            .await;
+        let sequential = SequentialExecutor::new()
+            .with_not_found_message(message)
+            .execute(calls)
+            .await;
+        assert_eq!(
+            contents(parallel),
+            vec!["No tool 'missing'. Use `search` instead."]
+        );
+        assert_eq!(
+            contents(sequential),
+            vec!["No tool 'missing'. Use `search` instead."]
+        );
+    }
+
+    struct EchoId;
+
+    #[async_trait]
+    impl Tool for EchoId {
+        async fn call(&self, _args: String) -> String {
+            unreachable!()
+        }
+
+        async fn call_with_meta(&self, call: &ToolCall) -> String {
+            format!("{} {}", call.function.name, call.id)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_call_with_meta() {
+        let mut parallel = ParallelExecutor::new();
+        let mut sequential = SequentialExecutor::new();
+        parallel.add("echo".into(), Box::new(EchoId));
+        sequential.add("echo".into(), Box::new(EchoId));
+        let calls = vec![tool_call("1", "echo", "{}"), tool_call("2", "echo", "{}")];
+
+        assert_eq!(
+            contents(parallel.execute(calls.clone()).await),
+            ["echo 1", "echo 2"]
+        );
+        assert_eq!(
+            contents(sequential.execute(calls).await),
+            ["echo 1", "echo 2"]
+        );
+    }
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn test_tool_call_spans() {
+        fn tools(executor: &mut dyn ToolExecutor) {
+            executor.add(
+                "echo".into(),
+                Box::new(crate::tool::ClosureTool::boxed(|args| {
+                    Box::pin(async move {
+                        tracing::info!("echo called");
+                        args
+                    })
+                })),
+            );
+        }
+        let mut parallel = ParallelExecutor::new();
+        let mut sequential = SequentialExecutor::new();
+        tools(&mut parallel);
+        tools(&mut sequential);
+
+        parallel
+            .execute(vec![
+                tool_call("p1", "echo", "{}"),
+                tool_call("p2", "echo", "{}"),
+                tool_call("p3", "missing", "{}"),
+            ])
+            .await;
+        sequential
+            .execute(vec![tool_call("s1", "echo", "{}")])
+            .await;
+
+        for id in ["p1", "p2", "s1"] {
+            assert!(logs_contain(&format!(
+                "tool_call{{tool_call_id=\"{}\" tool_name=\"echo\"}}: tiny_loop::tool::executor::tests: echo called",
+                id
+            )));
+            assert!(logs_contain(&format!(
+                "tool_call{{tool_call_id=\"{}\" tool_name=\"echo\"}}: tiny_loop::tool: Tool call completed",
+                id
+            )));
+        }
+        assert!(logs_contain(
+            "tool_call{tool_call_id=\"p3\" tool_name=\"missing\"}: tiny_loop::tool::executor: Tool 'missing' not found"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_keeps_interrupted_results() {
+        fn tools(executor: &mut dyn ToolExecutor) {
+            executor.add(
+                "fast".into(),
+                Box::new(crate::tool::ClosureTool::boxed(|_| {
+                    Box::pin(async { "done".into() })
+                })),
+            );
+            executor.add(
+                "hang".into(),
+                Box::new(crate::tool::ClosureTool::boxed(|_| {
+                    Box::pin(futures::future::pending())
+                })),
+            );
+        }
+        let calls = vec![tool_call("1", "fast", "{}"), tool_call("2", "hang", "{}")];
+
+        let mut parallel = ParallelExecutor::new();
+        let mut sequential = SequentialExecutor::new();
+        tools(&mut parallel);
+        tools(&mut sequential);
+        let executors: [&dyn ToolExecutor; 2] = [&parallel, &sequential];
+        for executor in executors {
+            let interrupted = tokio::time::timeout(
+                std::time::Duration::from_millis(50),
+                executor.execute(calls.clone()),
+            )
+            .await;
+            assert!(interrupted.is_err());
+
+            let results = executor.shutdown().await;
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].tool_message.tool_call_id, "1");
+            assert_eq!(results[0].tool_message.content, "done");
+            assert!(executor.shutdown().await.is_empty());
+        }
+    }
+}
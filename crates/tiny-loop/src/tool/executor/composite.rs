@@ -0,0 +1,232 @@
+use crate::{
+    tool::{Tool, executor::ToolExecutor},
+    types::{ToolCall, ToolResult},
+};
+use async_trait::async_trait;
+use futures::future::join_all;
+use std::collections::HashMap;
+
+/// Routes tool calls to named sub-executors, to mix execution strategies in one agent
+///
+/// # How it works
+///
+/// 1. [`route`](Self::route) assigns a tool name to a sub-executor;
+///    other tools go to the default executor
+/// 2. [`add`](ToolExecutor::add) registers each tool in its assigned executor,
+///    so routes must be set before the tools are registered
+/// 3. [`execute`](ToolExecutor::execute) partitions the calls per executor,
+///    runs the executors concurrently and returns the results in the order of the calls
+///
+/// # Example
+/// ```
+/// use tiny_loop::{Agent, llm::OpenAIProvider, tool::{CompositeExecutor, ParallelExecutor, SequentialExecutor, tool}};
+///
+/// /// Append a line to the log
+/// #[tool]
+/// async fn append(
+///     /// Line to append
+///     line: String,
+/// ) -> String {
+///     line
+/// }
+///
+/// /// Search the web
+/// #[tool]
+/// async fn search(
+///     /// Search query
+///     query: String,
+/// ) -> String {
+///     query
+/// }
+///
+/// // `append` calls run one by one, `search` calls in parallel
+/// let executor = CompositeExecutor::new(ParallelExecutor::new())
+///     .with_executor("stateful", SequentialExecutor::new())
+///     .route("append", "stateful");
+/// let agent = Agent::new(OpenAIProvider::new())
+///     .executor(executor)
+///     .tool(append)
+///     .tool(search);
+/// ```
+pub struct CompositeExecutor {
+    default: Box<dyn ToolExecutor + Sync>,
+    executors: HashMap<String, Box<dyn ToolExecutor + Sync>>,
+    /// Executor name by tool name
+    routes: HashMap<String, String>,
+}
+
+impl CompositeExecutor {
+    /// Create a composite executor running unrouted tools with `default`
+    pub fn new(default: impl ToolExecutor + Sync + 'static) -> Self {
+        Self {
+            default: Box::new(default),
+            executors: HashMap::new(),
+            routes: HashMap::new(),
+        }
+    }
+
+    /// Add a sub-executor under the given name
+    pub fn with_executor(
+        mut self,
+        name: impl Into<String>,
+        executor: impl ToolExecutor + Sync + 'static,
+    ) -> Self {
+        self.executors.insert(name.into(), Box::new(executor));
+        self
+    }
+
+    /// Run the tool `tool` with the sub-executor named `executor`
+    pub fn route(mut self, tool: impl Into<String>, executor: impl Into<String>) -> Self {
+        self.routes.insert(tool.into(), executor.into());
+        self
+    }
+
+    /// Name of the sub-executor for the given tool, `None` for the default executor
+    fn executor_name(&self, tool: &str) -> Option<&str> {
+        self.routes
+            .get(tool)
+            .map(String::as_str)
+            .filter(|name| self.executors.contains_key(*name))
+    }
+
+    fn executor(&self, name: Option<&str>) -> &(dyn ToolExecutor + Sync) {
+        match name {
+            Some(name) => self.executors[name].as_ref(),
+            None => self.default.as_ref(),
+        }
+    }
+}
+
+#[async_trait]
+impl ToolExecutor for CompositeExecutor {
+    fn add(&mut self, name: String, tool: Box<dyn Tool + Sync>) -> Option<Box<dyn Tool + Sync>> {
+        if let Some(executor) = self.routes.get(&name)
+            && !self.executors.contains_key(executor)
+        {
+            tracing::warn!(
+                "Executor '{}' for tool '{}' not found, using the default executor",
+                executor,
+                name
+            );
+        }
+        match self.executor_name(&name).map(str::to_string) {
+            Some(executor) => self.executors.get_mut(&executor).unwrap().add(name, tool),
+            None => self.default.add(name, tool),
+        }
+    }
+
+    fn add_alias(&mut self, alias: String, name: String) -> bool {
+        match self.executor_name(&name).map(str::to_string) {
+            Some(executor) => {
+                self.routes.insert(alias.clone(), executor.clone());
+                self.executors
+                    .get_mut(&executor)
+                    .unwrap()
+                    .add_alias(alias, name)
+            }
+            None => self.default.add_alias(alias, name),
+        }
+    }
+
+    async fn execute(&self, calls: Vec<ToolCall>) -> Vec<ToolResult> {
+        let order: HashMap<_, _> = calls
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (c.id.clone(), i))
+            .collect();
+        let mut groups: Vec<(Option<&str>, Vec<ToolCall>)> = Vec::new();
+        for call in calls {
+            let name = self.executor_name(&call.function.name);
+            match groups.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, group)) => group.push(call),
+                None => groups.push((name, vec![call])),
+            }
+        }
+        tracing::debug!("Executing tool calls with {} executors", groups.len());
+
+        let mut results: Vec<_> = join_all(
+            groups
+                .into_iter()
+                .map(|(name, calls)| self.executor(name).execute(calls)),
+        )
+        .await
+        .into_iter()
+        .flatten()
+        .collect();
+        results.sort_by_key(|r| {
+            order
+                .get(&r.tool_message.tool_call_id)
+                .copied()
+                .unwrap_or(usize::MAX)
+        });
+        results
+    }
+
+    async fn shutdown(&self) -> Vec<ToolResult> {
+        let mut results = self.default.shutdown().await;
+        for executor in self.executors.values() {
+            results.extend(executor.shutdown().await);
+        }
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        llm::mock::tool_call,
+        tool::{ClosureTool, ParallelExecutor, SequentialExecutor},
+    };
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    /// Tool sleeping briefly and recording its max concurrency
+    fn concurrent_tool(max: Arc<AtomicUsize>) -> Box<dyn Tool + Sync> {
+        let active = Arc::new(AtomicUsize::new(0));
+        Box::new(ClosureTool::boxed(move |args| {
+            let (active, max) = (active.clone(), max.clone());
+            Box::pin(async move {
+                let n = active.fetch_add(1, Ordering::SeqCst) + 1;
+                max.fetch_max(n, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                active.fetch_sub(1, Ordering::SeqCst);
+                args
+            })
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_routes_to_sub_executors() {
+        let mut executor = CompositeExecutor::new(ParallelExecutor::new())
+            .with_executor("sequential", SequentialExecutor::new())
+            .route("write", "sequential");
+        let write_max = Arc::new(AtomicUsize::new(0));
+        let read_max = Arc::new(AtomicUsize::new(0));
+        executor.add("write".into(), concurrent_tool(write_max.clone()));
+        executor.add("read".into(), concurrent_tool(read_max.clone()));
+
+        let results = executor
+            .execute(vec![
+                tool_call("1", "write", "w1"),
+                tool_call("2", "read", "r1"),
+                tool_call("3", "write", "w2"),
+                tool_call("4", "read", "r2"),
+                tool_call("5", "missing", "{}"),
+            ])
+            .await;
+
+        let contents: Vec<_> = results
+            .iter()
+            .map(|r| r.tool_message.content.as_str())
+            .collect();
+        assert_eq!(
+            contents,
+            vec!["w1", "r1", "w2", "r2", "Tool 'missing' not found"]
+        );
+        assert_eq!(write_max.load(Ordering::SeqCst), 1);
+        assert_eq!(read_max.load(Ordering::SeqCst), 2);
+    }
+}
@@ -3,7 +3,7 @@ use crate::{
     types::ToolCall,
 };
 use async_trait::async_trait;
-use futures::future::join_all;
+use futures::{StreamExt, stream::FuturesUnordered};
 use std::collections::HashMap;
 
 /// Executes tools in parallel by grouping calls by tool name and using [`Tool::call_batch`]
@@ -12,7 +12,14 @@ use std::collections::HashMap;
 ///
 /// 1. Groups tool calls by tool name
 /// 2. Executes each group in parallel using [`Tool::call_batch`]
-/// 3. Flattens and returns all results
+/// 3. Returns all results in the order of the calls
+///
+/// If the execution is dropped midway, results of the groups that already
+/// completed can be retrieved with [`ToolExecutor::shutdown`].
+///
+/// The default [`Tool::call_batch`] runs every call of the group concurrently,
+/// so all calls run concurrently. Tools backed by a batch API can override
+/// [`Tool::call_batch`] to handle all calls of one turn in a single request.
 ///
 /// # Example
 ///
@@ -30,9 +37,12 @@ use std::collections::HashMap;
 /// 2. Execute in parallel:
 ///    - `weather_tool.call_batch([call1, call3])` (runs concurrently)
 ///    - `search_tool.call_batch([call2])` (runs concurrently)
-/// 3. Return flattened results: `[result1, result3, result2]`
+/// 3. Return results in call order: `[result1, result2, result3]`
 pub struct ParallelExecutor {
     tools: HashMap<String, Box<dyn Tool + Sync>>,
+    aliases: HashMap<String, String>,
+    not_found: Option<super::NotFoundMessage>,
+    in_flight: super::InFlight,
 }
 
 impl ParallelExecutor {
@@ -40,8 +50,36 @@ impl ParallelExecutor {
     pub fn new() -> Self {
         Self {
             tools: HashMap::new(),
+            aliases: HashMap::new(),
+            not_found: None,
+            in_flight: super::InFlight::default(),
         }
     }
+
+    /// Customize the result sent to the model when it calls an unknown tool
+    /// (default: `Tool '{name}' not found`)
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::tool::ParallelExecutor;
+    ///
+    /// let executor = ParallelExecutor::new().with_not_found_message(|name| {
+    ///     format!("There is no tool named '{}'. Use `search` instead.", name)
+    /// });
+    /// ```
+    pub fn with_not_found_message(
+        mut self,
+        message: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.not_found = Some(Box::new(message));
+        self
+    }
+}
+
+impl Default for ParallelExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[async_trait]
@@ -51,33 +89,191 @@ impl ToolExecutor for ParallelExecutor {
         self.tools.insert(name, tool)
     }
 
+    fn add_alias(&mut self, alias: String, name: String) -> bool {
+        tracing::trace!("Registering tool alias: {} -> {}", alias, name);
+        self.aliases.insert(alias, name);
+        true
+    }
+
     async fn execute(&self, calls: Vec<ToolCall>) -> Vec<crate::types::ToolResult> {
         tracing::debug!("Executing {} tool calls in parallel", calls.len());
+        let order: HashMap<String, usize> = calls
+            .iter()
+            .enumerate()
+            .map(|(i, call)| (call.id.clone(), i))
+            .collect();
         let mut grouped: HashMap<String, Vec<ToolCall>> = HashMap::new();
         for call in calls {
+            let name = &call.function.name;
             grouped
-                .entry(call.function.name.clone())
+                .entry(self.aliases.get(name).unwrap_or(name).clone())
                 .or_default()
                 .push(call);
         }
 
         tracing::trace!("Grouped into {} unique tools", grouped.len());
 
-        let futures = grouped.into_iter().map(|(name, calls)| async move {
-            tracing::debug!("Executing {} calls for tool '{}'", calls.len(), name);
-            if let Some(tool) = self.tools.get(&name) {
-                tool.call_batch(calls).await
-            } else {
-                tracing::debug!("Tool '{}' not found", name);
-                calls
-                    .into_iter()
-                    .map(|call| super::tool_not_found_result(call.id, &name))
-                    .collect::<Vec<_>>()
-            }
-        });
+        let mut batch = self.in_flight.start();
+        let mut futures: FuturesUnordered<_> = grouped
+            .into_iter()
+            .map(|(name, calls)| async move {
+                tracing::debug!("Executing {} calls for tool '{}'", calls.len(), name);
+                if let Some(tool) = self.tools.get(&name) {
+                    tool.call_batch(calls).await
+                } else {
+                    calls
+                        .into_iter()
+                        .map(|call| {
+                            super::tool_not_found_result(call.id, &name, self.not_found.as_ref())
+                        })
+                        .collect::<Vec<_>>()
+                }
+            })
+            .collect();
+        while let Some(results) = futures.next().await {
+            batch.results.extend(results);
+        }
 
-        let results = join_all(futures).await.into_iter().flatten().collect();
+        let mut results = batch.finish();
+        results.sort_by_key(|r| {
+            order
+                .get(&r.tool_message.tool_call_id)
+                .copied()
+                .unwrap_or(usize::MAX)
+        });
         tracing::debug!("Parallel execution completed");
         results
     }
+
+    async fn shutdown(&self) -> Vec<crate::types::ToolResult> {
+        self.in_flight.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{llm::mock::tool_call, tool::ClosureTool, types::ToolResult};
+    use std::{sync::Arc, time::Duration};
+
+    #[tokio::test]
+    async fn test_results_in_call_order() {
+        let mut executor = ParallelExecutor::new();
+        for (name, delay) in [("slow", 30), ("fast", 0)] {
+            executor.add(
+                name.into(),
+                Box::new(ClosureTool::boxed(move |args| {
+                    Box::pin(async move {
+                        tokio::time::sleep(Duration::from_millis(delay)).await;
+                        args
+                    })
+                })),
+            );
+        }
+
+        let results = executor
+            .execute(vec![
+                tool_call("1", "slow", "a"),
+                tool_call("2", "fast", "b"),
+                tool_call("3", "slow", "c"),
+                tool_call("4", "missing", "d"),
+                tool_call("5", "fast", "e"),
+            ])
+            .await;
+
+        let ids: Vec<_> = results
+            .iter()
+            .map(|r| r.tool_message.tool_call_id.as_str())
+            .collect();
+        assert_eq!(ids, ["1", "2", "3", "4", "5"]);
+        assert_eq!(results[2].tool_message.content, "c");
+    }
+
+    #[tokio::test]
+    async fn test_calls_run_concurrently() {
+        // Every call waits for all others, so this only finishes if they run concurrently
+        let barrier = Arc::new(tokio::sync::Barrier::new(3));
+        let mut executor = ParallelExecutor::new();
+        for name in ["a", "b"] {
+            let barrier = barrier.clone();
+            executor.add(
+                name.into(),
+                Box::new(ClosureTool::boxed(move |_| {
+                    let barrier = barrier.clone();
+                    Box::pin(async move {
+                        barrier.wait().await;
+                        "ok".into()
+                    })
+                })),
+            );
+        }
+
+        let calls = vec![
+            tool_call("1", "a", "{}"),
+            tool_call("2", "b", "{}"),
+            tool_call("3", "a", "{}"),
+        ];
+        let results = tokio::time::timeout(Duration::from_secs(5), executor.execute(calls))
+            .await
+            .expect("calls did not run concurrently");
+        assert_eq!(results.len(), 3);
+    }
+
+    /// Tool handling all calls of a turn in one batch
+    struct BatchTool;
+
+    #[async_trait]
+    impl Tool for BatchTool {
+        async fn call(&self, _args: String) -> String {
+            unreachable!()
+        }
+
+        async fn call_batch(&self, calls: Vec<ToolCall>) -> Vec<ToolResult> {
+            let size = calls.len();
+            calls
+                .into_iter()
+                .rev()
+                .map(|call| ToolResult {
+                    tool_message: crate::types::ToolMessage {
+                        tool_call_id: call.id,
+                        content: format!("batch of {}", size),
+                    },
+                    timestamp: std::time::SystemTime::now(),
+                    elapsed: Duration::ZERO,
+                })
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_tool_receives_grouped_calls() {
+        let mut executor = ParallelExecutor::new();
+        executor.add("batch".into(), Box::new(BatchTool));
+
+        let results = executor
+            .execute(vec![
+                tool_call("1", "batch", "{}"),
+                tool_call("2", "missing", "{}"),
+                tool_call("3", "batch", "{}"),
+            ])
+            .await;
+
+        let results: Vec<_> = results
+            .iter()
+            .map(|r| {
+                (
+                    r.tool_message.tool_call_id.as_str(),
+                    r.tool_message.content.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            results,
+            [
+                ("1", "batch of 2"),
+                ("2", "Tool 'missing' not found"),
+                ("3", "batch of 2")
+            ]
+        );
+    }
 }
@@ -0,0 +1,117 @@
+use crate::{
+    tool::{Tool, executor::ToolExecutor},
+    types::{ToolCall, ToolResult},
+};
+use async_trait::async_trait;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+/// Returns scripted results instead of executing tools, for deterministic tests.
+/// Requires the `test-util` feature.
+///
+/// Each call pops the next result scripted for its tool name.
+/// Once a tool's results run out, the registered tool with that name is executed if any,
+/// otherwise the fallback result is returned.
+///
+/// # Example
+/// ```
+/// use tiny_loop::{Agent, llm::mock::MockProvider, tool::ScriptedExecutor};
+///
+/// # async fn example() -> tiny_loop::Result<()> {
+/// let llm = MockProvider::new()
+///     .tool_calls("", &[("call_1", "weather", r#"{"city":"Paris"}"#)])
+///     .text("It is sunny in Paris");
+/// let executor = ScriptedExecutor::new().result("weather", "Sunny, 25°C");
+/// let mut agent = Agent::new(llm).executor(executor);
+/// agent.chat("Weather in Paris?").await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ScriptedExecutor {
+    scripts: Mutex<HashMap<String, VecDeque<String>>>,
+    tools: HashMap<String, Box<dyn Tool + Sync>>,
+    fallback: String,
+}
+
+impl ScriptedExecutor {
+    /// Create an executor without scripted results
+    pub fn new() -> Self {
+        Self::from_scripts(HashMap::new())
+    }
+
+    /// Create an executor with scripted results per tool name
+    pub fn from_scripts(scripts: HashMap<String, VecDeque<String>>) -> Self {
+        Self {
+            scripts: Mutex::new(scripts),
+            tools: HashMap::new(),
+            fallback: "No scripted result".into(),
+        }
+    }
+
+    /// Queue a result for the given tool
+    pub fn result(self, name: impl Into<String>, content: impl Into<String>) -> Self {
+        self.scripts
+            .lock()
+            .unwrap()
+            .entry(name.into())
+            .or_default()
+            .push_back(content.into());
+        self
+    }
+
+    /// Set the result returned when no result is scripted and no tool is registered
+    /// (default: `"No scripted result"`)
+    pub fn fallback(mut self, content: impl Into<String>) -> Self {
+        self.fallback = content.into();
+        self
+    }
+}
+
+impl Default for ScriptedExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl ToolExecutor for ScriptedExecutor {
+    fn add(&mut self, name: String, tool: Box<dyn Tool + Sync>) -> Option<Box<dyn Tool + Sync>> {
+        tracing::trace!("Registering tool: {}", name);
+        self.tools.insert(name, tool)
+    }
+
+    async fn execute(&self, calls: Vec<ToolCall>) -> Vec<ToolResult> {
+        let mut results = Vec::new();
+        for call in calls {
+            let scripted = self
+                .scripts
+                .lock()
+                .unwrap()
+                .get_mut(&call.function.name)
+                .and_then(|q| q.pop_front());
+            let result = match (scripted, self.tools.get(&call.function.name)) {
+                (Some(content), _) => ToolResult {
+                    tool_message: crate::types::ToolMessage {
+                        tool_call_id: call.id,
+                        content,
+                    },
+                    timestamp: std::time::SystemTime::now(),
+                    elapsed: std::time::Duration::ZERO,
+                },
+                (None, Some(tool)) => tool.call_timed(call).await,
+                (None, None) => ToolResult {
+                    tool_message: crate::types::ToolMessage {
+                        tool_call_id: call.id,
+                        content: self.fallback.clone(),
+                    },
+                    timestamp: std::time::SystemTime::now(),
+                    elapsed: std::time::Duration::ZERO,
+                },
+            };
+            results.push(result);
+        }
+        results
+    }
+}
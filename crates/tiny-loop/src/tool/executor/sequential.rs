@@ -30,8 +30,14 @@ use async_trait::async_trait;
 /// 2. Execute `search_tool.call(call2)` and wait for completion
 /// 3. Execute `weather_tool.call(call3)` and wait for completion
 /// 4. Return results in order: `[result1, result2, result3]`
+///
+/// If the execution is dropped midway, results of the calls that already
+/// completed can be retrieved with [`ToolExecutor::shutdown`].
 pub struct SequentialExecutor {
     tools: HashMap<String, Box<dyn Tool + Sync>>,
+    aliases: HashMap<String, String>,
+    not_found: Option<super::NotFoundMessage>,
+    in_flight: super::InFlight,
 }
 
 impl SequentialExecutor {
@@ -39,8 +45,36 @@ impl SequentialExecutor {
     pub fn new() -> Self {
         Self {
             tools: HashMap::new(),
+            aliases: HashMap::new(),
+            not_found: None,
+            in_flight: super::InFlight::default(),
         }
     }
+
+    /// Customize the result sent to the model when it calls an unknown tool
+    /// (default: `Tool '{name}' not found`)
+    ///
+    /// # Example
+    /// ```
+    /// use tiny_loop::tool::SequentialExecutor;
+    ///
+    /// let executor = SequentialExecutor::new().with_not_found_message(|name| {
+    ///     format!("There is no tool named '{}'. Use `search` instead.", name)
+    /// });
+    /// ```
+    pub fn with_not_found_message(
+        mut self,
+        message: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.not_found = Some(Box::new(message));
+        self
+    }
+}
+
+impl Default for SequentialExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[async_trait]
@@ -50,19 +84,33 @@ impl ToolExecutor for SequentialExecutor {
         self.tools.insert(name, tool)
     }
 
+    fn add_alias(&mut self, alias: String, name: String) -> bool {
+        tracing::trace!("Registering tool alias: {} -> {}", alias, name);
+        self.aliases.insert(alias, name);
+        true
+    }
+
     async fn execute(&self, calls: Vec<ToolCall>) -> Vec<crate::types::ToolResult> {
         tracing::debug!("Executing {} tool calls sequentially", calls.len());
-        let mut results = Vec::new();
+        let mut batch = self.in_flight.start();
         for call in calls {
             tracing::debug!("Executing tool '{}'", call.function.name);
-            if let Some(tool) = self.tools.get(&call.function.name) {
-                results.push(tool.call_timed(call).await);
+            let name = &call.function.name;
+            if let Some(tool) = self.tools.get(self.aliases.get(name).unwrap_or(name)) {
+                batch.results.push(tool.call_timed(call).await);
             } else {
-                tracing::debug!("Tool '{}' not found", call.function.name);
-                results.push(super::tool_not_found_result(call.id, &call.function.name));
+                batch.results.push(super::tool_not_found_result(
+                    call.id,
+                    &call.function.name,
+                    self.not_found.as_ref(),
+                ));
             }
         }
         tracing::debug!("Sequential execution completed");
-        results
+        batch.finish()
+    }
+
+    async fn shutdown(&self) -> Vec<crate::types::ToolResult> {
+        self.in_flight.shutdown().await
     }
 }
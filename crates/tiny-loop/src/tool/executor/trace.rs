@@ -0,0 +1,247 @@
+use crate::{
+    tool::{Tool, executor::ToolExecutor},
+    types::{ToolCall, ToolResult},
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// A single tool call recorded by [`TracingExecutor`]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CallTrace {
+    /// Tool call ID
+    pub id: String,
+    /// Tool name
+    pub name: String,
+    /// JSON-encoded arguments
+    pub arguments: String,
+    /// Tool result content
+    pub result: String,
+    /// When the tool execution started
+    pub start: SystemTime,
+    /// Time taken to execute the tool
+    pub elapsed: Duration,
+}
+
+/// All tool calls executed in one agent step, recorded by [`TracingExecutor`]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StepTrace {
+    /// Zero-based index of the step
+    pub step: usize,
+    /// When the step's tool execution started
+    pub start: SystemTime,
+    /// Time taken to execute all tool calls of the step
+    pub elapsed: Duration,
+    /// Tool calls in the order they were requested
+    pub calls: Vec<CallTrace>,
+}
+
+/// Shared handle to the traces recorded by a [`TracingExecutor`].
+///
+/// Cloning the handle is cheap and all clones observe the same traces,
+/// so it can be kept after the executor is moved into an [`Agent`](crate::Agent).
+#[derive(Clone, Default)]
+pub struct Traces(Arc<Mutex<Vec<StepTrace>>>);
+
+impl Traces {
+    /// Get a snapshot of all recorded steps
+    pub fn get(&self) -> Vec<StepTrace> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Remove all recorded steps
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+
+    /// Export recorded steps in the [Chrome trace event format](https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU),
+    /// viewable in `chrome://tracing` or [Perfetto](https://ui.perfetto.dev).
+    ///
+    /// Each step is a complete event on thread 0; each tool call is a complete event
+    /// on its own thread so concurrent calls render side by side.
+    pub fn to_chrome_trace(&self) -> Value {
+        fn micros(t: SystemTime) -> u128 {
+            t.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros()
+        }
+
+        let mut events = Vec::new();
+        for step in self.0.lock().unwrap().iter() {
+            events.push(json!({
+                "name": format!("step {}", step.step),
+                "cat": "step",
+                "ph": "X",
+                "ts": micros(step.start),
+                "dur": step.elapsed.as_micros(),
+                "pid": 1,
+                "tid": 0,
+            }));
+            for (i, call) in step.calls.iter().enumerate() {
+                events.push(json!({
+                    "name": call.name,
+                    "cat": "tool",
+                    "ph": "X",
+                    "ts": micros(call.start),
+                    "dur": call.elapsed.as_micros(),
+                    "pid": 1,
+                    "tid": i + 1,
+                    "args": {
+                        "id": call.id,
+                        "arguments": call.arguments,
+                        "result": call.result,
+                    },
+                }));
+            }
+        }
+        json!({ "traceEvents": events })
+    }
+}
+
+/// Wraps another executor and records every [`execute`](ToolExecutor::execute) call as a [`StepTrace`]
+///
+/// # Example
+///
+/// ```
+/// use tiny_loop::{Agent, llm::OpenAIProvider, tool::{ParallelExecutor, TracingExecutor}};
+///
+/// let executor = TracingExecutor::new(ParallelExecutor::new());
+/// let traces = executor.traces();
+/// let agent = Agent::new(OpenAIProvider::new()).executor(executor);
+///
+/// // After running the agent:
+/// let json = traces.to_chrome_trace();
+/// ```
+pub struct TracingExecutor {
+    inner: Box<dyn ToolExecutor + Sync>,
+    traces: Traces,
+}
+
+impl TracingExecutor {
+    /// Create a new tracing executor wrapping the given executor
+    pub fn new(inner: impl ToolExecutor + Sync + 'static) -> Self {
+        Self {
+            inner: Box::new(inner),
+            traces: Traces::default(),
+        }
+    }
+
+    /// Get a shared handle to the recorded traces
+    pub fn traces(&self) -> Traces {
+        self.traces.clone()
+    }
+}
+
+#[async_trait]
+impl ToolExecutor for TracingExecutor {
+    fn add(&mut self, name: String, tool: Box<dyn Tool + Sync>) -> Option<Box<dyn Tool + Sync>> {
+        self.inner.add(name, tool)
+    }
+
+    fn add_alias(&mut self, alias: String, name: String) -> bool {
+        self.inner.add_alias(alias, name)
+    }
+
+    async fn execute(&self, calls: Vec<ToolCall>) -> Vec<ToolResult> {
+        let requested: Vec<_> = calls
+            .iter()
+            .map(|c| {
+                (
+                    c.id.clone(),
+                    c.function.name.clone(),
+                    c.function.arguments.clone(),
+                )
+            })
+            .collect();
+
+        let start = SystemTime::now();
+        let results = self.inner.execute(calls).await;
+        let elapsed = start.elapsed().unwrap_or_default();
+
+        let calls = requested
+            .into_iter()
+            .filter_map(|(id, name, arguments)| {
+                let r = results.iter().find(|r| r.tool_message.tool_call_id == id)?;
+                Some(CallTrace {
+                    id,
+                    name,
+                    arguments,
+                    result: r.tool_message.content.clone(),
+                    start: r.timestamp - r.elapsed,
+                    elapsed: r.elapsed,
+                })
+            })
+            .collect();
+
+        let mut traces = self.traces.0.lock().unwrap();
+        let step = traces.len();
+        tracing::trace!("Recorded trace for step {}", step);
+        traces.push(StepTrace {
+            step,
+            start,
+            elapsed,
+            calls,
+        });
+        drop(traces);
+
+        results
+    }
+
+    async fn shutdown(&self) -> Vec<ToolResult> {
+        self.inner.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tool::{ClosureTool, ParallelExecutor},
+        types::FunctionCall,
+    };
+
+    fn call(id: &str, name: &str, args: &str) -> ToolCall {
+        ToolCall {
+            id: id.into(),
+            call_type: "function".into(),
+            function: FunctionCall {
+                name: name.into(),
+                arguments: args.into(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_records_steps_and_exports_chrome_trace() {
+        let mut executor = TracingExecutor::new(ParallelExecutor::new());
+        executor.add(
+            "echo".into(),
+            Box::new(ClosureTool::boxed(|s| Box::pin(async move { s }))),
+        );
+        let traces = executor.traces();
+
+        executor
+            .execute(vec![call("1", "echo", "a"), call("2", "echo", "b")])
+            .await;
+        executor.execute(vec![call("3", "missing", "c")]).await;
+
+        let steps = traces.get();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].calls.len(), 2);
+        assert_eq!(steps[0].calls[1].arguments, "b");
+        assert_eq!(steps[0].calls[1].result, "b");
+        assert_eq!(steps[1].step, 1);
+        assert_eq!(steps[1].calls[0].result, "Tool 'missing' not found");
+
+        let chrome = traces.to_chrome_trace();
+        let events = chrome["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 5);
+        assert_eq!(events[0]["ph"], "X");
+        assert_eq!(events[1]["name"], "echo");
+        assert_eq!(events[1]["args"]["id"], "1");
+
+        serde_json::to_string(&steps).unwrap();
+    }
+}
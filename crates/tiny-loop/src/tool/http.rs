@@ -0,0 +1,327 @@
+use std::{collections::HashMap, time::Duration};
+use tiny_loop_macros::tool_internal;
+
+/// Default maximum number of response body bytes returned to the model
+const DEFAULT_MAX_BODY_BYTES: usize = 16 * 1024;
+/// Default timeout of a whole request
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// General HTTP request tool with scheme and host allowlisting.
+///
+/// Only URLs whose scheme and host are explicitly allowed can be requested,
+/// and redirects are not followed, to prevent server-side request forgery.
+/// By default only `https` is allowed and no hosts are allowed.
+/// Response bodies are read up to the size limit only.
+///
+/// # Example
+/// ```
+/// use tiny_loop::{Agent, llm::OpenAIProvider, tool::HttpTool};
+///
+/// let http = HttpTool::new().allow_host("api.github.com");
+/// let agent = Agent::new(OpenAIProvider::new())
+///     .bind(http, HttpTool::http_request);
+/// ```
+#[derive(Clone)]
+pub struct HttpTool {
+    client: reqwest::Client,
+    allowed_schemes: Vec<String>,
+    allowed_hosts: Vec<String>,
+    max_body_bytes: usize,
+    timeout: Duration,
+}
+
+impl Default for HttpTool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HttpTool {
+    /// Create a new HTTP tool allowing `https` and no hosts
+    ///
+    /// # Panics
+    ///
+    /// Panics if the HTTP client cannot be initialized, e.g. if the TLS backend fails to load.
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .expect("failed to build the HTTP client"),
+            allowed_schemes: vec!["https".into()],
+            allowed_hosts: Vec::new(),
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Allow requests using the given URL scheme, e.g. `http`
+    pub fn allow_scheme(mut self, scheme: impl Into<String>) -> Self {
+        self.allowed_schemes.push(scheme.into());
+        self
+    }
+
+    /// Allow requests to the given host, e.g. `api.example.com`
+    pub fn allow_host(mut self, host: impl Into<String>) -> Self {
+        self.allowed_hosts.push(host.into());
+        self
+    }
+
+    /// Set the maximum number of response body bytes returned to the model (default: 16 KiB)
+    pub fn max_body_bytes(mut self, limit: usize) -> Self {
+        self.max_body_bytes = limit;
+        self
+    }
+
+    /// Set the timeout of a whole request, including reading the body (default: 30 seconds)
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Check the URL against the allowlists
+    fn check_url(&self, url: &str) -> Result<reqwest::Url, String> {
+        let url = reqwest::Url::parse(url).map_err(|e| format!("Invalid URL: {}", e))?;
+        if !self.allowed_schemes.iter().any(|s| s == url.scheme()) {
+            return Err(format!("Scheme '{}' is not allowed", url.scheme()));
+        }
+        let host = url.host_str().unwrap_or_default();
+        if !self.allowed_hosts.iter().any(|h| h == host) {
+            return Err(format!("Host '{}' is not allowed", host));
+        }
+        Ok(url)
+    }
+}
+
+#[tool_internal]
+impl HttpTool {
+    /// Send an HTTP request and return the status, response headers and (truncated) body
+    pub async fn http_request(
+        self,
+        /// HTTP method, e.g. GET, POST, PUT, DELETE
+        method: String,
+        /// Absolute URL to request
+        url: String,
+        /// Request headers
+        headers: Option<HashMap<String, String>>,
+        /// Request body
+        body: Option<String>,
+    ) -> String {
+        let url = match self.check_url(&url) {
+            Ok(url) => url,
+            Err(e) => return e,
+        };
+        let method = match reqwest::Method::from_bytes(method.to_uppercase().as_bytes()) {
+            Ok(method) => method,
+            Err(e) => return format!("Invalid method: {}", e),
+        };
+
+        tracing::debug!("HTTP tool request: {} {}", method, url);
+        let mut request = self.client.request(method, url).timeout(self.timeout);
+        for (k, v) in headers.unwrap_or_default() {
+            request = request.header(k, v);
+        }
+        if let Some(body) = body {
+            request = request.body(body);
+        }
+
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(e) => return format!("Request failed: {}", e),
+        };
+
+        let mut result = format!("Status: {}\nHeaders:\n", response.status());
+        for (k, v) in response.headers() {
+            result.push_str(&format!("{}: {}\n", k, v.to_str().unwrap_or("<binary>")));
+        }
+        let total = response.content_length();
+        let (body, truncated) = match self.read_body(response).await {
+            Ok(body) => body,
+            Err(e) => return format!("{}\nFailed to read body: {}", result, e),
+        };
+        result.push_str("\nBody:\n");
+        result.push_str(&body);
+        if truncated {
+            match total {
+                Some(total) => result.push_str(&format!("\n[Truncated, {} bytes total]", total)),
+                None => result.push_str(&format!(
+                    "\n[Truncated after {} bytes, the rest was not read]",
+                    self.max_body_bytes
+                )),
+            }
+        }
+        result
+    }
+}
+
+impl HttpTool {
+    /// Read at most `max_body_bytes` of the body, cut at a char boundary.
+    /// Return the text and whether the body was longer
+    async fn read_body(&self, response: reqwest::Response) -> reqwest::Result<(String, bool)> {
+        use futures::TryStreamExt;
+
+        let mut stream = response.bytes_stream();
+        let mut body = Vec::new();
+        let mut truncated = false;
+        while let Some(chunk) = stream.try_next().await? {
+            let room = self.max_body_bytes - body.len();
+            if chunk.len() > room {
+                body.extend_from_slice(&chunk[..room]);
+                truncated = true;
+                break;
+            }
+            body.extend_from_slice(&chunk);
+        }
+        if truncated && let Err(e) = std::str::from_utf8(&body) {
+            // Drop a char split by the limit
+            if e.error_len().is_none() {
+                body.truncate(e.valid_up_to());
+            }
+        }
+        Ok((String::from_utf8_lossy(&body).into_owned(), truncated))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::mock::MockServer;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::AsyncWriteExt;
+
+    async fn server() -> (String, Arc<Mutex<Vec<String>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let r = requests.clone();
+        let url = MockServer::spawn(move |mut stream, request| {
+            r.lock().unwrap().push(request);
+            async move {
+                let body = r#"{"ok":true}"#;
+                let response = format!(
+                    "HTTP/1.1 201 Created\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        })
+        .await;
+        (url, requests)
+    }
+
+    fn tool() -> HttpTool {
+        HttpTool::new().allow_scheme("http").allow_host("127.0.0.1")
+    }
+
+    #[tokio::test]
+    async fn test_get_with_headers() {
+        let (url, requests) = server().await;
+        let result = tool()
+            .http_request(HttpRequestArgs {
+                method: "get".into(),
+                url: format!("{}/items", url),
+                headers: Some(HashMap::from([("x-api-key".into(), "secret".into())])),
+                body: None,
+            })
+            .await;
+
+        assert!(result.starts_with("Status: 201 Created"));
+        assert!(result.contains("content-type: application/json"));
+        assert!(result.ends_with("Body:\n{\"ok\":true}"));
+        let request = requests.lock().unwrap()[0].clone();
+        assert!(request.starts_with("GET /items HTTP/1.1"));
+        assert!(request.contains("x-api-key: secret"));
+    }
+
+    #[tokio::test]
+    async fn test_post_with_body() {
+        let (url, requests) = server().await;
+        let result = tool()
+            .max_body_bytes(4)
+            .http_request(HttpRequestArgs {
+                method: "POST".into(),
+                url,
+                headers: Some(HashMap::from([(
+                    "content-type".into(),
+                    "application/json".into(),
+                )])),
+                body: Some(r#"{"name":"x"}"#.into()),
+            })
+            .await;
+
+        assert!(result.contains("Body:\n{\"ok\n[Truncated, 11 bytes total]"));
+        let request = requests.lock().unwrap()[0].clone();
+        assert!(request.starts_with("POST / HTTP/1.1"));
+        assert!(request.ends_with(r#"{"name":"x"}"#));
+    }
+
+    #[tokio::test]
+    async fn test_large_body_without_length() {
+        let url = MockServer::spawn(|mut stream, _| async move {
+            let head = "HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n";
+            let _ = stream.write_all(head.as_bytes()).await;
+            // Far more than the limit; the tool stops reading before the end
+            for _ in 0..1024 {
+                if stream.write_all(&[b'x'; 1024]).await.is_err() {
+                    break;
+                }
+            }
+        })
+        .await;
+
+        let result = tool()
+            .max_body_bytes(5)
+            .http_request(HttpRequestArgs {
+                method: "GET".into(),
+                url,
+                headers: None,
+                body: None,
+            })
+            .await;
+        assert!(result.ends_with("Body:\nxxxxx\n[Truncated after 5 bytes, the rest was not read]"));
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let url = MockServer::spawn(|stream, _| async move {
+            let _stream = stream;
+            futures::future::pending::<()>().await
+        })
+        .await;
+
+        let request = tool()
+            .timeout(Duration::from_millis(50))
+            .http_request(HttpRequestArgs {
+                method: "GET".into(),
+                url,
+                headers: None,
+                body: None,
+            });
+        let result = tokio::time::timeout(Duration::from_secs(5), request)
+            .await
+            .expect("request did not time out");
+        assert!(result.starts_with("Request failed"), "{}", result);
+    }
+
+    #[tokio::test]
+    async fn test_disallowed_urls() {
+        let args = |url: &str| HttpRequestArgs {
+            method: "GET".into(),
+            url: url.into(),
+            headers: None,
+            body: None,
+        };
+        assert_eq!(
+            HttpTool::new()
+                .http_request(args("http://example.com"))
+                .await,
+            "Scheme 'http' is not allowed"
+        );
+        assert_eq!(
+            HttpTool::new()
+                .allow_host("example.com")
+                .http_request(args("https://169.254.169.254/latest"))
+                .await,
+            "Host '169.254.169.254' is not allowed"
+        );
+    }
+}
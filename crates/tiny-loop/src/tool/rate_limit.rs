@@ -0,0 +1,125 @@
+use crate::{tool::Tool, types::ToolCall};
+use async_trait::async_trait;
+use std::{sync::Mutex, time::Duration};
+use tokio::time::Instant;
+
+/// Tool wrapper spacing the calls of the inner tool by a minimum interval,
+/// e.g. for tools hitting an external API with its own rate limit.
+///
+/// Calls wait for their slot before calling the inner tool, so the limit
+/// holds under any executor, including concurrent calls of [`ParallelExecutor`](crate::tool::ParallelExecutor).
+///
+/// # Example
+/// ```
+/// use tiny_loop::tool::{ParallelExecutor, RateLimitedTool, Tool, ToolExecutor};
+/// use std::time::Duration;
+///
+/// struct Geocode;
+///
+/// #[async_trait::async_trait]
+/// impl Tool for Geocode {
+///     async fn call(&self, args: String) -> String {
+///         format!("Coordinates of {}", args)
+///     }
+/// }
+///
+/// let mut executor = ParallelExecutor::new();
+/// // At most one request per second
+/// executor.add(
+///     "geocode".into(),
+///     Box::new(RateLimitedTool::new(Geocode, Duration::from_secs(1))),
+/// );
+/// ```
+pub struct RateLimitedTool<T> {
+    inner: T,
+    min_interval: Duration,
+    /// Earliest start of the next call
+    next_slot: Mutex<Option<Instant>>,
+}
+
+impl<T> RateLimitedTool<T> {
+    /// Wrap `inner` so its calls start at least `min_interval` apart
+    pub fn new(inner: T, min_interval: Duration) -> Self {
+        Self {
+            inner,
+            min_interval,
+            next_slot: Mutex::new(None),
+        }
+    }
+
+    /// Reserve the next slot and return its start
+    fn reserve(&self) -> Instant {
+        let mut next_slot = self.next_slot.lock().unwrap();
+        let slot = next_slot.map_or(Instant::now(), |next| next.max(Instant::now()));
+        *next_slot = Some(slot + self.min_interval);
+        slot
+    }
+
+    /// Wait for the next slot
+    async fn wait(&self) {
+        let slot = self.reserve();
+        if slot > Instant::now() {
+            tracing::debug!("Tool rate limited, waiting {:?}", slot - Instant::now());
+            tokio::time::sleep_until(slot).await;
+        }
+    }
+}
+
+#[async_trait]
+impl<T: Tool + Sync> Tool for RateLimitedTool<T> {
+    async fn call(&self, args: String) -> String {
+        self.wait().await;
+        self.inner.call(args).await
+    }
+
+    async fn call_with_meta(&self, call: &ToolCall) -> String {
+        self.wait().await;
+        self.inner.call_with_meta(call).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        llm::mock::tool_call,
+        tool::{ClosureTool, ParallelExecutor, ToolExecutor},
+    };
+    use std::sync::Arc;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_min_interval() {
+        let start = Instant::now();
+        let starts = Arc::new(Mutex::new(Vec::new()));
+        let s = starts.clone();
+        let tool = ClosureTool::boxed(move |args| {
+            s.lock().unwrap().push(start.elapsed());
+            Box::pin(async move { args })
+        });
+        let mut executor = ParallelExecutor::new();
+        executor.add(
+            "geocode".into(),
+            Box::new(RateLimitedTool::new(tool, Duration::from_millis(500))),
+        );
+
+        let results = executor
+            .execute(vec![
+                tool_call("1", "geocode", "Paris"),
+                tool_call("2", "geocode", "Rome"),
+            ])
+            .await;
+        executor
+            .execute(vec![tool_call("3", "geocode", "Oslo")])
+            .await;
+
+        assert_eq!(results[1].tool_message.content, "Rome");
+        assert_eq!(
+            *starts.lock().unwrap(),
+            vec![
+                Duration::ZERO,
+                Duration::from_millis(500),
+                Duration::from_millis(1000)
+            ]
+        );
+    }
+}
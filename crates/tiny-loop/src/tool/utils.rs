@@ -0,0 +1,265 @@
+use serde_json::Value;
+use std::{
+    io::Write,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Write `content` to a new file in the system temp directory and return a preview pointing to it.
+///
+/// The returned text contains the file path and the first `preview_len` bytes of `content`
+/// (rounded down to a char boundary), so the model can read the rest in ranges with a file tool.
+/// This crate doesn't provide such a tool; register your own.
+///
+/// The file is readable by the current user only (on Unix) and never overwrites an existing file.
+/// It is not deleted automatically; remove it with [`std::fs::remove_file`] when no longer needed.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be created or written.
+pub fn spill_to_file(
+    content: &str,
+    name_hint: &str,
+    preview_len: usize,
+) -> std::io::Result<(PathBuf, String)> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let file_name: String = format!(
+        "tiny-loop-{}-{}-{}-{}.txt",
+        name_hint,
+        std::process::id(),
+        nanos,
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+    .chars()
+    .map(|c| {
+        if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+            c
+        } else {
+            '_'
+        }
+    })
+    .collect();
+    let path = std::env::temp_dir().join(file_name);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(&path)?.write_all(content.as_bytes())?;
+
+    let mut end = preview_len.min(content.len());
+    while !content.is_char_boundary(end) {
+        end -= 1;
+    }
+    let preview = format!(
+        "[Output too large ({} bytes). Full output saved to: {}. Use a file reading tool to read it in ranges.]\n\nPreview:\n{}",
+        content.len(),
+        path.display(),
+        &content[..end]
+    );
+    Ok((path, preview))
+}
+
+/// Shrink a JSON value so it serializes to at most `max_len` bytes, keeping it valid JSON.
+///
+/// Arrays and objects keep their first items and end with a marker like `"...3 more items"`
+/// (objects use a `"..."` key). Collections nested too deep are replaced by such a marker.
+/// The largest result within the budget is returned. Strings are kept whole,
+/// so the result may still exceed `max_len` if a single string does.
+///
+/// # Example
+/// ```
+/// use tiny_loop::tool::truncate_json;
+///
+/// let value = serde_json::json!({ "items": (0..1000).collect::<Vec<_>>() });
+/// let truncated = truncate_json(&value, 100);
+/// assert!(truncated.to_string().len() <= 100);
+/// ```
+pub fn truncate_json(value: &Value, max_len: usize) -> Value {
+    if value.to_string().len() <= max_len {
+        return value.clone();
+    }
+    // Find the largest number of items and nesting levels to keep
+    let (mut low, mut high) = (0, max_collection_size(value));
+    while low < high {
+        let mid = (low + high).div_ceil(2);
+        if shrink_json(value, mid, mid).to_string().len() <= max_len {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    shrink_json(value, low, low)
+}
+
+/// Largest length or depth of the collections in `value`
+fn max_collection_size(value: &Value) -> usize {
+    match value {
+        Value::Array(items) => items
+            .iter()
+            .map(|v| max_collection_size(v) + 1)
+            .fold(items.len(), usize::max),
+        Value::Object(map) => map
+            .values()
+            .map(|v| max_collection_size(v) + 1)
+            .fold(map.len(), usize::max),
+        _ => 0,
+    }
+}
+
+/// Keep the first `items` items of every collection and `depth` levels of nesting
+fn shrink_json(value: &Value, items: usize, depth: usize) -> Value {
+    let more = |n: usize| format!("...{} more items", n);
+    match value {
+        Value::Array(values) if depth == 0 && !values.is_empty() => more(values.len()).into(),
+        Value::Object(map) if depth == 0 && !map.is_empty() => more(map.len()).into(),
+        Value::Array(values) => {
+            let mut kept: Vec<_> = values
+                .iter()
+                .take(items)
+                .map(|v| shrink_json(v, items, depth - 1))
+                .collect();
+            if values.len() > items {
+                kept.push(more(values.len() - items).into());
+            }
+            kept.into()
+        }
+        Value::Object(map) => {
+            let mut kept: serde_json::Map<_, _> = map
+                .iter()
+                .take(items)
+                .map(|(k, v)| (k.clone(), shrink_json(v, items, depth - 1)))
+                .collect();
+            if map.len() > items {
+                kept.insert(
+                    "...".into(),
+                    format!("{} more items", map.len() - items).into(),
+                );
+            }
+            kept.into()
+        }
+        _ => value.clone(),
+    }
+}
+
+/// Parse tool calls written as text, for models that don't use the tool calls field.
+/// See [`Agent::text_tool_calls`](crate::Agent::text_tool_calls)
+///
+/// Every fenced code block containing a JSON object like
+/// `{"tool": "search", "args": {"query": "rust"}}` is one call.
+/// Without fenced blocks, the whole content is parsed as such an object.
+///
+/// # Example
+/// ```
+/// use tiny_loop::tool::parse_fenced_tool_calls;
+///
+/// let content = "I'll search.\n```json\n{\"tool\": \"search\", \"args\": {\"query\": \"rust\"}}\n```";
+/// let calls = parse_fenced_tool_calls(content);
+/// assert_eq!(calls[0].name, "search");
+/// assert_eq!(calls[0].arguments, r#"{"query":"rust"}"#);
+/// ```
+pub fn parse_fenced_tool_calls(content: &str) -> Vec<crate::types::FunctionCall> {
+    let parse = |text: &str| {
+        let value: Value = serde_json::from_str(text.trim()).ok()?;
+        Some(crate::types::FunctionCall {
+            name: value.get("tool")?.as_str()?.to_string(),
+            arguments: match value.get("args") {
+                Some(args @ Value::Object(_)) => args.to_string(),
+                Some(_) => return None,
+                None => "{}".into(),
+            },
+        })
+    };
+    let blocks: Vec<_> = content.split("```").skip(1).step_by(2).collect();
+    if blocks.is_empty() {
+        return parse(content).into_iter().collect();
+    }
+    blocks
+        .into_iter()
+        .filter_map(|block| {
+            // Skip the language tag, e.g. `json`
+            let body = match block.split_once('\n') {
+                Some((tag, body)) if !tag.trim_start().starts_with('{') => body,
+                _ => block,
+            };
+            parse(body)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spill_to_file() {
+        let content = "é".repeat(100);
+        let (path, preview) = spill_to_file(&content, "call/1", 5).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), content);
+        assert!(preview.contains(&path.display().to_string()));
+        assert!(preview.ends_with("Preview:\néé"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let (other, _) = spill_to_file(&content, "call/1", 5).unwrap();
+        assert_ne!(path, other);
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(other).unwrap();
+    }
+
+    #[test]
+    fn test_parse_fenced_tool_calls() {
+        let content = "Let me check.\n```json\n{\"tool\": \"weather\", \"args\": {\"city\": \"Paris\"}}\n```\n\
+                       ```\n{\"tool\": \"time\"}\n```\n```rust\nfn main() {}\n```";
+        let calls = parse_fenced_tool_calls(content);
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].name, "weather");
+        assert_eq!(calls[0].arguments, r#"{"city":"Paris"}"#);
+        assert_eq!(calls[1].name, "time");
+        assert_eq!(calls[1].arguments, "{}");
+
+        assert_eq!(parse_fenced_tool_calls(r#" {"tool": "time"} "#).len(), 1);
+        assert!(parse_fenced_tool_calls("The answer is 42.").is_empty());
+        assert!(parse_fenced_tool_calls(r#"{"tool": "time", "args": 1}"#).is_empty());
+    }
+
+    #[test]
+    fn test_truncate_json_array() {
+        let value = serde_json::json!((0..1000).collect::<Vec<_>>());
+        let truncated = truncate_json(&value, 50);
+        let text = truncated.to_string();
+        assert!(text.len() <= 50);
+        assert!(serde_json::from_str::<Value>(&text).is_ok());
+        let items = truncated.as_array().unwrap();
+        assert_eq!(items[0], 0);
+        let kept = items.len() - 1;
+        assert_eq!(
+            items[kept],
+            format!("...{} more items", 1000 - kept).as_str()
+        );
+
+        let small = serde_json::json!([1, 2, 3]);
+        assert_eq!(truncate_json(&small, 50), small);
+    }
+
+    #[test]
+    fn test_truncate_json_nested() {
+        let mut value = serde_json::json!("leaf");
+        for i in 0..50 {
+            value = serde_json::json!({ "level": i, "child": value, "tags": ["a", "b", "c"] });
+        }
+        let truncated = truncate_json(&value, 200);
+        let text = truncated.to_string();
+        assert!(text.len() <= 200);
+        assert!(serde_json::from_str::<Value>(&text).is_ok());
+        assert!(text.contains("more items"));
+        assert_eq!(truncated["level"], 49);
+    }
+}
@@ -2,7 +2,7 @@ use super::message::AssistantMessage;
 use serde::{Deserialize, Serialize};
 
 /// Finish reason for LLM completion
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 #[serde(rename_all = "snake_case")]
 pub enum FinishReason {
     Stop,
@@ -13,9 +13,122 @@ pub enum FinishReason {
     Custom(String),
 }
 
+/// Controls which tool, if any, the model must call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolChoice {
+    /// The model decides whether to call tools
+    Auto,
+    /// The model must not call tools
+    None,
+    /// The model must call at least one tool
+    Required,
+    /// The model must call the named tool
+    Function(String),
+    /// The model must call at least one of the named tools.
+    ///
+    /// There is no native wire format for this, so providers emulate it by
+    /// sending `required` and only sending the named tools in the request.
+    RequiredFrom(Vec<String>),
+}
+
+/// How much the model should reason before answering.
+///
+/// Providers taking a level (e.g. OpenAI's `reasoning_effort`) send it as is;
+/// providers taking a thinking token budget use [`token_budget`](Self::token_budget).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    Low,
+    Medium,
+    High,
+}
+
+impl ReasoningEffort {
+    /// Thinking token budget for providers configured by budget
+    pub fn token_budget(self) -> u32 {
+        match self {
+            Self::Low => 1024,
+            Self::Medium => 4096,
+            Self::High => 16384,
+        }
+    }
+}
+
+/// Log probability of one alternative token
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TopLogprob {
+    pub token: String,
+    pub logprob: f64,
+    /// UTF-8 bytes of the token, if it is not valid UTF-8 on its own
+    #[serde(default)]
+    pub bytes: Option<Vec<u8>>,
+}
+
+/// Log probability of one output token, with the most likely alternatives
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f64,
+    /// UTF-8 bytes of the token, if it is not valid UTF-8 on its own
+    #[serde(default)]
+    pub bytes: Option<Vec<u8>>,
+    /// Most likely tokens at this position
+    #[serde(default)]
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+/// Log probabilities of the output tokens
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Logprobs {
+    /// Tokens of the message content
+    #[serde(default)]
+    pub content: Option<Vec<TokenLogprob>>,
+    /// Tokens of the refusal message
+    #[serde(default)]
+    pub refusal: Option<Vec<TokenLogprob>>,
+}
+
+/// Token usage of one or more LLM calls
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Usage {
+    /// Tokens in the request
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    /// Tokens generated by the model
+    #[serde(default)]
+    pub completion_tokens: u64,
+    /// Prompt and completion tokens
+    #[serde(default)]
+    pub total_tokens: u64,
+}
+
+impl std::ops::Add for Usage {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            prompt_tokens: self.prompt_tokens + other.prompt_tokens,
+            completion_tokens: self.completion_tokens + other.completion_tokens,
+            total_tokens: self.total_tokens + other.total_tokens,
+        }
+    }
+}
+
+impl std::ops::AddAssign for Usage {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
 /// LLM response containing message and finish reason
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct LLMResponse {
     pub message: AssistantMessage,
     pub finish_reason: FinishReason,
+    /// Log probabilities of the output tokens, if requested and supported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<Logprobs>,
+    /// Token usage of the call, if reported by the provider
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
 }
@@ -19,11 +19,53 @@ pub struct UserMessage {
 /// Assistant message body
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct AssistantMessage {
-    /// Message content
+    /// Message content (empty if the API sent `null`, e.g. alongside tool calls or a refusal)
+    #[serde(default, deserialize_with = "null_as_default")]
     pub content: String,
     /// Tool calls requested by the assistant
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_calls: Option<Vec<ToolCall>>,
+    /// Refusal explanation returned instead of content (e.g. by structured outputs)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refusal: Option<String>,
+    /// Citations in the content, e.g. from web search models.
+    /// Not sent back to the API
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<Vec<Annotation>>,
+}
+
+/// Annotation of the assistant content, e.g. a citation
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Annotation {
+    /// Annotation type, e.g. `url_citation`
+    #[serde(rename = "type")]
+    pub annotation_type: String,
+    /// Cited URL, present for `url_citation` annotations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url_citation: Option<UrlCitation>,
+}
+
+/// URL cited in the assistant content
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct UrlCitation {
+    /// Byte index of the first character of the citation in the content
+    pub start_index: usize,
+    /// Byte index after the last character of the citation in the content
+    pub end_index: usize,
+    /// URL of the source
+    pub url: String,
+    /// Title of the source
+    #[serde(default)]
+    pub title: String,
+}
+
+/// Deserialize `null` as the default value
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
 }
 
 /// Tool message body
@@ -109,10 +151,27 @@ pub struct ToolCall {
 pub struct FunctionCall {
     /// Function name to call
     pub name: String,
-    /// JSON-encoded function arguments
+    /// JSON-encoded function arguments.
+    /// Deserializes `null` or a missing field as `{}`, as sent by some models for no-arg tools
+    #[serde(
+        default = "empty_arguments",
+        deserialize_with = "null_as_empty_arguments"
+    )]
     pub arguments: String,
 }
 
+/// Arguments of a call without arguments
+fn empty_arguments() -> String {
+    "{}".into()
+}
+
+fn null_as_empty_arguments<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(Option::<String>::deserialize(deserializer)?.unwrap_or_else(empty_arguments))
+}
+
 /// Message with timing metadata
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct TimedMessage {
@@ -121,6 +180,10 @@ pub struct TimedMessage {
     pub timestamp: SystemTime,
     /// Time taken to generate this message
     pub elapsed: Duration,
+    /// Application data attached to the message, e.g. which user sent it.
+    /// Never sent to the LLM
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Value>,
 }
 
 /// Tool execution result with timing metadata
@@ -162,12 +225,14 @@ mod tests {
         let msg = Message::Assistant(AssistantMessage {
             content: "test".into(),
             tool_calls: None,
+            refusal: None,
+            annotations: None,
         });
         let json = serde_json::to_string(&msg).unwrap();
         assert!(!json.contains("tool_calls"));
         let parsed: Message = serde_json::from_str(&json).unwrap();
         assert!(
-            matches!(parsed, Message::Assistant(AssistantMessage { content, tool_calls: None }) if content == "test")
+            matches!(parsed, Message::Assistant(AssistantMessage { content, tool_calls: None, refusal: None, .. }) if content == "test")
         );
     }
 
@@ -183,6 +248,8 @@ mod tests {
                     arguments: "{}".into(),
                 },
             }]),
+            refusal: None,
+            annotations: None,
         });
         let json = serde_json::to_string(&msg).unwrap();
         let parsed: Message = serde_json::from_str(&json).unwrap();
@@ -216,6 +283,39 @@ mod tests {
         assert!(matches!(parsed, Message::Custom(CustomMessage { role, .. }) if role == "custom"));
     }
 
+    #[test]
+    fn test_timed_message_metadata_roundtrip() {
+        let mut timed = TimedMessage {
+            message: Message::User(UserMessage {
+                content: "hi".into(),
+            }),
+            timestamp: SystemTime::UNIX_EPOCH,
+            elapsed: Duration::ZERO,
+            metadata: Some(serde_json::json!({"user_id": "u_1"})),
+        };
+        let json = serde_json::to_string(&timed).unwrap();
+        let parsed: TimedMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.metadata, timed.metadata);
+
+        timed.metadata = None;
+        let json = serde_json::to_string(&timed).unwrap();
+        assert!(!json.contains("metadata"));
+        let parsed: TimedMessage = serde_json::from_str(&json).unwrap();
+        assert!(parsed.metadata.is_none());
+    }
+
+    #[test]
+    fn test_tool_call_without_arguments() {
+        for json in [
+            r#"{"id":"call_1","type":"function","function":{"name":"now"}}"#,
+            r#"{"id":"call_1","type":"function","function":{"name":"now","arguments":null}}"#,
+        ] {
+            let parsed: ToolCall = serde_json::from_str(json).unwrap();
+            assert_eq!(parsed.function.name, "now");
+            assert_eq!(parsed.function.arguments, "{}");
+        }
+    }
+
     #[test]
     fn test_tool_call_roundtrip() {
         let tc = ToolCall {
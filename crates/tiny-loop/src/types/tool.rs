@@ -1,6 +1,7 @@
 use schemars::{JsonSchema, generate::SchemaSettings};
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
+use std::collections::HashMap;
 
 /// Tool definition for LLM
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -12,6 +13,78 @@ pub struct ToolDefinition {
     pub function: ToolFunction,
 }
 
+impl ToolDefinition {
+    /// Check that the parameters are a well-formed object schema
+    ///
+    /// Verifies that `type` is `"object"`, `properties` (if present) is an object,
+    /// and every `required` entry names an existing property.
+    ///
+    /// # Errors
+    ///
+    /// Returns a human-readable reason if the schema is malformed.
+    pub fn validate(&self) -> Result<(), String> {
+        let schema = &self.function.parameters.0;
+
+        if schema.get("type").and_then(Value::as_str) != Some("object") {
+            return Err("parameters `type` must be \"object\"".into());
+        }
+
+        let properties = match schema.get("properties") {
+            None => None,
+            Some(Value::Object(props)) => Some(props),
+            Some(_) => return Err("parameters `properties` must be an object".into()),
+        };
+
+        if let Some(required) = schema.get("required") {
+            let Some(required) = required.as_array() else {
+                return Err("parameters `required` must be an array".into());
+            };
+            for name in required {
+                let Some(name) = name.as_str() else {
+                    return Err("parameters `required` entries must be strings".into());
+                };
+                if !properties.is_some_and(|p| p.contains_key(name)) {
+                    return Err(format!(
+                        "required parameter `{}` is not defined in `properties`",
+                        name
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replace the descriptions of the tool and its top-level parameters with those set in `meta`.
+    /// Parameters not defined in the schema are ignored
+    pub fn apply_meta(&mut self, meta: &ToolMeta) {
+        if let Some(description) = &meta.description {
+            self.function.description = description.clone();
+        }
+        let Some(Value::Object(properties)) = self.function.parameters.0.get_mut("properties")
+        else {
+            return;
+        };
+        for (name, description) in &meta.parameters {
+            if let Some(Value::Object(property)) = properties.get_mut(name) {
+                property.insert("description".into(), description.clone().into());
+            }
+        }
+    }
+}
+
+/// Overrides of the prompt text of a tool, e.g. loaded from a localized JSON catalog.
+/// See [`Agent::apply_tool_catalog`](crate::Agent::apply_tool_catalog)
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ToolMeta {
+    /// Replacement tool description
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Replacement parameter descriptions by parameter name
+    #[serde(default)]
+    pub parameters: HashMap<String, String>,
+}
+
 /// Tool function definition
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ToolFunction {
@@ -29,15 +102,31 @@ pub struct Parameters(Map<String, Value>);
 
 impl Parameters {
     /// Create Parameters from a Json object (map)
-    pub fn from_object(mut obj: Map<String, Value>) -> Self {
+    pub fn from_object(obj: Map<String, Value>) -> Self {
+        Self::from_object_keep(obj, false)
+    }
+
+    /// Create Parameters from a Json object (map), optionally keeping the top-level `description`
+    pub fn from_object_keep(mut obj: Map<String, Value>, keep_description: bool) -> Self {
         // Remove `$schema`, `title`, and `description` fields from JSON schema
         obj.remove("$schema");
         obj.remove("title");
-        obj.remove("description");
+        if !keep_description {
+            obj.remove("description");
+        }
 
         Self(obj)
     }
 
+    /// Whether the schema defines no parameters, i.e. `properties` is missing or empty
+    pub fn is_empty(&self) -> bool {
+        match self.0.get("properties") {
+            None => true,
+            Some(Value::Object(properties)) => properties.is_empty(),
+            Some(_) => false,
+        }
+    }
+
     /// Create Parameters from a JsonSchema
     pub fn from_schema(schema: schemars::Schema) -> Self {
         let obj = schema.to_value().as_object().unwrap().clone();
@@ -46,12 +135,18 @@ impl Parameters {
 
     /// Create Parameters from a type implementing JsonSchema
     pub fn from_type<T: JsonSchema>() -> Self {
+        Self::from_type_with::<T>(false)
+    }
+
+    /// Create Parameters from a type implementing JsonSchema, optionally keeping the top-level `description`
+    pub fn from_type_with<T: JsonSchema>(keep_description: bool) -> Self {
         let settings = SchemaSettings::default().with(|s| {
             s.inline_subschemas = true;
         });
         let generator = settings.into_generator();
         let schema = generator.into_root_schema_for::<T>();
-        Self::from_schema(schema)
+        let obj = schema.to_value().as_object().unwrap().clone();
+        Self::from_object_keep(obj, keep_description)
     }
 }
 
@@ -73,4 +168,101 @@ mod tests {
         assert!(json.contains(r#""type":"function"#));
         assert!(json.contains(r#""name":"test"#));
     }
+
+    #[test]
+    fn test_from_object_strips_metadata() {
+        let obj = serde_json::json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": "Args",
+            "description": "Search arguments",
+            "type": "object"
+        });
+        let obj = obj.as_object().unwrap().clone();
+
+        let stripped = Parameters::from_object(obj.clone()).0;
+        assert_eq!(stripped.keys().collect::<Vec<_>>(), vec!["type"]);
+
+        let kept = Parameters::from_object_keep(obj, true).0;
+        assert!(kept.get("$schema").is_none());
+        assert!(kept.get("title").is_none());
+        assert_eq!(kept["description"], "Search arguments");
+    }
+
+    /// Search arguments
+    #[derive(JsonSchema)]
+    #[allow(dead_code)]
+    struct SearchArgs {
+        query: String,
+    }
+
+    #[test]
+    fn test_from_type_with() {
+        let stripped = Parameters::from_type::<SearchArgs>().0;
+        assert!(stripped.get("description").is_none());
+        assert!(stripped.get("title").is_none());
+
+        let kept = Parameters::from_type_with::<SearchArgs>(true).0;
+        assert_eq!(kept["description"], "Search arguments");
+        assert!(kept.get("title").is_none());
+        assert_eq!(kept["properties"], stripped["properties"]);
+    }
+
+    fn definition(schema: Value) -> ToolDefinition {
+        ToolDefinition {
+            tool_type: "function".into(),
+            function: ToolFunction {
+                name: "test".into(),
+                description: "desc".into(),
+                parameters: Parameters::from_object(schema.as_object().unwrap().clone()),
+            },
+        }
+    }
+
+    #[test]
+    fn test_parameters_is_empty() {
+        let params = |schema: Value| Parameters::from_object(schema.as_object().unwrap().clone());
+        assert!(params(serde_json::json!({"type": "object"})).is_empty());
+        assert!(params(serde_json::json!({"type": "object", "properties": {}})).is_empty());
+        assert!(!Parameters::from_type::<SearchArgs>().is_empty());
+    }
+
+    #[test]
+    fn test_validate_valid_schema() {
+        #[derive(JsonSchema)]
+        #[allow(dead_code)]
+        struct Args {
+            city: String,
+        }
+        let td = ToolDefinition {
+            tool_type: "function".into(),
+            function: ToolFunction {
+                name: "test".into(),
+                description: "desc".into(),
+                parameters: Parameters::from_type::<Args>(),
+            },
+        };
+        assert_eq!(td.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_missing_type() {
+        let td = definition(serde_json::json!({ "properties": {} }));
+        assert!(td.validate().unwrap_err().contains("type"));
+    }
+
+    #[test]
+    fn test_validate_properties_not_object() {
+        let td = definition(serde_json::json!({ "type": "object", "properties": [] }));
+        assert!(td.validate().unwrap_err().contains("properties"));
+    }
+
+    #[test]
+    fn test_validate_required_missing_property() {
+        let td = definition(serde_json::json!({
+            "type": "object",
+            "properties": { "city": { "type": "string" } },
+            "required": ["city", "country"]
+        }));
+        assert!(td.validate().unwrap_err().contains("country"));
+    }
 }
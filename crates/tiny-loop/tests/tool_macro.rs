@@ -5,6 +5,17 @@ async fn my_function(#[serde(rename = "customParam")] param: String) -> String {
     format!("Got: {}", param)
 }
 
+#[tool]
+async fn get_events(
+    /// Start date
+    #[example = "2024-01-01"]
+    #[example = "2024-12-31"]
+    since: String,
+    #[example = 10] limit: u32,
+) -> String {
+    format!("{} {}", since, limit)
+}
+
 #[derive(Clone)]
 #[allow(dead_code)]
 struct MyService;
@@ -46,3 +57,15 @@ fn test_serde_rename_method() {
     let args: CustomMethodOneArgs = serde_json::from_str(json).unwrap();
     assert_eq!(args.param, "test");
 }
+
+#[test]
+fn test_param_examples() {
+    let def = serde_json::to_value(GetEventsArgs::definition()).unwrap();
+    let properties = &def["function"]["parameters"]["properties"];
+    assert_eq!(
+        properties["since"]["examples"],
+        serde_json::json!(["2024-01-01", "2024-12-31"])
+    );
+    assert_eq!(properties["since"]["description"], "Start date");
+    assert_eq!(properties["limit"]["examples"], serde_json::json!([10]));
+}